@@ -0,0 +1,25 @@
+-- Append-only history of everything that happens to keys, rounds and the config
+CREATE TABLE IF NOT EXISTS events (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  kind VARCHAR(255) NOT NULL, -- e.g., 'claim', 'delivery', 'failure'
+  actor VARCHAR(255), -- user that caused the event, NULL for the bot itself
+  target VARCHAR(255), -- user the event is about
+  key_id INTEGER,
+  round_id INTEGER,
+  details TEXT,
+  created_at DATE DEFAULT (datetime('now', 'localtime')) NOT NULL,
+  FOREIGN KEY (key_id) REFERENCES keys (id),
+  FOREIGN KEY (round_id) REFERENCES giveaway_rounds (round_id)
+);
+
+CREATE INDEX IF NOT EXISTS events_target ON events (target);
+
+CREATE TRIGGER IF NOT EXISTS events_no_update BEFORE UPDATE ON events
+BEGIN
+  SELECT RAISE(ABORT, 'events are append-only');
+END;
+
+CREATE TRIGGER IF NOT EXISTS events_no_delete BEFORE DELETE ON events
+BEGIN
+  SELECT RAISE(ABORT, 'events are append-only');
+END;
@@ -0,0 +1,2 @@
+-- Revoked keys are never handed out again and don't count towards a user's claims
+ALTER TABLE keys ADD COLUMN revoked BOOLEAN DEFAULT FALSE NOT NULL;
@@ -0,0 +1,173 @@
+-- Timestamps used to be written by `datetime('now', 'localtime')`, in the host's time zone and to the
+-- second. They are now UTC with milliseconds, `YYYY-MM-DD HH:MM:SS.SSS`, so they sort and compare as
+-- text. Existing rows are converted with the offset of the host running this migration, which is
+-- the one that wrote them unless the database was moved.
+--
+-- SQLite can't change a column's type or default in place, so every table with a timestamp is
+-- rebuilt. This runs with foreign keys off, see `migrate`.
+
+CREATE TABLE keys_new (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  key_val VARCHAR(255) NOT NULL,
+  claimed BOOLEAN DEFAULT FALSE NOT NULL,
+  user_claim VARCHAR(255),
+  claimed_at DATETIME,
+  added_at DATETIME DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+  claim_round INTEGER,
+  revoked BOOLEAN DEFAULT FALSE NOT NULL,
+  pool VARCHAR(255) DEFAULT 'default' NOT NULL,
+  UNIQUE (key_val),
+  FOREIGN KEY (user_claim) references users (id),
+  FOREIGN KEY (claim_round) REFERENCES giveaway_rounds (round_id)
+);
+
+INSERT INTO keys_new
+SELECT
+  id,
+  key_val,
+  claimed,
+  user_claim,
+  strftime('%Y-%m-%d %H:%M:%f', claimed_at, 'utc'),
+  strftime('%Y-%m-%d %H:%M:%f', added_at, 'utc'),
+  claim_round,
+  revoked,
+  pool
+FROM keys;
+
+CREATE TABLE giveaway_rounds_new (
+  round_id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  status VARCHAR(255) NOT NULL, -- 'planned', 'active' or 'closed'
+  name VARCHAR(255),
+  starts_at DATETIME,
+  ends_at DATETIME,
+  notes TEXT
+);
+
+INSERT INTO giveaway_rounds_new
+SELECT
+  round_id,
+  status,
+  name,
+  strftime('%Y-%m-%d %H:%M:%f', starts_at, 'utc'),
+  strftime('%Y-%m-%d %H:%M:%f', ends_at, 'utc'),
+  notes
+FROM giveaway_rounds;
+
+CREATE TABLE events_new (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  kind VARCHAR(255) NOT NULL, -- e.g., 'claim', 'delivery', 'failure'
+  actor VARCHAR(255), -- user that caused the event, NULL for the bot itself
+  target VARCHAR(255), -- user the event is about
+  key_id INTEGER,
+  round_id INTEGER,
+  details TEXT,
+  created_at DATETIME DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')) NOT NULL,
+  FOREIGN KEY (key_id) REFERENCES keys (id),
+  FOREIGN KEY (round_id) REFERENCES giveaway_rounds (round_id)
+);
+
+INSERT INTO events_new
+SELECT
+  id,
+  kind,
+  actor,
+  target,
+  key_id,
+  round_id,
+  details,
+  strftime('%Y-%m-%d %H:%M:%f', created_at, 'utc')
+FROM events;
+
+CREATE TABLE claims_new (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  key_id INTEGER NOT NULL,
+  user_id INTEGER NOT NULL,
+  round_id INTEGER NOT NULL,
+  pool VARCHAR(255) NOT NULL,
+  slot INTEGER, -- 1 up to the per round limit, NULL for waived claims
+  waiver_reason TEXT,
+  claimed_at DATETIME DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')) NOT NULL,
+  UNIQUE (key_id),
+  UNIQUE (user_id, round_id, pool, slot),
+  CHECK (slot IS NULL OR slot >= 1),
+  CHECK ((slot IS NULL) = (waiver_reason IS NOT NULL)),
+  FOREIGN KEY (key_id) REFERENCES keys (id),
+  FOREIGN KEY (user_id) REFERENCES users (id),
+  FOREIGN KEY (round_id) REFERENCES giveaway_rounds (round_id)
+);
+
+INSERT INTO claims_new
+SELECT
+  id,
+  key_id,
+  user_id,
+  round_id,
+  pool,
+  slot,
+  waiver_reason,
+  strftime('%Y-%m-%d %H:%M:%f', claimed_at, 'utc')
+FROM claims;
+
+CREATE TABLE round_reports_new (
+  round_id INTEGER PRIMARY KEY NOT NULL,
+  keys_claimed INTEGER NOT NULL,
+  claimants INTEGER NOT NULL,
+  failed_deliveries INTEGER NOT NULL,
+  created_at DATETIME DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')) NOT NULL,
+  FOREIGN KEY (round_id) REFERENCES giveaway_rounds (round_id)
+);
+
+INSERT INTO round_reports_new
+SELECT
+  round_id,
+  keys_claimed,
+  claimants,
+  failed_deliveries,
+  strftime('%Y-%m-%d %H:%M:%f', created_at, 'utc')
+FROM round_reports;
+
+CREATE TABLE low_stock_alerts_new (
+  pool VARCHAR(255) PRIMARY KEY NOT NULL,
+  remaining INTEGER NOT NULL,
+  alerted_at DATETIME DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')) NOT NULL
+);
+
+INSERT INTO low_stock_alerts_new
+SELECT pool, remaining, strftime('%Y-%m-%d %H:%M:%f', alerted_at, 'utc')
+FROM low_stock_alerts;
+
+-- ids of deleted rows are not handed out again
+UPDATE sqlite_sequence
+SET seq = MAX(seq, COALESCE((SELECT old.seq FROM sqlite_sequence old WHERE old.name || '_new' = sqlite_sequence.name), 0))
+WHERE name LIKE '%\_new' ESCAPE '\';
+
+DROP TABLE keys;
+DROP TABLE giveaway_rounds;
+DROP TABLE events;
+DROP TABLE claims;
+DROP TABLE round_reports;
+DROP TABLE low_stock_alerts;
+
+ALTER TABLE keys_new RENAME TO keys;
+ALTER TABLE giveaway_rounds_new RENAME TO giveaway_rounds;
+ALTER TABLE events_new RENAME TO events;
+ALTER TABLE claims_new RENAME TO claims;
+ALTER TABLE round_reports_new RENAME TO round_reports;
+ALTER TABLE low_stock_alerts_new RENAME TO low_stock_alerts;
+
+CREATE INDEX IF NOT EXISTS keys_pool ON keys (pool, claimed);
+
+CREATE UNIQUE INDEX IF NOT EXISTS giveaway_rounds_one_active ON giveaway_rounds (status)
+WHERE status = 'active';
+
+CREATE INDEX IF NOT EXISTS events_target ON events (target);
+
+CREATE TRIGGER IF NOT EXISTS events_no_update BEFORE UPDATE ON events
+BEGIN
+  SELECT RAISE(ABORT, 'events are append-only');
+END;
+
+CREATE TRIGGER IF NOT EXISTS events_no_delete BEFORE DELETE ON events
+BEGIN
+  SELECT RAISE(ABORT, 'events are append-only');
+END;
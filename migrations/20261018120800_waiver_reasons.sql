@@ -0,0 +1,44 @@
+-- A waived claim has to say why, a reason of only whitespace doesn't count. Blank reasons stored
+-- before this check existed are replaced, so they still show up as waivers.
+--
+-- SQLite can't add a CHECK to an existing table, so claims is rebuilt. This runs with foreign keys
+-- off, see `migrate`.
+
+CREATE TABLE claims_new (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  key_id INTEGER NOT NULL,
+  user_id INTEGER NOT NULL,
+  round_id INTEGER NOT NULL,
+  pool VARCHAR(255) NOT NULL,
+  slot INTEGER, -- 1 up to the per round limit, NULL for waived claims
+  waiver_reason TEXT,
+  claimed_at DATETIME DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')) NOT NULL,
+  UNIQUE (key_id),
+  UNIQUE (user_id, round_id, pool, slot),
+  CHECK (slot IS NULL OR slot >= 1),
+  CHECK ((slot IS NULL) = (waiver_reason IS NOT NULL)),
+  CHECK (length(trim(waiver_reason)) > 0),
+  FOREIGN KEY (key_id) REFERENCES keys (id),
+  FOREIGN KEY (user_id) REFERENCES users (id),
+  FOREIGN KEY (round_id) REFERENCES giveaway_rounds (round_id)
+);
+
+INSERT INTO claims_new
+SELECT
+  id,
+  key_id,
+  user_id,
+  round_id,
+  pool,
+  slot,
+  CASE WHEN length(trim(waiver_reason)) = 0 THEN 'no reason given' ELSE waiver_reason END,
+  claimed_at
+FROM claims;
+
+-- ids of deleted rows are not handed out again
+UPDATE sqlite_sequence
+SET seq = MAX(seq, COALESCE((SELECT old.seq FROM sqlite_sequence old WHERE old.name = 'claims'), 0))
+WHERE name = 'claims_new';
+
+DROP TABLE claims;
+ALTER TABLE claims_new RENAME TO claims;
@@ -0,0 +1,60 @@
+-- Revoking a key used to delete its claim, which took it out of the round reports as well. The
+-- claim is now flagged instead. Revoked claims don't count towards the limits, so the unique slot
+-- becomes a partial index over the claims that aren't revoked.
+--
+-- Keys revoked before this version have lost their claim, it is added back as revoked where the
+-- key still says who claimed it in which round.
+--
+-- SQLite can't drop a table constraint, so claims is rebuilt. This runs with foreign keys off, see
+-- `migrate`.
+
+CREATE TABLE claims_new (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  key_id INTEGER NOT NULL,
+  user_id INTEGER NOT NULL,
+  round_id INTEGER NOT NULL,
+  pool VARCHAR(255) NOT NULL,
+  slot INTEGER, -- 1 up to the per round limit, NULL for waived claims
+  waiver_reason TEXT,
+  claimed_at DATETIME DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')) NOT NULL,
+  revoked BOOLEAN DEFAULT FALSE NOT NULL,
+  UNIQUE (key_id),
+  CHECK (slot IS NULL OR slot >= 1),
+  CHECK ((slot IS NULL) = (waiver_reason IS NOT NULL)),
+  CHECK (length(trim(waiver_reason)) > 0),
+  FOREIGN KEY (key_id) REFERENCES keys (id),
+  FOREIGN KEY (user_id) REFERENCES users (id),
+  FOREIGN KEY (round_id) REFERENCES giveaway_rounds (round_id)
+);
+
+INSERT INTO claims_new (id, key_id, user_id, round_id, pool, slot, waiver_reason, claimed_at)
+SELECT id, key_id, user_id, round_id, pool, slot, waiver_reason, claimed_at
+FROM claims;
+
+-- ids of deleted rows are not handed out again
+UPDATE sqlite_sequence
+SET seq = MAX(seq, COALESCE((SELECT old.seq FROM sqlite_sequence old WHERE old.name = 'claims'), 0))
+WHERE name = 'claims_new';
+
+DROP TABLE claims;
+ALTER TABLE claims_new RENAME TO claims;
+
+-- the slot of a revoked claim doesn't matter, it is left out of the index below
+INSERT INTO claims (key_id, user_id, round_id, pool, slot, claimed_at, revoked)
+SELECT
+  k.id,
+  k.user_claim,
+  k.claim_round,
+  k.pool,
+  1,
+  COALESCE(k.claimed_at, strftime('%Y-%m-%d %H:%M:%f', 'now')),
+  TRUE
+FROM keys k
+WHERE k.revoked = TRUE
+AND k.claimed = TRUE
+AND k.user_claim IS NOT NULL
+AND k.claim_round IS NOT NULL
+AND NOT EXISTS (SELECT 1 FROM claims c WHERE c.key_id = k.id);
+
+CREATE UNIQUE INDEX IF NOT EXISTS claims_slot ON claims (user_id, round_id, pool, slot)
+WHERE revoked = FALSE;
@@ -0,0 +1,29 @@
+-- Postgres version of migrations/20240130214702_tables.sql
+CREATE TABLE IF NOT EXISTS users (
+  id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+  username VARCHAR(255) NOT NULL,
+  UNIQUE (username)
+);
+
+CREATE TABLE IF NOT EXISTS giveaway_rounds (
+  round_id BIGINT PRIMARY KEY NOT NULL, -- picked by the bot, see create_round
+  status VARCHAR(255) NOT NULL -- e.g., 'active', 'completed'
+);
+
+CREATE TABLE IF NOT EXISTS keys (
+  id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+  key_val VARCHAR(255) NOT NULL,
+  claimed BOOLEAN DEFAULT FALSE NOT NULL,
+  user_claim BIGINT,
+  claimed_at TIMESTAMPTZ,
+  added_at TIMESTAMPTZ DEFAULT now(),
+  claim_round BIGINT,
+  UNIQUE (key_val),
+  FOREIGN KEY (user_claim) REFERENCES users (id),
+  FOREIGN KEY (claim_round) REFERENCES giveaway_rounds (round_id)
+);
+
+CREATE TABLE IF NOT EXISTS config (
+  key VARCHAR(255) PRIMARY KEY NOT NULL,
+  value VARCHAR(255) NOT NULL
+);
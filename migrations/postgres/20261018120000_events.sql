@@ -0,0 +1,27 @@
+-- Append-only history of everything that happens to keys, rounds and the config
+CREATE TABLE IF NOT EXISTS events (
+  id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+  kind VARCHAR(255) NOT NULL, -- e.g., 'claim', 'delivery', 'failure'
+  actor VARCHAR(255), -- user that caused the event, NULL for the bot itself
+  target VARCHAR(255), -- user the event is about
+  key_id BIGINT,
+  round_id BIGINT,
+  details TEXT,
+  created_at TIMESTAMPTZ DEFAULT now() NOT NULL,
+  FOREIGN KEY (key_id) REFERENCES keys (id),
+  FOREIGN KEY (round_id) REFERENCES giveaway_rounds (round_id)
+);
+
+CREATE INDEX IF NOT EXISTS events_target ON events (target);
+
+CREATE OR REPLACE FUNCTION events_append_only() RETURNS trigger AS $$
+BEGIN
+  RAISE EXCEPTION 'events are append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER events_no_update BEFORE UPDATE ON events
+FOR EACH ROW EXECUTE FUNCTION events_append_only();
+
+CREATE TRIGGER events_no_delete BEFORE DELETE ON events
+FOR EACH ROW EXECUTE FUNCTION events_append_only();
@@ -0,0 +1,4 @@
+-- Keys can be split into pools, e.g. one per game or platform, claim limits are counted per pool
+ALTER TABLE keys ADD COLUMN pool VARCHAR(255) DEFAULT 'default' NOT NULL;
+
+CREATE INDEX IF NOT EXISTS keys_pool ON keys (pool, claimed);
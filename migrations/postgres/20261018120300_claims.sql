@@ -0,0 +1,35 @@
+-- One row per key a user holds. (user, round, pool, slot) is unique, so a user can never hold more
+-- keys in a round than there are slots, even when claims race each other. Admin grants that skip
+-- the check are waived: they have no slot and must record why.
+CREATE TABLE IF NOT EXISTS claims (
+  id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+  key_id BIGINT NOT NULL,
+  user_id BIGINT NOT NULL,
+  round_id BIGINT NOT NULL,
+  pool VARCHAR(255) NOT NULL,
+  slot INTEGER, -- 1 up to the per round limit, NULL for waived claims
+  waiver_reason TEXT,
+  claimed_at TIMESTAMPTZ DEFAULT now() NOT NULL,
+  UNIQUE (key_id),
+  UNIQUE (user_id, round_id, pool, slot),
+  CHECK (slot IS NULL OR slot >= 1),
+  CHECK ((slot IS NULL) = (waiver_reason IS NOT NULL)),
+  FOREIGN KEY (key_id) REFERENCES keys (id),
+  FOREIGN KEY (user_id) REFERENCES users (id),
+  FOREIGN KEY (round_id) REFERENCES giveaway_rounds (round_id)
+);
+
+-- keys claimed before this table existed each get their own slot
+INSERT INTO claims (key_id, user_id, round_id, pool, slot, claimed_at)
+SELECT
+  id,
+  user_claim,
+  claim_round,
+  pool,
+  ROW_NUMBER() OVER (PARTITION BY user_claim, claim_round, pool ORDER BY claimed_at, id),
+  COALESCE(claimed_at, now())
+FROM keys
+WHERE claimed = TRUE
+AND revoked = FALSE
+AND user_claim IS NOT NULL
+AND claim_round IS NOT NULL;
@@ -0,0 +1,18 @@
+-- Rounds are planned, then active, then closed. Only one round can be active at a time.
+ALTER TABLE giveaway_rounds ADD COLUMN name VARCHAR(255);
+ALTER TABLE giveaway_rounds ADD COLUMN starts_at TIMESTAMPTZ;
+ALTER TABLE giveaway_rounds ADD COLUMN ends_at TIMESTAMPTZ;
+ALTER TABLE giveaway_rounds ADD COLUMN notes TEXT;
+
+UPDATE giveaway_rounds SET status = 'closed' WHERE status = 'completed';
+
+-- older versions could leave several rounds active, keep the newest one
+UPDATE giveaway_rounds SET status = 'closed'
+WHERE status = 'active'
+AND round_id < (SELECT MAX(round_id) FROM giveaway_rounds WHERE status = 'active');
+
+UPDATE giveaway_rounds
+SET starts_at = (SELECT MIN(claimed_at) FROM claims WHERE claims.round_id = giveaway_rounds.round_id);
+
+CREATE UNIQUE INDEX IF NOT EXISTS giveaway_rounds_one_active ON giveaway_rounds (status)
+WHERE status = 'active';
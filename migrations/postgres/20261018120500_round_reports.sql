@@ -0,0 +1,19 @@
+-- Summary of a round, taken when it closes so later changes to the pools don't alter it
+CREATE TABLE IF NOT EXISTS round_reports (
+  round_id BIGINT PRIMARY KEY NOT NULL,
+  keys_claimed INTEGER NOT NULL,
+  claimants INTEGER NOT NULL,
+  failed_deliveries INTEGER NOT NULL,
+  created_at TIMESTAMPTZ DEFAULT now() NOT NULL,
+  FOREIGN KEY (round_id) REFERENCES giveaway_rounds (round_id)
+);
+
+CREATE TABLE IF NOT EXISTS round_report_pools (
+  round_id BIGINT NOT NULL,
+  pool VARCHAR(255) NOT NULL,
+  keys_claimed INTEGER NOT NULL,
+  remaining INTEGER NOT NULL,
+  exhausted_after BIGINT, -- seconds from the start of the round to the last key, NULL if keys were left
+  PRIMARY KEY (round_id, pool),
+  FOREIGN KEY (round_id) REFERENCES round_reports (round_id) ON DELETE CASCADE
+);
@@ -0,0 +1,7 @@
+-- Pools that are below their low-stock threshold and have been alerted about, a row is removed once
+-- the pool is refilled so the next crossing alerts again
+CREATE TABLE IF NOT EXISTS low_stock_alerts (
+  pool VARCHAR(255) PRIMARY KEY NOT NULL,
+  remaining INTEGER NOT NULL,
+  alerted_at TIMESTAMPTZ DEFAULT now() NOT NULL
+);
@@ -0,0 +1,3 @@
+-- SQLite stored timestamps in the host's time zone until this version, Postgres always used
+-- TIMESTAMPTZ, which is UTC already, so there is nothing to convert here
+SELECT 1;
@@ -0,0 +1,6 @@
+-- A waived claim has to say why, a reason of only whitespace doesn't count. Blank reasons stored
+-- before this check existed are replaced, so they still show up as waivers.
+UPDATE claims SET waiver_reason = 'no reason given' WHERE length(trim(waiver_reason)) = 0;
+
+ALTER TABLE claims ADD CONSTRAINT claims_waiver_reason_not_blank
+CHECK (length(trim(waiver_reason)) > 0);
@@ -0,0 +1,29 @@
+-- Revoking a key used to delete its claim, which took it out of the round reports as well. The
+-- claim is now flagged instead. Revoked claims don't count towards the limits, so the unique slot
+-- becomes a partial index over the claims that aren't revoked.
+--
+-- Keys revoked before this version have lost their claim, it is added back as revoked where the
+-- key still says who claimed it in which round.
+ALTER TABLE claims ADD COLUMN revoked BOOLEAN DEFAULT FALSE NOT NULL;
+
+ALTER TABLE claims DROP CONSTRAINT claims_user_id_round_id_pool_slot_key;
+
+-- the slot of a revoked claim doesn't matter, it is left out of the index below
+INSERT INTO claims (key_id, user_id, round_id, pool, slot, claimed_at, revoked)
+SELECT
+  k.id,
+  k.user_claim,
+  k.claim_round,
+  k.pool,
+  1,
+  COALESCE(k.claimed_at, now()),
+  TRUE
+FROM keys k
+WHERE k.revoked = TRUE
+AND k.claimed = TRUE
+AND k.user_claim IS NOT NULL
+AND k.claim_round IS NOT NULL
+AND NOT EXISTS (SELECT 1 FROM claims c WHERE c.key_id = k.id);
+
+CREATE UNIQUE INDEX IF NOT EXISTS claims_slot ON claims (user_id, round_id, pool, slot)
+WHERE revoked = FALSE;
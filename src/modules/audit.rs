@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+
+use poise::serenity_prelude as serenity;
+use tokio::sync::Mutex;
+use tracing::{error, info};
+
+use crate::db::Store;
+
+/// Kinds of events written to the audit log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    /// New keys were read from the keys file
+    Ingest,
+    /// A key was assigned to a user
+    Claim,
+    /// A key was sent to a user
+    Delivery,
+    /// Claiming or sending a key failed
+    Failure,
+    /// A user was not allowed to claim a key
+    Rejected,
+    /// A claimed key was taken back
+    Revoke,
+    /// A user asked for their key again
+    Resend,
+    ConfigChange,
+    RoundChange,
+}
+
+impl EventKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EventKind::Ingest => "ingest",
+            EventKind::Claim => "claim",
+            EventKind::Delivery => "delivery",
+            EventKind::Failure => "failure",
+            EventKind::Rejected => "rejected",
+            EventKind::Revoke => "revoke",
+            EventKind::Resend => "resend",
+            EventKind::ConfigChange => "config_change",
+            EventKind::RoundChange => "round_change",
+        }
+    }
+
+    fn color(self) -> u32 {
+        match self {
+            EventKind::Ingest | EventKind::Delivery | EventKind::Resend => 0x57f287,
+            EventKind::Claim => 0x5865f2,
+            EventKind::Failure | EventKind::Revoke => 0xed4245,
+            EventKind::Rejected => 0xfee75c,
+            EventKind::ConfigChange | EventKind::RoundChange => 0x99aab5,
+        }
+    }
+}
+
+/// Something that happened to a key, a round or the config
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub kind: EventKind,
+    pub actor: Option<String>,
+    pub target: Option<String>,
+    pub key: Option<String>,
+    pub round: Option<i64>,
+    pub details: Option<String>,
+}
+
+impl Event {
+    pub fn new(kind: EventKind) -> Self {
+        Self {
+            kind,
+            actor: None,
+            target: None,
+            key: None,
+            round: None,
+            details: None,
+        }
+    }
+
+    /// The user that caused the event, leave it out for things the bot does on its own
+    pub fn actor(mut self, actor: impl Into<String>) -> Self {
+        self.actor = Some(actor.into());
+        self
+    }
+
+    /// The user the event is about
+    pub fn target(mut self, target: impl Into<String>) -> Self {
+        self.target = Some(target.into());
+        self
+    }
+
+    pub fn key(mut self, key: impl Into<String>) -> Self {
+        self.key = Some(key.into());
+        self
+    }
+
+    /// The round the event belongs to, defaults to the active round
+    pub fn round(mut self, round: i64) -> Self {
+        self.round = Some(round);
+        self
+    }
+
+    pub fn details(mut self, details: impl Into<String>) -> Self {
+        self.details = Some(details.into());
+        self
+    }
+
+    fn embed(&self) -> serenity::CreateEmbed {
+        let mut embed = serenity::CreateEmbed::new()
+            .title(self.kind.as_str())
+            .color(self.kind.color())
+            .timestamp(serenity::Timestamp::now());
+
+        if let Some(actor) = &self.actor {
+            embed = embed.field("Actor", actor, true);
+        }
+        if let Some(target) = &self.target {
+            embed = embed.field("Target", target, true);
+        }
+        if let Some(round) = self.round {
+            embed = embed.field("Round", round.to_string(), true);
+        }
+        if let Some(key) = &self.key {
+            embed = embed.field("Key", mask_key(key), true);
+        }
+        if let Some(details) = &self.details {
+            embed = embed.description(details);
+        }
+
+        embed
+    }
+}
+
+// the log channel is readable by more people than the database, so only show the start of a key
+fn mask_key(key: &str) -> String {
+    key.chars()
+        .enumerate()
+        .map(|(i, c)| if i < 4 || c == '-' { c } else { '*' })
+        .collect()
+}
+
+/// Stores `event` in the events table without posting it, used when the bot isn't connected
+///
+/// Errors are logged and otherwise ignored, a broken audit log should never stop a key from being
+/// handed out.
+pub async fn store(db: &dyn Store, event: &Event) {
+    // the key is left out on purpose, logs are kept and shipped around far more than the database
+    info!(
+        event = event.kind.as_str(),
+        actor = event.actor.as_deref(),
+        target = event.target.as_deref(),
+        round = event.round,
+        details = event.details.as_deref(),
+        "Audit event"
+    );
+
+    if let Err(e) = db
+        .insert_event(
+            event.kind.as_str(),
+            event.actor.as_deref(),
+            event.target.as_deref(),
+            event.key.as_deref(),
+            event.round,
+            event.details.as_deref(),
+        )
+        .await
+    {
+        error!(
+            "Could not write {} event to the audit log: {e}",
+            event.kind.as_str()
+        );
+    }
+}
+
+/// Stores `event` in the events table and posts it to the log channel if `log_channel_id` is set
+pub async fn record(
+    http: &serenity::Http,
+    db: &dyn Store,
+    config: &Mutex<HashMap<String, String>>,
+    event: Event,
+) {
+    store(db, &event).await;
+
+    let channel = config
+        .lock()
+        .await
+        .get("log_channel_id")
+        .and_then(|c| c.parse::<u64>().ok());
+
+    if let Some(channel) = channel {
+        let msg = serenity::CreateMessage::new().embed(event.embed());
+
+        if let Err(e) = serenity::ChannelId::new(channel)
+            .send_message(http, msg)
+            .await
+        {
+            error!(
+                "Could not post {} event to the log channel: {e}",
+                event.kind.as_str()
+            );
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use std::{
+    collections::HashMap,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use color_eyre::eyre::{eyre, Result};
+use tokio::sync::Mutex;
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    db::{Db, SqliteStore, Store},
+    shutdown::Shutdown,
+};
+
+const DEFAULT_DIR: &str = "backups";
+const DEFAULT_INTERVAL_HOURS: u64 = 24;
+const DEFAULT_KEEP: usize = 7;
+
+/// Why a backup was taken, part of its file name
+///
+/// Old backups are removed separately for each reason, so many backups before revoking keys don't
+/// push out the scheduled ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    Scheduled,
+    Manual,
+    Migration,
+    Revoke,
+    Restore,
+}
+
+impl Reason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Reason::Scheduled => "scheduled",
+            Reason::Manual => "manual",
+            Reason::Migration => "pre-migration",
+            Reason::Revoke => "pre-revoke",
+            Reason::Restore => "pre-restore",
+        }
+    }
+}
+
+/// Where backups go, how often they are taken and how many are kept, from the `backup_*` settings
+#[derive(Debug, Clone)]
+pub struct Backups {
+    pub dir: PathBuf,
+    /// `None` if scheduled backups are turned off
+    pub interval: Option<Duration>,
+    /// Backups kept for each reason
+    pub keep: usize,
+}
+
+impl Backups {
+    pub fn from_config(config: &HashMap<String, String>) -> Self {
+        let dir = config
+            .get("backup_dir")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_DIR));
+        let hours = config
+            .get("backup_interval_hours")
+            .and_then(|h| h.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_INTERVAL_HOURS);
+        let keep = config
+            .get("backup_keep")
+            .and_then(|k| k.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_KEEP);
+
+        Self {
+            dir,
+            interval: (hours > 0).then(|| Duration::from_secs(hours * 60 * 60)),
+            keep: keep.max(1),
+        }
+    }
+}
+
+// backups of `beta_keys.db` are called `beta_keys-<time>-<reason>.db`
+fn stem(database_name: &str) -> String {
+    Path::new(database_name)
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "database".to_owned())
+}
+
+/// Copies the database into a new file in the backup directory while it stays in use, then removes
+/// the oldest backups taken for the same reason
+///
+/// Only SQLite databases are backed up, `None` is returned for Postgres, which has `pg_dump` for
+/// that.
+pub async fn create(db: &dyn Store, backups: &Backups, reason: Reason) -> Result<Option<PathBuf>> {
+    let Some(sqlite) = db.as_sqlite() else {
+        debug!("Not backing up the database, Postgres is backed up with pg_dump");
+        return Ok(None);
+    };
+
+    tokio::fs::create_dir_all(&backups.dir).await.map_err(|e| {
+        eyre!(
+            "Could not create the backup directory {}: {e}",
+            backups.dir.display()
+        )
+    })?;
+
+    let stem = stem(sqlite.path());
+    let path = backups.dir.join(format!(
+        "{stem}-{}-{}.db",
+        chrono::Utc::now().format("%Y%m%d-%H%M%S%.3f"),
+        reason.as_str()
+    ));
+    let target = path
+        .to_str()
+        .ok_or_else(|| eyre!("The backup path {} is not valid UTF-8", path.display()))?;
+    sqlite.vacuum_into(target).await?;
+    info!(path = %path.display(), reason = reason.as_str(), "Database backed up");
+
+    if let Err(e) = rotate(backups, &stem, reason).await {
+        warn!("Could not remove old backups: {e}");
+    }
+
+    Ok(Some(path))
+}
+
+/// Removes all but the newest `backups.keep` backups taken for `reason`
+async fn rotate(backups: &Backups, stem: &str, reason: Reason) -> Result<()> {
+    let prefix = format!("{stem}-");
+    let suffix = format!("-{}.db", reason.as_str());
+
+    let mut names = Vec::new();
+    let mut entries = tokio::fs::read_dir(&backups.dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with(&prefix) && name.ends_with(&suffix) {
+            names.push(name);
+        }
+    }
+    // the time in the name sorts the same way as the time itself
+    names.sort();
+
+    let old = names.len().saturating_sub(backups.keep);
+    for name in &names[..old] {
+        tokio::fs::remove_file(backups.dir.join(name)).await?;
+        info!("Removed old backup {name}");
+    }
+
+    Ok(())
+}
+
+async fn remove_if_exists(path: &str) -> Result<()> {
+    match tokio::fs::remove_file(path).await {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Replaces the database file with the backup `backup`, returns the backup of the database that was
+/// replaced
+///
+/// The backup is checked before anything is replaced. `db` is closed, the bot must not be running.
+/// Only SQLite databases can be restored.
+pub async fn restore(db: &dyn Store, backup: &Path, backups: &Backups) -> Result<PathBuf> {
+    let Some(sqlite) = db.as_sqlite() else {
+        return Err(eyre!(
+            "Only SQLite databases can be restored, use pg_restore for Postgres"
+        ));
+    };
+    let database_name = sqlite.path();
+
+    if !backup.is_file() {
+        return Err(eyre!("{} is not a file", backup.display()));
+    }
+
+    // checked as a copy next to the database, so the backup itself stays untouched and the final
+    // rename doesn't cross file systems
+    let temp = format!("{database_name}.restoring");
+    tokio::fs::copy(backup, &temp).await?;
+
+    let checked = async {
+        let copy = SqliteStore::connect_or_create(&temp).await?;
+        let result = match copy.is_empty().await {
+            Ok(true) => Err(eyre!("it has no tables")),
+            Ok(false) => copy.integrity_check().await,
+            Err(e) => Err(e),
+        };
+        copy.close().await?;
+
+        result
+    }
+    .await;
+    if let Err(e) = checked {
+        remove_if_exists(&temp).await?;
+        return Err(eyre!("{} is not a usable database: {e}", backup.display()));
+    }
+
+    let previous = create(db, backups, Reason::Restore)
+        .await?
+        .ok_or_else(|| eyre!("The database was not backed up"))?;
+    db.close().await?;
+
+    for suffix in ["-wal", "-shm"] {
+        remove_if_exists(&format!("{database_name}{suffix}")).await?;
+        remove_if_exists(&format!("{temp}{suffix}")).await?;
+    }
+    tokio::fs::rename(&temp, database_name).await?;
+
+    Ok(previous)
+}
+
+/// Backs up the database every `backup_interval_hours` until the bot stops
+///
+/// The settings are read again after every backup, a changed interval applies from the next one.
+/// Nothing is scheduled for Postgres.
+pub async fn schedule(
+    db: Db,
+    config: Arc<Mutex<HashMap<String, String>>>,
+    shutdown: Arc<Shutdown>,
+) {
+    if db.as_sqlite().is_none() {
+        info!("No scheduled backups, Postgres is backed up with pg_dump");
+        return;
+    }
+
+    loop {
+        let backups = Backups::from_config(&*config.lock().await);
+        // turned off for now, look again later in case it is turned on
+        let wait = backups.interval.unwrap_or(Duration::from_secs(60 * 60));
+
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            _ = shutdown.requested() => break,
+        }
+
+        let backups = Backups::from_config(&*config.lock().await);
+        if backups.interval.is_none() {
+            continue;
+        }
+        let Some(_running) = shutdown.start() else {
+            break;
+        };
+
+        if let Err(e) = create(&*db, &backups, Reason::Scheduled).await {
+            error!("Scheduled backup failed: {e}");
+        }
+    }
+}
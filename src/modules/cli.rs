@@ -0,0 +1,440 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use clap::Subcommand;
+use color_eyre::eyre::{eyre, Result};
+use tokio::sync::Mutex;
+
+use crate::{
+    audit::{self, Event, EventKind},
+    backup::{self, Backups, Reason},
+    db::{read_beta_keys_file, set_round_db, Store, DEFAULT_POOL},
+    giveaway::{Claimant, Giveaway, Outcome},
+    settings,
+    templates::Language,
+};
+
+/// Name events caused by the command line are logged under
+const CLI_ACTOR: &str = "cli";
+
+/// Administration tasks that work on the database without connecting to discord
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Add the keys in a file, one key or `pool:key` per line
+    Import {
+        file: String,
+        /// Pool for keys that don't name one
+        #[arg(short, long, default_value = DEFAULT_POOL)]
+        pool: String,
+    },
+    /// Print every key as CSV
+    Export {
+        /// Only print the keys of this pool
+        #[arg(short, long)]
+        pool: Option<String>,
+    },
+    /// Show the active round and how many keys each pool has left
+    Stats,
+    /// List or open giveaway rounds
+    #[command(subcommand)]
+    Rounds(RoundsCommand),
+    /// Manage single keys
+    #[command(subcommand)]
+    Keys(KeysCommand),
+    /// Read or change settings stored in the database
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Manage the database itself
+    #[command(subcommand)]
+    Db(DbCommand),
+    /// Rehearse a giveaway with synthetic users clicking the button of the post
+    ///
+    /// Runs on an in-memory copy of the database unless `--write` is given, so nothing is claimed.
+    Simulate {
+        /// Number of users that click the button
+        #[arg(short, long, default_value_t = 10)]
+        users: u32,
+        /// How often every user clicks the button
+        #[arg(long, default_value_t = 1)]
+        clicks: u32,
+        /// Pool to take the keys from
+        #[arg(short, long, default_value = DEFAULT_POOL)]
+        pool: String,
+        /// How many of the users have an account that was created today
+        #[arg(long, default_value_t = 0)]
+        new_accounts: u32,
+        /// How many of the users don't have the key role
+        #[arg(long, default_value_t = 0)]
+        without_role: u32,
+        /// Add the keys in this file before the giveaway starts
+        #[arg(long)]
+        keys: Option<String>,
+        /// Claim keys in the database itself instead of a copy
+        #[arg(long)]
+        write: bool,
+    },
+    /// Back up the database into the backup directory
+    Backup,
+    /// Replace the database with a backup, the current database is backed up first
+    ///
+    /// Stop the bot before restoring, it would keep using the old database otherwise.
+    Restore {
+        /// The backup to restore, e.g. `backups/beta_keys-20261018-120000.000-scheduled.db`
+        backup: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum RoundsCommand {
+    /// List every round
+    List,
+    /// Open a round, creating it if it doesn't exist, the active round is closed
+    Set {
+        round: i64,
+        /// Open the round even if it was closed before
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum KeysCommand {
+    /// Take a key out of circulation, a claim of it no longer counts
+    Revoke {
+        key: String,
+        #[arg(short, long)]
+        reason: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Print the value of a setting
+    Get { key: String },
+    /// Change a setting, the bot picks it up the next time it starts
+    Set { key: String, value: String },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DbCommand {
+    /// Apply the migrations the database is missing
+    Migrate,
+}
+
+/// Runs `command` against the database `db`, the database has to be migrated for everything except
+/// `db migrate`, `backup` and `restore`
+pub async fn run(db: &dyn Store, command: Command) -> Result<()> {
+    if !matches!(
+        command,
+        Command::Db(DbCommand::Migrate) | Command::Backup | Command::Restore { .. }
+    ) {
+        let pending = db.pending_migrations().await?;
+        if pending > 0 {
+            return Err(eyre!(
+                "The database is missing {pending} migration(s), run `keybot db migrate` first"
+            ));
+        }
+    }
+
+    match command {
+        Command::Import {
+            file,
+            pool: key_pool,
+        } => {
+            let added = read_beta_keys_file(db, &file, &key_pool).await?;
+            if added > 0 {
+                let event = Event::new(EventKind::Ingest)
+                    .actor(CLI_ACTOR)
+                    .details(format!("{added} new keys read from {file}"));
+                audit::store(db, &event).await;
+            }
+            println!("Added {added} new key(s) from {file}");
+        }
+        Command::Export { pool: key_pool } => {
+            println!("key,pool,status,user,round,claimed_at");
+            for key in db.all_keys(key_pool.as_deref()).await? {
+                let status = if key.revoked {
+                    "revoked"
+                } else if key.username.is_some() {
+                    "claimed"
+                } else {
+                    "unclaimed"
+                };
+                println!(
+                    "{},{},{status},{},{},{}",
+                    key.key_val,
+                    key.pool,
+                    key.username.unwrap_or_default(),
+                    key.round.map(|r| r.to_string()).unwrap_or_default(),
+                    key.claimed_at.map(format_timestamp).unwrap_or_default()
+                );
+            }
+        }
+        Command::Stats => {
+            match db.get_round().await? {
+                Some(round) => println!("Active round: {round}"),
+                None => println!("Active round: none"),
+            }
+            for stats in db.pool_stats().await? {
+                println!(
+                    "{}: {} left, {} claimed, {} revoked, {} total",
+                    stats.pool, stats.remaining, stats.claimed, stats.revoked, stats.total
+                );
+            }
+        }
+        Command::Rounds(RoundsCommand::List) => {
+            for round in db.list_rounds().await? {
+                println!(
+                    "{}\t{}\t{}\t{} - {}",
+                    round.round_id,
+                    round.status,
+                    round.name.unwrap_or_default(),
+                    round.starts_at.map(format_timestamp).unwrap_or_default(),
+                    round.ends_at.map(format_timestamp).unwrap_or_default()
+                );
+            }
+        }
+        Command::Rounds(RoundsCommand::Set { round, force }) => {
+            let previous = set_round_db(db, round, force, &mut HashMap::new()).await?;
+
+            let details = match previous {
+                Some(previous) if previous == round => {
+                    println!("Round {round} is already active");
+                    return Ok(());
+                }
+                Some(previous) => {
+                    db.save_round_report(previous).await?;
+                    format!("Round changed from {previous} to {round}")
+                }
+                None => format!("Round {round} started"),
+            };
+
+            let event = Event::new(EventKind::RoundChange)
+                .actor(CLI_ACTOR)
+                .round(round)
+                .details(details.clone());
+            audit::store(db, &event).await;
+            println!("{details}");
+        }
+        Command::Keys(KeysCommand::Revoke { key, reason }) => {
+            let backups = Backups::from_config(&settings::load(db).await?);
+            backup::create(db, &backups, Reason::Revoke).await?;
+
+            let owner = db.revoke_key(&key).await?;
+
+            let mut event = Event::new(EventKind::Revoke)
+                .actor(CLI_ACTOR)
+                .key(key.as_str());
+            if let Some(owner) = &owner {
+                event = event.target(owner.as_str());
+            }
+            if let Some(reason) = &reason {
+                event = event.details(reason.as_str());
+            }
+            audit::store(db, &event).await;
+
+            match owner {
+                Some(owner) => println!("Revoked {key}, it was claimed by {owner}"),
+                None => println!("Revoked {key}, it was not claimed yet"),
+            }
+        }
+        Command::Config(ConfigCommand::Get { key }) => {
+            if !settings::is_known(&key) {
+                return Err(eyre!("Unknown setting `{key}`"));
+            }
+
+            // values set with `/config set` take precedence over the file, like when the bot starts
+            let value = match db.get_config_val(&key).await {
+                Ok(value) => Some(value),
+                Err(_) => settings::load_file(settings::CONFIG_FILE)
+                    .ok()
+                    .and_then(|file| file.get(&key).cloned()),
+            };
+            println!("{}", value.as_deref().unwrap_or("not set"));
+        }
+        Command::Config(ConfigCommand::Set { key, value }) => {
+            if !settings::is_known(&key) {
+                return Err(eyre!("Unknown setting `{key}`"));
+            }
+            let value = settings::validate(&key, &value)
+                .map_err(|e| eyre!("Invalid value for `{key}`: {e}"))?;
+
+            let old = db.get_config_val(&key).await.ok();
+            db.set_config_val(&key, &value).await?;
+
+            let details = format!(
+                "{key} changed from {} to {value}",
+                old.as_deref().unwrap_or("not set")
+            );
+            let event = Event::new(EventKind::ConfigChange)
+                .actor(CLI_ACTOR)
+                .details(details.clone());
+            audit::store(db, &event).await;
+            println!("{details}");
+        }
+        Command::Simulate {
+            users,
+            clicks,
+            pool: key_pool,
+            new_accounts,
+            without_role,
+            keys,
+            write,
+        } => {
+            let copy;
+            let db: &dyn Store = if write {
+                db
+            } else {
+                let Some(sqlite) = db.as_sqlite() else {
+                    return Err(eyre!(
+                        "Simulations without --write need a SQLite database, they run on an in-memory copy of it"
+                    ));
+                };
+                copy = sqlite.memory_copy().await?;
+                &copy
+            };
+
+            simulate(
+                db,
+                &key_pool,
+                keys.as_deref(),
+                users,
+                clicks,
+                new_accounts,
+                without_role,
+            )
+            .await?;
+        }
+        Command::Db(DbCommand::Migrate) => {
+            // checked first, looking for pending migrations creates the table they are tracked in
+            let empty = db.is_empty().await?;
+            let pending = db.pending_migrations().await?;
+            if pending > 0 && !empty {
+                let backups = Backups::from_config(&settings::load(db).await?);
+                if let Some(path) = backup::create(db, &backups, Reason::Migration).await? {
+                    println!("Backed up the database to {}", path.display());
+                }
+            }
+            db.add_tables().await?;
+            println!("Applied {pending} migration(s)");
+        }
+        Command::Backup => {
+            let backups = Backups::from_config(&settings::load(db).await?);
+            let Some(path) = backup::create(db, &backups, Reason::Manual).await? else {
+                return Err(eyre!(
+                    "Only SQLite databases can be backed up, use pg_dump for Postgres"
+                ));
+            };
+            println!("Backed up the database to {}", path.display());
+        }
+        Command::Restore { backup } => {
+            let backups = Backups::from_config(&settings::load(db).await?);
+            let previous = backup::restore(db, &backup, &backups).await?;
+            println!(
+                "Restored {} from {}, the previous database was backed up to {}",
+                db.as_sqlite().map(|s| s.path()).unwrap_or_default(),
+                backup.display(),
+                previous.display()
+            );
+            return Ok(());
+        }
+    }
+
+    Ok(())
+}
+
+// RFC 3339 in UTC, e.g. `2024-02-01T18:30:00.250Z`, so exports read the same wherever they are taken
+fn format_timestamp(at: chrono::DateTime<chrono::Utc>) -> String {
+    at.to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+}
+
+/// Lets `users` synthetic users click the button of a giveaway post `clicks` times each
+///
+/// The first `new_accounts` users have accounts that are too new, the next `without_role` users
+/// lack the key role. Keys are "delivered" by printing them.
+async fn simulate(
+    db: &dyn Store,
+    key_pool: &str,
+    keys: Option<&str>,
+    users: u32,
+    clicks: u32,
+    new_accounts: u32,
+    without_role: u32,
+) -> Result<()> {
+    if let Some(keys) = keys {
+        let added = read_beta_keys_file(db, keys, key_pool).await?;
+        println!("Added {added} new key(s) from {keys}");
+    }
+
+    // the same config the bot would start with
+    let config = settings::load(db).await?;
+
+    let Some(role) = config.get("role_id").cloned() else {
+        return Err(eyre!(
+            "No key role is set, the giveaway post would not start, set one with /set_key_role or `keybot config set role_id <id>`"
+        ));
+    };
+    let language = Language::from_config(&config).unwrap_or_default();
+
+    let config = Mutex::new(config);
+    let giveaway = Giveaway {
+        db,
+        config: &config,
+        http: None,
+        key_pool,
+    };
+
+    let now = chrono::Utc::now();
+    let (mut claimed, mut rejected, mut failed) = (0, 0, 0);
+
+    for _ in 0..clicks {
+        for i in 0..users {
+            let name = format!("sim-user-{i}");
+            let claimant = Claimant {
+                mention: name.clone(),
+                name,
+                created_at: if i < new_accounts {
+                    now
+                } else {
+                    now - chrono::Duration::days(365)
+                },
+                has_role: i < new_accounts || i >= new_accounts + without_role,
+                roles: Vec::new(),
+            };
+
+            let outcome = giveaway.claim(&claimant).await;
+            match &outcome {
+                Outcome::Claimed { key } => {
+                    claimed += 1;
+                    giveaway
+                        .deliver(
+                            &claimant.name,
+                            &claimant.mention,
+                            key,
+                            language,
+                            |content| {
+                                println!("{}: {content}", claimant.name);
+                                async { Ok(()) }
+                            },
+                        )
+                        .await?;
+                }
+                Outcome::Failed(_) => failed += 1,
+                _ => rejected += 1,
+            }
+
+            if let Some(content) = giveaway
+                .rejection(&outcome, &claimant, &role, language)
+                .await
+            {
+                println!("{}: {content}", claimant.name);
+            }
+        }
+    }
+
+    let remaining = db.remaining_unclaimed(key_pool).await?;
+    println!(
+        "{claimed} key(s) claimed, {rejected} click(s) rejected, {failed} claim(s) failed, {remaining} key(s) left in {key_pool}"
+    );
+
+    Ok(())
+}
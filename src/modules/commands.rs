@@ -1,36 +1,163 @@
-use std::{collections::HashMap, str::FromStr};
+use std::{collections::HashMap, str::FromStr, sync::Arc};
 
 use chrono::{self};
-use poise::serenity_prelude as serenity;
+use poise::serenity_prelude::{self as serenity, futures::StreamExt, Mentionable};
 use tokio::sync::Mutex;
+use tracing::{error, info, info_span, warn, Instrument, Span};
 
 use crate::{
-    db::{claim_key_with_user, set_config_val, set_round_db},
+    audit::{self, Event, EventKind},
+    backup::{self, Backups, Reason},
+    db::{Db, KeyInfo, Round, DEFAULT_POOL},
+    error::{ClaimError, Ineligible},
+    giveaway::{Claimant, Giveaway, Outcome},
+    limits::{LimitReached, Limits},
+    metrics::METRICS,
+    reports,
+    settings::{self, SETTINGS},
+    shutdown::Shutdown,
+    stock,
+    templates::{Language, Scope, Template},
     Args,
 };
 pub struct Data {
-    db: sqlx::SqlitePool,
+    db: Db,
     args: Args,
-    config: Mutex<HashMap<String, String>>,
+    config: Arc<Mutex<HashMap<String, String>>>,
+    shutdown: Arc<Shutdown>,
 } // User data, which is stored and accessible in all command invocations
 
 impl Data {
-    pub fn new(db: sqlx::SqlitePool, args: Args, config: Mutex<HashMap<String, String>>) -> Self {
-        Self { db, args, config }
+    pub fn new(
+        db: Db,
+        args: Args,
+        config: Arc<Mutex<HashMap<String, String>>>,
+        shutdown: Arc<Shutdown>,
+    ) -> Self {
+        Self {
+            db,
+            args,
+            config,
+            shutdown,
+        }
+    }
+
+    /// Backs up the database into the directory set with `backup_dir`, does nothing for Postgres
+    pub async fn backup(&self, reason: Reason) -> color_eyre::Result<Option<std::path::PathBuf>> {
+        let backups = Backups::from_config(&*self.config.lock().await);
+
+        backup::create(&*self.db, &backups, reason).await
+    }
+
+    /// Picks the language for a message, the user's discord locale is used first, then the
+    /// server's locale and finally the default_locale setting
+    pub async fn language(
+        &self,
+        user_locale: Option<&str>,
+        guild_locale: Option<&str>,
+    ) -> Language {
+        let default = Language::from_config(&*self.config.lock().await);
+
+        user_locale
+            .and_then(Language::from_locale)
+            .or_else(|| guild_locale.and_then(Language::from_locale))
+            .or(default)
+            .unwrap_or_default()
+    }
+
+    /// Writes an event to the audit log
+    pub async fn audit(&self, http: &serenity::Http, event: Event) {
+        audit::record(http, &*self.db, &self.config, event).await;
+    }
+
+    /// Stores the report of a round that was just closed and posts it to the report channel
+    pub async fn report_round(&self, http: &serenity::Http, round: i64) {
+        reports::publish(http, &*self.db, &self.config, round).await;
+    }
+
+    /// Alerts the admins if `key_pool` dropped below its low-stock threshold
+    pub async fn check_stock(&self, http: &serenity::Http, key_pool: &str) {
+        stock::check(Some(http), &*self.db, &self.config, key_pool).await;
+    }
+
+    /// The claim, eligibility and delivery logic for `key_pool`
+    pub fn giveaway<'a>(&'a self, http: &'a serenity::Http, key_pool: &'a str) -> Giveaway<'a> {
+        Giveaway {
+            db: &*self.db,
+            config: &self.config,
+            http: Some(http),
+            key_pool,
+        }
     }
+
+    /// Renders a message template with the current config
+    pub async fn render(
+        &self,
+        template: Template,
+        language: Language,
+        values: &[(&str, String)],
+    ) -> String {
+        template.render(&*self.config.lock().await, language, values)
+    }
+
+    /// Renders a template of a giveaway in `key_pool`, see [`Template::pool_text`]
+    pub async fn render_in_pool(
+        &self,
+        template: Template,
+        language: Language,
+        key_pool: &str,
+        values: &[(&str, String)],
+    ) -> String {
+        template.render_in_pool(&*self.config.lock().await, language, key_pool, values)
+    }
+}
+
+fn guild_locale(ctx: Context<'_>) -> Option<&str> {
+    match ctx {
+        poise::Context::Application(ctx) => ctx.interaction.guild_locale.as_deref(),
+        poise::Context::Prefix(_) => None,
+    }
+}
+
+/// The language of the user who invoked the command
+async fn user_language(ctx: Context<'_>) -> Language {
+    ctx.data().language(ctx.locale(), guild_locale(ctx)).await
+}
+
+/// The language of the server, used for public posts and DMs to users other than the invoker
+async fn guild_language(ctx: Context<'_>) -> Language {
+    ctx.data().language(None, guild_locale(ctx)).await
 }
 
 // Types used by all command functions
 pub type Error = Box<dyn std::error::Error + Send + Sync>;
 pub type Context<'a> = poise::Context<'a, Data, Error>;
 
+/// Runs before every command, fills in the span the command runs in and logs the invocation
+pub async fn pre_command(ctx: Context<'_>) {
+    let span = Span::current();
+    span.record("command", ctx.command().qualified_name.as_str());
+    if let Ok(Some(round)) = ctx.data().db.get_round().await {
+        span.record("round", round);
+    }
+
+    info!("Command invoked");
+}
+
 /// Command to explain other commands
 ///
 /// example invocation: `/help give_key`
-#[poise::command(slash_command, required_permissions = "ADMINISTRATOR")]
+#[poise::command(
+    slash_command,
+    required_permissions = "ADMINISTRATOR",
+    description_localized("de", "Erklärt die anderen Befehle"),
+    description_localized("es-ES", "Explica los demás comandos")
+)]
 pub async fn help(
     ctx: Context<'_>,
     #[description = "Specific command to show help about"]
+    #[description_localized("de", "Befehl, zu dem Hilfe angezeigt werden soll")]
+    #[description_localized("es-ES", "Comando del que mostrar ayuda")]
     #[autocomplete = "poise::builtins::autocomplete_command"]
     command: Option<String>,
 ) -> Result<(), Error> {
@@ -47,25 +174,359 @@ pub async fn help(
     Ok(())
 }
 
-#[poise::command(slash_command, required_permissions = "ADMINISTRATOR", ephemeral)]
+/// Show or change the bot config without restarting
+///
+/// example invocation: `/config set age_bound 7`
+#[poise::command(
+    slash_command,
+    required_permissions = "ADMINISTRATOR",
+    subcommands("config_show", "config_set"),
+    subcommand_required,
+    description_localized("de", "Konfiguration des Bots anzeigen oder ändern"),
+    description_localized("es-ES", "Mostrar o cambiar la configuración del bot")
+)]
+pub async fn config(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Show the current value of every setting
+#[poise::command(
+    slash_command,
+    rename = "show",
+    required_permissions = "ADMINISTRATOR",
+    ephemeral,
+    description_localized("de", "Den aktuellen Wert jeder Einstellung anzeigen"),
+    description_localized("es-ES", "Mostrar el valor actual de cada ajuste")
+)]
+pub async fn config_show(ctx: Context<'_>) -> Result<(), Error> {
+    let language = user_language(ctx).await;
+    let conf = ctx.data().config.lock().await;
+    let not_set = Template::NotSet.render(&conf, language, &[]);
+
+    let mut lines = SETTINGS
+        .iter()
+        .map(|s| {
+            let value = conf.get(s.key).unwrap_or(&not_set);
+            format!("`{}` = `{}` - {}", s.key, value, s.description)
+        })
+        .collect::<Vec<_>>();
+
+    // values set for a single pool, e.g. `embed_title_vip`
+    let mut pool_values = conf
+        .iter()
+        .filter(|(key, _)| settings::split_pool_key(key).is_some())
+        .map(|(key, value)| format!("`{key}` = `{value}`"))
+        .collect::<Vec<_>>();
+    pool_values.sort();
+    lines.extend(pool_values);
+
+    let custom_templates = settings::known_keys()
+        .filter(|key| key.starts_with("template_") && conf.contains_key(key))
+        .collect::<Vec<_>>();
+    drop(conf);
+
+    let mut content = lines.join("\n");
+    if !custom_templates.is_empty() {
+        content.push_str(&format!(
+            "\n\nCustom templates: `{}`, see `/preview_template`",
+            custom_templates.join("`, `")
+        ));
+    }
+
+    ctx.say(content).await?;
+
+    Ok(())
+}
+
+async fn autocomplete_setting<'a>(
+    _ctx: Context<'_>,
+    partial: &'a str,
+) -> impl Iterator<Item = String> + 'a {
+    settings::known_keys().filter(move |key| key.starts_with(partial))
+}
+
+/// Change a setting, the new value is used right away and kept across restarts
+#[poise::command(
+    slash_command,
+    rename = "set",
+    required_permissions = "ADMINISTRATOR",
+    ephemeral,
+    description_localized(
+        "de",
+        "Eine Einstellung ändern, sie gilt sofort und bleibt nach einem Neustart erhalten"
+    ),
+    description_localized(
+        "es-ES",
+        "Cambiar un ajuste, se aplica al instante y se conserva al reiniciar"
+    )
+)]
+pub async fn config_set(
+    ctx: Context<'_>,
+    #[description = "Setting to change"]
+    #[description_localized("de", "Zu ändernde Einstellung")]
+    #[description_localized("es-ES", "Ajuste a cambiar")]
+    #[autocomplete = "autocomplete_setting"]
+    key: String,
+    #[description = "New value for the setting"]
+    #[description_localized("de", "Neuer Wert der Einstellung")]
+    #[description_localized("es-ES", "Nuevo valor del ajuste")]
+    value: String,
+) -> Result<(), Error> {
+    let language = user_language(ctx).await;
+
+    if !settings::is_known(&key) {
+        let content = ctx
+            .data()
+            .render(Template::UnknownSetting, language, &[("key", key)])
+            .await;
+        ctx.say(content).await?;
+        return Ok(());
+    }
+
+    let value = match settings::validate(&key, &value) {
+        Ok(value) => value,
+        Err(e) => {
+            let content = ctx
+                .data()
+                .render(
+                    Template::InvalidValue,
+                    language,
+                    &[("key", key), ("reason", e.to_string())],
+                )
+                .await;
+            ctx.say(content).await?;
+            return Ok(());
+        }
+    };
+
+    let mut conf = ctx.data().config.lock().await;
+
+    ctx.data().db.set_config_val(&key, &value).await?;
+    let old = conf.insert(key.clone(), value.clone());
+    drop(conf);
+
+    let event = Event::new(EventKind::ConfigChange)
+        .actor(ctx.author().name.clone())
+        .details(format!(
+            "{key} changed from {} to {value}",
+            old.as_deref().unwrap_or("not set")
+        ));
+    ctx.data().audit(ctx.http(), event).await;
+
+    let old = match old {
+        Some(old) => old,
+        None => ctx.data().render(Template::NotSet, language, &[]).await,
+    };
+    let content = ctx
+        .data()
+        .render(
+            Template::SettingChanged,
+            language,
+            &[("key", key), ("old", old), ("new", value)],
+        )
+        .await;
+    ctx.say(content).await?;
+
+    Ok(())
+}
+
+/// Set the role users need to claim a key
+#[poise::command(
+    slash_command,
+    required_permissions = "ADMINISTRATOR",
+    ephemeral,
+    name_localized("de", "schlüsselrolle_setzen"),
+    name_localized("es-ES", "establecer_rol_de_clave"),
+    description_localized("de", "Die Rolle festlegen, die für einen Schlüssel benötigt wird"),
+    description_localized("es-ES", "Establecer el rol necesario para obtener una clave")
+)]
 pub async fn set_key_role(
     ctx: Context<'_>,
     #[autocomplete = "poise::builtins::autocomplete_command"]
     #[description = "Role to give to users who claim a key"]
+    #[description_localized("de", "Rolle, die einen Schlüssel erhalten darf")]
+    #[description_localized("es-ES", "Rol que puede obtener una clave")]
     role: serenity::Role,
 ) -> Result<(), Error> {
     let mut d = ctx.data().config.lock().await;
 
     d.insert(String::from("role_id"), role.id.to_string());
 
-    set_config_val(&ctx.data().db, "role_id", &role.id.to_string()).await?;
+    ctx.data()
+        .db
+        .set_config_val("role_id", &role.id.to_string())
+        .await?;
     drop(d);
 
-    ctx.say(format!("Key role set to {}", role.name)).await?;
+    let event = Event::new(EventKind::ConfigChange)
+        .actor(ctx.author().name.clone())
+        .details(format!("role_id changed to {} ({})", role.id, role.name));
+    ctx.data().audit(ctx.http(), event).await;
+
+    let language = user_language(ctx).await;
+    let content = ctx
+        .data()
+        .render(Template::RoleSet, language, &[("role", role.name.clone())])
+        .await;
+    ctx.say(content).await?;
+
+    Ok(())
+}
+
+/// Sends `key` from `key_pool` to `user` as a DM using the key_dm template
+async fn send_key(
+    ctx: Context<'_>,
+    user: &serenity::User,
+    key: &str,
+    key_pool: &str,
+    language: Language,
+) -> Result<(), ClaimError> {
+    ctx.data()
+        .giveaway(ctx.http(), key_pool)
+        .deliver(
+            &user.name,
+            &user.mention().to_string(),
+            key,
+            language,
+            |content| async move {
+                user.direct_message(ctx, serenity::CreateMessage::new().content(content))
+                    .await
+                    .map(|_| ())
+            },
+        )
+        .await
+}
+
+/// Renders the keys `user` claimed in the active round so they can be shown to them again
+///
+/// Nothing is claimed, each key that is shown is logged as a resend. Users can only do this once
+/// every `resend_cooldown` seconds.
+async fn resend_keys(
+    ctx: Context<'_>,
+    user: &serenity::User,
+    language: Language,
+    via: &str,
+) -> Result<String, Error> {
+    let db = &ctx.data().db;
+    let cooldown = ctx
+        .data()
+        .config
+        .lock()
+        .await
+        .get("resend_cooldown")
+        .and_then(|c| c.parse::<u64>().ok())
+        .unwrap_or(600);
+
+    if cooldown > 0
+        && db
+            .recent_events(EventKind::Resend.as_str(), &user.name, cooldown)
+            .await?
+            > 0
+    {
+        let minutes = cooldown.div_ceil(60).to_string();
+        return Ok(ctx
+            .data()
+            .render(
+                Template::ResendCooldown,
+                language,
+                &[("user", user.mention().to_string()), ("minutes", minutes)],
+            )
+            .await);
+    }
+
+    let round = db
+        .get_round()
+        .await?
+        .map(|r| r.to_string())
+        .unwrap_or_default();
+    let keys = db.round_keys(&user.name).await?;
+
+    if keys.is_empty() {
+        return Ok(ctx
+            .data()
+            .render(
+                Template::NoKeyThisRound,
+                language,
+                &[("user", user.mention().to_string()), ("round", round)],
+            )
+            .await);
+    }
+
+    let mut lines = Vec::new();
+    for key in keys {
+        let event = Event::new(EventKind::Resend)
+            .target(user.name.clone())
+            .key(key.clone())
+            .details(format!("shown again via {via}"));
+        ctx.data().audit(ctx.http(), event).await;
+
+        lines.push(
+            ctx.data()
+                .render(
+                    Template::KeyResent,
+                    language,
+                    &[
+                        ("key", key),
+                        ("user", user.mention().to_string()),
+                        ("round", round.clone()),
+                    ],
+                )
+                .await,
+        );
+    }
+
+    Ok(lines.join("\n"))
+}
+
+/// Show the key you claimed in the current round again, only you can see the reply
+#[poise::command(
+    slash_command,
+    ephemeral,
+    name_localized("de", "meine_schlüssel"),
+    name_localized("es-ES", "mis_claves"),
+    description_localized(
+        "de",
+        "Deinen Schlüssel der aktuellen Runde erneut anzeigen, nur du siehst die Antwort"
+    ),
+    description_localized(
+        "es-ES",
+        "Mostrar de nuevo tu clave de la ronda actual, solo tú ves la respuesta"
+    )
+)]
+pub async fn my_keys(ctx: Context<'_>) -> Result<(), Error> {
+    let language = user_language(ctx).await;
+    let content = resend_keys(ctx, ctx.author(), language, "/my_keys").await?;
+    ctx.say(content).await?;
 
     Ok(())
 }
 
+/// The roles of `user` in the server the command was used in, used for `claim_limit_roles`
+async fn member_roles(ctx: Context<'_>, user: serenity::UserId) -> Vec<serenity::RoleId> {
+    match ctx.guild_id() {
+        Some(guild) => guild
+            .member(ctx, user)
+            .await
+            .map(|m| m.roles)
+            .unwrap_or_default(),
+        None => Vec::new(),
+    }
+}
+
+/// Works out the claim limits of a user with `roles`, and which of them is reached if any
+async fn check_limits(
+    ctx: Context<'_>,
+    user: &str,
+    roles: &[serenity::RoleId],
+    key_pool: &str,
+) -> Result<(Limits, Option<LimitReached>), Error> {
+    Ok(ctx
+        .data()
+        .giveaway(ctx.http(), key_pool)
+        .check_limits(user, roles)
+        .await?)
+}
+
 // Command to give a key to a user
 //
 // Works as a slash command and a context menu command
@@ -74,226 +535,1929 @@ pub async fn set_key_role(
 #[poise::command(
     slash_command,
     required_permissions = "ADMINISTRATOR",
-    context_menu_command = "Give Key"
+    context_menu_command = "Give Key",
+    name_localized("de", "schlüssel_geben"),
+    name_localized("es-ES", "dar_clave"),
+    description_localized("de", "Einem Nutzer einen Schlüssel per DM schicken"),
+    description_localized("es-ES", "Enviar una clave a un usuario por MD")
 )]
 pub async fn give_key(
     ctx: Context<'_>,
     #[description = "Give key to this user, key is sent as a DM to the user"]
+    #[description_localized("de", "Nutzer, der den Schlüssel per DM erhält")]
+    #[description_localized("es-ES", "Usuario que recibe la clave por MD")]
     #[autocomplete = "poise::builtins::autocomplete_command"]
     user: serenity::User,
 ) -> Result<(), Error> {
-    let key = claim_key_with_user(&ctx.data().db, &user.name).await;
+    // checking the limits, claiming and sending the DM can take longer than discord waits for a
+    // first response
+    ctx.defer_ephemeral().await?;
+    let language = user_language(ctx).await;
 
     if user.bot {
-        ctx.defer_ephemeral().await?;
-        ctx.say("You can't give a key to a bot!").await?;
+        let content = ctx.data().render(Template::BotUser, language, &[]).await;
+        ctx.say(content).await?;
         return Ok(());
     }
 
-    if let Err(e) = key {
-        ctx.defer_ephemeral().await?;
-        ctx.say(format!(
-            "Could not get key, please try again later\n\nError: {e}"
-        ))
-        .await?;
-        return Ok(());
-    }
+    let roles = member_roles(ctx, user.id).await;
+    let (limits, reached) = check_limits(ctx, &user.name, &roles, DEFAULT_POOL).await?;
+
+    let key = match reached {
+        Some(reached) => Err(ClaimError::NotEligible(Ineligible::LimitReached(reached))),
+        None => {
+            ctx.data()
+                .db
+                .claim_key_with_user(&user.name, DEFAULT_POOL, &limits)
+                .await
+        }
+    };
 
-    let msg = serenity::CreateMessage::new().content(String::from(format!(
-        r#"Congratulations, you have been given a key!
-You can claim your key by entering it into steam.
-Your key is: {}
-"#,
-        key.expect("Could not get key, this options should be unreachable, please contact Yousof if you see this message")
-    )));
-    user.direct_message(&ctx, msg).await?;
+    let key = match key {
+        Ok(key) => key,
+        Err(e) => {
+            let event = Event::new(EventKind::Failure)
+                .actor(ctx.author().name.clone())
+                .target(user.name.clone())
+                .details(format!("Could not claim key: {e}"));
+            ctx.data().audit(ctx.http(), event).await;
+            METRICS.failed(e.kind());
 
-    ctx.defer_ephemeral().await?;
-    ctx.say(format!("Key sent to {}", user.name)).await?;
+            let content = ctx
+                .data()
+                .render(
+                    Template::GiveKeyFailed,
+                    language,
+                    &[("user", user.name.clone()), ("reason", e.to_string())],
+                )
+                .await;
+            ctx.say(content).await?;
+            return Ok(());
+        }
+    };
+
+    let event = Event::new(EventKind::Claim)
+        .actor(ctx.author().name.clone())
+        .target(user.name.clone())
+        .key(key.clone())
+        .details("given by an admin");
+    ctx.data().audit(ctx.http(), event).await;
+    METRICS.claimed();
+    ctx.data().check_stock(ctx.http(), DEFAULT_POOL).await;
+
+    // admins get the full cause, the user only ever sees the key
+    let content = match send_key(ctx, &user, &key, DEFAULT_POOL, guild_language(ctx).await).await {
+        Ok(()) => {
+            ctx.data()
+                .render(Template::KeySent, language, &[("user", user.name.clone())])
+                .await
+        }
+        Err(e) => {
+            ctx.data()
+                .render(
+                    Template::GiveKeyFailed,
+                    language,
+                    &[("user", user.name.clone()), ("reason", e.to_string())],
+                )
+                .await
+        }
+    };
+    ctx.say(content).await?;
 
     Ok(())
 }
 
+/// Asks the admin why a claim limit is waived
+#[derive(Debug, poise::Modal)]
+#[name = "Give key unchecked"]
+struct WaiverModal {
+    #[name = "Why does this user get an extra key?"]
+    #[placeholder = "e.g. replacement for a key that did not work"]
+    #[paragraph]
+    #[min_length = 3]
+    #[max_length = 500]
+    reason: String,
+}
+
 // Command to give a key to a user but dosn't check if the user has claimed a key before
 //
+// The claim is recorded as a waiver, a modal asks for the reason that is stored with it
+//
 // Works as a slash command and a context menu command
 // example invocation: `/give_key @user`
 // example invocation: Right click on username -> apps -> Give Key
 #[poise::command(
     slash_command,
     required_permissions = "ADMINISTRATOR",
-    context_menu_command = "Give Key unchecked"
+    context_menu_command = "Give Key unchecked",
+    name_localized("de", "schlüssel_geben_ungeprüft"),
+    name_localized("es-ES", "dar_clave_sin_comprobar"),
+    description_localized(
+        "de",
+        "Einem Nutzer einen Schlüssel schicken, auch wenn er in dieser Runde schon einen hat"
+    ),
+    description_localized(
+        "es-ES",
+        "Enviar una clave a un usuario aunque ya tenga una en esta ronda"
+    )
 )]
 pub async fn give_key_unchecked(
     ctx: Context<'_>,
     #[description = "Give key to this user, key is sent as a DM to the user"]
+    #[description_localized("de", "Nutzer, der den Schlüssel per DM erhält")]
+    #[description_localized("es-ES", "Usuario que recibe la clave por MD")]
     #[autocomplete = "poise::builtins::autocomplete_command"]
     user: serenity::User,
 ) -> Result<(), Error> {
-    let key = crate::db::give_key_unchecked(&ctx.data().db, &user.name).await;
+    let poise::Context::Application(app_ctx) = ctx else {
+        return Ok(());
+    };
+    // discord only takes a modal as the first response, so this can't be deferred like give_key.
+    // The modal is opened right away and its submission is acknowledged as soon as it arrives,
+    // which gives the claim below as long as a deferred response would.
+    let Some(WaiverModal { reason }) = poise::execute_modal(
+        app_ctx,
+        None::<WaiverModal>,
+        Some(std::time::Duration::from_secs(300)),
+    )
+    .await?
+    else {
+        return Ok(());
+    };
+    // the modal is the first response, so the replies below are follow ups that have to be marked
+    // ephemeral themselves
+
+    let language = user_language(ctx).await;
 
-    if let Err(e) = key {
-        ctx.defer_ephemeral().await?;
-        ctx.say(format!(
-            "Could not get key, please try again later\n\nError: {e}"
-        ))
+    // discord only checks the length, a reason of spaces would still get through
+    let reason = reason.trim();
+    if reason.is_empty() {
+        let content = ctx
+            .data()
+            .render(Template::WaiverReasonMissing, language, &[])
+            .await;
+        ctx.send(
+            poise::CreateReply::default()
+                .content(content)
+                .ephemeral(true),
+        )
         .await?;
         return Ok(());
     }
 
-    let msg = serenity::CreateMessage::new().content(String::from(format!(
-        r#"Congratulations, you have been given a key!
-You can claim your key by entering it into steam.
-Your key is: {}
-"#,
-        key.expect("Could not get key, this options should be unreachable, please contact Yousof if you see this message")
-    )));
-    user.direct_message(&ctx, msg).await?;
+    let key = match ctx
+        .data()
+        .db
+        .give_key_unchecked(&user.name, DEFAULT_POOL, reason)
+        .await
+    {
+        Ok(key) => key,
+        Err(e) => {
+            let event = Event::new(EventKind::Failure)
+                .actor(ctx.author().name.clone())
+                .target(user.name.clone())
+                .details(format!("Could not claim unchecked key: {e}"));
+            ctx.data().audit(ctx.http(), event).await;
+            METRICS.failed(e.kind());
 
-    ctx.defer_ephemeral().await?;
-    ctx.say(format!("Key sent to {}", user.name)).await?;
+            let content = ctx
+                .data()
+                .render(
+                    Template::GiveKeyFailed,
+                    language,
+                    &[("user", user.name.clone()), ("reason", e.to_string())],
+                )
+                .await;
+            ctx.send(
+                poise::CreateReply::default()
+                    .content(content)
+                    .ephemeral(true),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    let event = Event::new(EventKind::Claim)
+        .actor(ctx.author().name.clone())
+        .target(user.name.clone())
+        .key(key.clone())
+        .details(format!(
+            "given by an admin without checking earlier claims: {reason}"
+        ));
+    ctx.data().audit(ctx.http(), event).await;
+    METRICS.claimed();
+    ctx.data().check_stock(ctx.http(), DEFAULT_POOL).await;
+
+    let content = match send_key(ctx, &user, &key, DEFAULT_POOL, guild_language(ctx).await).await {
+        Ok(()) => {
+            ctx.data()
+                .render(Template::KeySent, language, &[("user", user.name.clone())])
+                .await
+        }
+        Err(e) => {
+            ctx.data()
+                .render(
+                    Template::GiveKeyFailed,
+                    language,
+                    &[("user", user.name.clone()), ("reason", e.to_string())],
+                )
+                .await
+        }
+    };
+    ctx.send(
+        poise::CreateReply::default()
+            .content(content)
+            .ephemeral(true),
+    )
+    .await?;
 
     Ok(())
 }
 
-#[poise::command(slash_command, required_permissions = "ADMINISTRATOR", ephemeral)]
-pub async fn set_round(ctx: Context<'_>, round: i64) -> Result<(), Error> {
-    let mut conf = ctx.data().config.lock().await;
+// a DM to every user at once trips discord's spam protection, so bulk gives are paced
+const BULK_DELAY: std::time::Duration = std::time::Duration::from_secs(1);
 
-    set_round_db(&ctx.data().db, round, &mut conf).await?;
+// parses a pasted list of user ids and mentions separated by spaces, commas or new lines
+fn parse_user_ids(list: &str) -> (Vec<serenity::UserId>, Vec<String>) {
+    let mut ids = Vec::new();
+    let mut invalid = Vec::new();
 
-    ctx.say(format!("Round set to {}", round)).await?;
+    for entry in list
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|e| !e.is_empty())
+    {
+        let id = entry
+            .trim_start_matches("<@")
+            .trim_start_matches('!')
+            .trim_end_matches('>');
 
-    Ok(())
+        match id.parse::<u64>() {
+            Ok(id) if id != 0 => ids.push(serenity::UserId::new(id)),
+            _ => invalid.push(entry.to_owned()),
+        }
+    }
+
+    (ids, invalid)
 }
 
-#[poise::command(slash_command, required_permissions = "ADMINISTRATOR", track_edits)]
-pub async fn create_key_post(
+/// Give a key to every member of a role or to a list of users
+///
+/// Shows how many users would get a key first and waits for a confirmation
+/// example invocation: `/give_keys_bulk role:@testers`
+#[poise::command(
+    slash_command,
+    required_permissions = "ADMINISTRATOR",
+    guild_only,
+    name_localized("de", "schlüssel_massenvergabe"),
+    name_localized("es-ES", "dar_claves_en_masa"),
+    description_localized(
+        "de",
+        "Allen Mitgliedern einer Rolle oder einer Liste von Nutzern einen Schlüssel geben"
+    ),
+    description_localized(
+        "es-ES",
+        "Dar una clave a todos los miembros de un rol o a una lista de usuarios"
+    )
+)]
+pub async fn give_keys_bulk(
     ctx: Context<'_>,
-    #[description = "Duration of the giveaway in seconds, defaults to 1 hour"] duration: Option<
-        u64,
-    >,
-    message: Option<String>,
+    #[description = "Give a key to every member of this role"]
+    #[description_localized("de", "Jedes Mitglied dieser Rolle erhält einen Schlüssel")]
+    #[description_localized("es-ES", "Cada miembro de este rol recibe una clave")]
+    role: Option<serenity::Role>,
+    #[description = "User IDs or mentions separated by spaces or commas"]
+    #[description_localized(
+        "de",
+        "Nutzer-IDs oder Erwähnungen, getrennt durch Leerzeichen oder Kommas"
+    )]
+    #[description_localized("es-ES", "IDs o menciones de usuarios separados por espacios o comas")]
+    users: Option<String>,
+    #[description = "Pool to take the keys from, defaults to the default pool"]
+    #[description_localized("de", "Pool, aus dem die Schlüssel kommen")]
+    #[description_localized("es-ES", "Grupo del que se toman las claves")]
+    #[autocomplete = "autocomplete_pool"]
+    pool: Option<String>,
 ) -> Result<(), Error> {
-    let data_map = ctx.data().config.lock().await;
-    let role = data_map.get("role_id");
+    let key_pool = pool.as_deref().unwrap_or(DEFAULT_POOL);
+    let language = user_language(ctx).await;
 
-    let role = if let Some(role) = role {
-        role
-    } else {
-        ctx.say("No role set, please set a role using /set_key_role")
-            .await?;
+    if role.is_none() && users.is_none() {
+        let content = ctx
+            .data()
+            .render(Template::BulkNoTargets, language, &[])
+            .await;
+        ctx.send(
+            poise::CreateReply::default()
+                .content(content)
+                .ephemeral(true),
+        )
+        .await?;
         return Ok(());
-    };
+    }
 
-    let reply = {
-        let embed = serenity::CreateEmbed::default().image("https://upload.wikimedia.org/wikipedia/commons/thumb/8/83/Steam_icon_logo.svg/512px-Steam_icon_logo.svg.png"); //TODO: make this an option
+    // resolving a large role can take longer than discord waits for a reply, the dry run and the
+    // progress only concern the admin
+    ctx.defer_ephemeral().await?;
 
-        let components = vec![serenity::CreateActionRow::Buttons(vec![
-            serenity::CreateButton::new("get_key_comp")
-                .label("Get key")
-                .style(serenity::ButtonStyle::Primary),
-        ])];
+    let guild_id = ctx.guild_id().expect("command is guild only");
+    let mut targets: Vec<(serenity::User, Vec<serenity::RoleId>)> = Vec::new();
+    let mut failed: Vec<String> = Vec::new();
+    let mut skipped: Vec<String> = Vec::new();
 
-        poise::CreateReply::default()
-            .content(message.unwrap_or_else(|| {
-                format!(
-                    "If you have the role <@&{}>\n\nClick the button below to get a beta key",
-                    role
-                )
-            }))
-            .embed(embed)
-            .components(components)
-    };
+    if let Some(role) = &role {
+        let mut members = guild_id.members_iter(ctx).boxed();
+        while let Some(member) = members.next().await {
+            let member = member?;
+            if member.roles.contains(&role.id) {
+                targets.push((member.user, member.roles));
+            }
+        }
+    }
 
-    let res = ctx.send(reply).await?;
+    if let Some(users) = &users {
+        let (ids, invalid) = parse_user_ids(users);
+        for entry in invalid {
+            failed.push(
+                ctx.data()
+                    .render(Template::BulkInvalidUser, language, &[("entry", entry)])
+                    .await,
+            );
+        }
 
-    while let Some(mci) = serenity::ComponentInteractionCollector::new(ctx)
-        .channel_id(ctx.channel_id())
-        .timeout(std::time::Duration::from_secs(
-            duration.unwrap_or_else(|| ctx.data().args.giveaway_duration),
-        ))
-        .filter(move |mci| mci.data.custom_id == "get_key_comp")
-        .await
-    {
-        // check if interaction uer has permission to claim a key
-        // mci.user.has_role(ctx, ctx.guild_id());
-        let has_role = mci
-            .user
-            .has_role(
-                ctx,
-                ctx.guild_id().expect("Could not get the guildID"),
-                serenity::RoleId::from_str(role).expect("Could not parse role id"),
-            )
-            .await?;
-        let now = chrono::Utc::now().naive_utc();
-        let age = mci.user.created_at().naive_utc();
-        let min_age = data_map
-            .get("age_bound")
-            .expect("Could not get age bound")
-            .parse::<i64>()
-            .expect("Age could not be parsed as a number");
-        let is_old = now.signed_duration_since(age).num_days() > min_age;
-
-        if !is_old {
-            mci.user
-                .direct_message(
-                    &ctx,
-                    serenity::CreateMessage::new()
-                        .content(format!("Your account is too new to claim a key. Your account must be at least {} days old", min_age)),
+        for id in ids {
+            match id.to_user(ctx).await {
+                Ok(user) => targets.push((user, member_roles(ctx, id).await)),
+                Err(e) => failed.push(format!("`{id}`: {e}")),
+            }
+        }
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    targets.retain(|(user, _)| seen.insert(user.id));
+
+    let mut pending = Vec::new();
+    for (user, roles) in targets {
+        if user.bot {
+            skipped.push(
+                ctx.data()
+                    .render(Template::BulkBotSkipped, language, &[("user", user.name)])
+                    .await,
+            );
+            continue;
+        }
+
+        match check_limits(ctx, &user.name, &roles, key_pool).await? {
+            (_, Some(reached)) => skipped.push(format!("{} ({reached})", user.name)),
+            (limits, None) => pending.push((user, limits)),
+        }
+    }
+
+    let remaining = ctx.data().db.remaining_unclaimed(key_pool).await?;
+    let confirm_id = format!("{}confirm", ctx.id());
+    let cancel_id = format!("{}cancel", ctx.id());
+
+    let mut summary = ctx
+        .data()
+        .render(
+            Template::BulkDryRun,
+            language,
+            &[
+                ("users", pending.len().to_string()),
+                ("pool", key_pool.to_owned()),
+                ("skipped", skipped.len().to_string()),
+                ("unresolved", failed.len().to_string()),
+                ("remaining", remaining.to_string()),
+            ],
+        )
+        .await;
+    if pending.len() > remaining as usize {
+        summary.push('\n');
+        summary += &ctx
+            .data()
+            .render(Template::BulkNotEnoughKeys, language, &[])
+            .await;
+    }
+
+    let reply = poise::CreateReply::default()
+        .content(&summary)
+        .components(vec![serenity::CreateActionRow::Buttons(vec![
+            serenity::CreateButton::new(&confirm_id)
+                .label(
+                    ctx.data()
+                        .render(Template::BulkConfirm, language, &[])
+                        .await,
                 )
-                .await?;
+                .style(serenity::ButtonStyle::Success)
+                .disabled(pending.is_empty()),
+            serenity::CreateButton::new(&cancel_id)
+                .label(ctx.data().render(Template::BulkCancel, language, &[]).await)
+                .style(serenity::ButtonStyle::Secondary),
+        ])]);
+    let res = ctx.send(reply).await?;
+    // giving keys to a large role takes longer than the 15 minutes an interaction token is valid,
+    // so progress is written to the message itself
+    let mut post = res.message().await?.into_owned();
 
+    let author = ctx.author().id;
+    let choice = serenity::ComponentInteractionCollector::new(ctx)
+        .message_id(post.id)
+        .author_id(author)
+        .timeout(std::time::Duration::from_secs(120))
+        .await;
+
+    let confirmed = match &choice {
+        Some(mci) => {
             mci.create_response(ctx, serenity::CreateInteractionResponse::Acknowledge)
                 .await?;
-
-            return Ok(());
+            mci.data.custom_id == confirm_id
         }
+        None => false,
+    };
 
-        if has_role {
-            let key = claim_key_with_user(&ctx.data().db, &mci.user.name).await;
+    if !confirmed {
+        let cancelled = ctx
+            .data()
+            .render(Template::BulkCancelled, language, &[])
+            .await;
+        post.edit(
+            ctx,
+            serenity::EditMessage::new()
+                .content(format!("{summary}\n\n{cancelled}"))
+                .components(vec![]),
+        )
+        .await?;
+        return Ok(());
+    }
 
-            if let Err(e) = key {
-                ctx.defer_ephemeral().await?;
-                mci.user
-                    .direct_message(
-                        ctx,
-                        serenity::CreateMessage::new()
-                            .content(format!("Could not claim key\nreason: {e}")),
-                    )
-                    .await?;
-            } else {
-                let msg = serenity::CreateMessage::new().content(String::from(format!(
-        r#"Congratulations, you have been given a key!
-You can claim your key by entering it into steam.
-Your key is: {}
-"#,
-        key.expect("Could not get key, this options should be unreachable, please contact Yousof if you see this message")
-    )));
-                mci.user.direct_message(&ctx, msg).await?;
-            }
-        } else {
-            mci.user
-                .direct_message(
-                    &ctx,
-                    serenity::CreateMessage::new().content(
-                        "You do not have permission to claim a key, please contact an admin if you think this is a mistake",
+    // the keys are sent on behalf of the server, so the DMs use its language
+    let dm_language = guild_language(ctx).await;
+    let total = pending.len();
+    let mut given = Vec::new();
+
+    for (i, (user, limits)) in pending.into_iter().enumerate() {
+        match ctx
+            .data()
+            .db
+            .claim_key_with_user(&user.name, key_pool, &limits)
+            .await
+        {
+            Ok(key) => {
+                let event = Event::new(EventKind::Claim)
+                    .actor(ctx.author().name.clone())
+                    .target(user.name.clone())
+                    .key(key.clone())
+                    .details("given in bulk by an admin");
+                ctx.data().audit(ctx.http(), event).await;
+                METRICS.claimed();
+
+                match send_key(ctx, &user, &key, key_pool, dm_language).await {
+                    Ok(()) => given.push(user.name.clone()),
+                    Err(e) => failed.push(
+                        ctx.data()
+                            .render(
+                                Template::BulkDmFailed,
+                                language,
+                                &[("user", user.name.clone()), ("reason", e.to_string())],
+                            )
+                            .await,
                     ),
-                )
-                .await?;
+                }
+            }
+            Err(e) => {
+                let event = Event::new(EventKind::Failure)
+                    .actor(ctx.author().name.clone())
+                    .target(user.name.clone())
+                    .details(format!("Could not claim key in bulk give: {e}"));
+                ctx.data().audit(ctx.http(), event).await;
+                METRICS.failed(e.kind());
+
+                failed.push(format!("{}: {e}", user.name));
+            }
         }
 
-        mci.create_response(ctx, serenity::CreateInteractionResponse::Acknowledge)
+        if (i + 1) % 10 == 0 && i + 1 < total {
+            let progress = ctx
+                .data()
+                .render(
+                    Template::BulkProgress,
+                    language,
+                    &[("done", (i + 1).to_string()), ("total", total.to_string())],
+                )
+                .await;
+            post.edit(
+                ctx,
+                serenity::EditMessage::new()
+                    .content(progress)
+                    .components(vec![]),
+            )
             .await?;
+        }
+
+        tokio::time::sleep(BULK_DELAY).await;
+    }
+    ctx.data().check_stock(ctx.http(), key_pool).await;
+
+    let conf = ctx.data().config.lock().await.clone();
+    let mut report = Template::BulkDone.render(
+        &conf,
+        language,
+        &[
+            ("given", given.len().to_string()),
+            ("skipped", skipped.len().to_string()),
+            ("failed", failed.len().to_string()),
+        ],
+    );
+    for (title, lines) in [
+        (Template::BulkSkipped, &skipped),
+        (Template::BulkFailed, &failed),
+    ] {
+        if !lines.is_empty() {
+            report += &format!(
+                "\n\n**{}**\n{}",
+                title.render(&conf, language, &[]),
+                join_lines(lines, &conf, language)
+            );
+        }
+    }
+    // join_lines keeps each list short, but both together can still be too long for one message
+    if report.chars().count() > 2000 {
+        report = report.chars().take(1997).collect::<String>() + "…";
     }
 
-    res.edit(
+    post.edit(
         ctx,
-        poise::reply::CreateReply::default()
-            .content("This key giveaway is over!")
+        serenity::EditMessage::new()
+            .content(report)
             .components(vec![]),
     )
     .await?;
 
     Ok(())
 }
+
+/// Plan, open, close and look at giveaway rounds
+///
+/// example invocation: `/round create "Closed beta 2"`
+#[poise::command(
+    slash_command,
+    required_permissions = "ADMINISTRATOR",
+    subcommands(
+        "round_create",
+        "round_open",
+        "round_close",
+        "round_list",
+        "round_info"
+    ),
+    subcommand_required,
+    name_localized("de", "runde"),
+    name_localized("es-ES", "ronda"),
+    description_localized("de", "Gewinnspielrunden planen, öffnen, schließen und anzeigen"),
+    description_localized("es-ES", "Planificar, abrir, cerrar y consultar rondas")
+)]
+pub async fn round(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// One line summary of a round
+fn describe_round(round: &Round, conf: &HashMap<String, String>, language: Language) -> String {
+    let mut line =
+        Template::RoundTitle.render(conf, language, &[("round", round.round_id.to_string())]);
+    if let Some(name) = &round.name {
+        line += &format!(" {name}");
+    }
+    line += &format!(" ({})", round.status);
+    if let Some(starts_at) = &round.starts_at {
+        let time = format!("<t:{}:f>", starts_at.timestamp());
+        line += ", ";
+        line += &Template::RoundStarted.render(conf, language, &[("time", time)]);
+    }
+    if let Some(ends_at) = &round.ends_at {
+        let time = format!("<t:{}:f>", ends_at.timestamp());
+        line += ", ";
+        line += &Template::RoundEnded.render(conf, language, &[("time", time)]);
+    }
+
+    line
+}
+
+/// Plan a new round, it has to be opened before keys can be claimed in it
+#[poise::command(
+    slash_command,
+    rename = "create",
+    required_permissions = "ADMINISTRATOR",
+    ephemeral,
+    description_localized("de", "Eine neue Runde planen"),
+    description_localized("es-ES", "Planificar una nueva ronda")
+)]
+pub async fn round_create(
+    ctx: Context<'_>,
+    #[description = "Name of the round"]
+    #[description_localized("de", "Name der Runde")]
+    #[description_localized("es-ES", "Nombre de la ronda")]
+    name: String,
+    #[description = "Notes for other admins"]
+    #[description_localized("de", "Notizen für andere Admins")]
+    #[description_localized("es-ES", "Notas para otros administradores")]
+    notes: Option<String>,
+) -> Result<(), Error> {
+    let id = ctx.data().db.create_round(&name, notes.as_deref()).await?;
+
+    let event = Event::new(EventKind::RoundChange)
+        .actor(ctx.author().name.clone())
+        .round(id)
+        .details(format!("Round {id} \"{name}\" planned"));
+    ctx.data().audit(ctx.http(), event).await;
+
+    let language = user_language(ctx).await;
+    let content = ctx
+        .data()
+        .render(
+            Template::RoundPlanned,
+            language,
+            &[("round", id.to_string()), ("name", name)],
+        )
+        .await;
+    ctx.say(content).await?;
+
+    Ok(())
+}
+
+/// Open a round, the active round is closed
+#[poise::command(
+    slash_command,
+    rename = "open",
+    required_permissions = "ADMINISTRATOR",
+    ephemeral,
+    description_localized("de", "Eine Runde öffnen, die aktive Runde wird geschlossen"),
+    description_localized("es-ES", "Abrir una ronda, la ronda activa se cierra")
+)]
+pub async fn round_open(
+    ctx: Context<'_>,
+    #[description = "Number of the round"]
+    #[description_localized("de", "Nummer der Runde")]
+    #[description_localized("es-ES", "Número de la ronda")]
+    round: i64,
+    #[description = "Open the round even if it was closed before"]
+    #[description_localized("de", "Die Runde auch öffnen, wenn sie schon geschlossen wurde")]
+    #[description_localized("es-ES", "Abrir la ronda aunque ya se haya cerrado")]
+    force: Option<bool>,
+) -> Result<(), Error> {
+    let force = force.unwrap_or(false);
+    let language = user_language(ctx).await;
+    let previous = match ctx.data().db.open_round(round, force).await {
+        Ok(previous) => previous,
+        Err(e) => {
+            let content = ctx
+                .data()
+                .render(
+                    Template::RoundOpenFailed,
+                    language,
+                    &[("reason", e.to_string())],
+                )
+                .await;
+            ctx.say(content).await?;
+            return Ok(());
+        }
+    };
+
+    if previous == Some(round) {
+        let content = ctx
+            .data()
+            .render(
+                Template::RoundAlreadyActive,
+                language,
+                &[("round", round.to_string())],
+            )
+            .await;
+        ctx.say(content).await?;
+        return Ok(());
+    }
+
+    ctx.data()
+        .config
+        .lock()
+        .await
+        .insert("claim_round".to_owned(), round.to_string());
+
+    let mut details = match previous {
+        Some(previous) => format!("Round {previous} closed, round {round} opened"),
+        None => format!("Round {round} opened"),
+    };
+    if force {
+        details += " (forced)";
+    }
+    let event = Event::new(EventKind::RoundChange)
+        .actor(ctx.author().name.clone())
+        .round(round)
+        .details(details);
+    ctx.data().audit(ctx.http(), event).await;
+
+    let content = match previous {
+        Some(previous) => {
+            ctx.data()
+                .render(
+                    Template::RoundSwitched,
+                    language,
+                    &[
+                        ("previous", previous.to_string()),
+                        ("round", round.to_string()),
+                    ],
+                )
+                .await
+        }
+        None => {
+            ctx.data()
+                .render(
+                    Template::RoundOpened,
+                    language,
+                    &[("round", round.to_string())],
+                )
+                .await
+        }
+    };
+    ctx.say(content).await?;
+
+    if let Some(previous) = previous {
+        ctx.data().report_round(ctx.http(), previous).await;
+    }
+
+    Ok(())
+}
+
+/// Close a round, no keys can be claimed until another round is opened
+#[poise::command(
+    slash_command,
+    rename = "close",
+    required_permissions = "ADMINISTRATOR",
+    ephemeral,
+    description_localized("de", "Eine Runde schließen"),
+    description_localized("es-ES", "Cerrar una ronda")
+)]
+pub async fn round_close(
+    ctx: Context<'_>,
+    #[description = "Number of the round, defaults to the active round"]
+    #[description_localized("de", "Nummer der Runde, standardmäßig die aktive Runde")]
+    #[description_localized("es-ES", "Número de la ronda, por defecto la ronda activa")]
+    round: Option<i64>,
+) -> Result<(), Error> {
+    let language = user_language(ctx).await;
+    let round = match round {
+        Some(round) => round,
+        None => match ctx.data().db.get_round().await? {
+            Some(round) => round,
+            None => {
+                let content = ctx
+                    .data()
+                    .render(Template::NoActiveRound, language, &[])
+                    .await;
+                ctx.say(content).await?;
+                return Ok(());
+            }
+        },
+    };
+
+    if let Err(e) = ctx.data().db.close_round(round).await {
+        let content = ctx
+            .data()
+            .render(
+                Template::RoundCloseFailed,
+                language,
+                &[("reason", e.to_string())],
+            )
+            .await;
+        ctx.say(content).await?;
+        return Ok(());
+    }
+
+    let event = Event::new(EventKind::RoundChange)
+        .actor(ctx.author().name.clone())
+        .round(round)
+        .details(format!("Round {round} closed"));
+    ctx.data().audit(ctx.http(), event).await;
+
+    let content = ctx
+        .data()
+        .render(
+            Template::RoundClosed,
+            language,
+            &[("round", round.to_string())],
+        )
+        .await;
+    ctx.say(content).await?;
+
+    ctx.data().report_round(ctx.http(), round).await;
+
+    Ok(())
+}
+
+/// List every round
+#[poise::command(
+    slash_command,
+    rename = "list",
+    required_permissions = "ADMINISTRATOR",
+    ephemeral,
+    description_localized("de", "Alle Runden anzeigen"),
+    description_localized("es-ES", "Mostrar todas las rondas")
+)]
+pub async fn round_list(ctx: Context<'_>) -> Result<(), Error> {
+    let rounds = ctx.data().db.list_rounds().await?;
+    let language = user_language(ctx).await;
+    let conf = ctx.data().config.lock().await.clone();
+
+    if rounds.is_empty() {
+        ctx.say(Template::NoRounds.render(&conf, language, &[]))
+            .await?;
+        return Ok(());
+    }
+
+    let lines = rounds
+        .iter()
+        .map(|round| describe_round(round, &conf, language))
+        .collect::<Vec<_>>();
+    ctx.say(join_lines(&lines, &conf, language)).await?;
+
+    Ok(())
+}
+
+/// Show the details of a round
+#[poise::command(
+    slash_command,
+    rename = "info",
+    required_permissions = "ADMINISTRATOR",
+    ephemeral,
+    description_localized("de", "Details einer Runde anzeigen"),
+    description_localized("es-ES", "Mostrar los detalles de una ronda")
+)]
+pub async fn round_info(
+    ctx: Context<'_>,
+    #[description = "Number of the round, defaults to the active round"]
+    #[description_localized("de", "Nummer der Runde, standardmäßig die aktive Runde")]
+    #[description_localized("es-ES", "Número de la ronda, por defecto la ronda activa")]
+    round: Option<i64>,
+) -> Result<(), Error> {
+    let language = user_language(ctx).await;
+    let conf = ctx.data().config.lock().await.clone();
+    let round = match round {
+        Some(round) => Some(round),
+        None => ctx.data().db.get_round().await?,
+    };
+    let Some(info) = (match round {
+        Some(round) => ctx.data().db.round_info(round).await?,
+        None => None,
+    }) else {
+        ctx.say(Template::NoSuchRound.render(&conf, language, &[]))
+            .await?;
+        return Ok(());
+    };
+
+    let (claims, users) = ctx.data().db.round_claim_counts(info.round_id).await?;
+    let mut content = format!(
+        "{}\n{}",
+        describe_round(&info, &conf, language),
+        Template::RoundClaims.render(
+            &conf,
+            language,
+            &[("claims", claims.to_string()), ("users", users.to_string())],
+        )
+    );
+    if let Some(notes) = &info.notes {
+        content += &format!("\n\n{notes}");
+    }
+    ctx.say(content).await?;
+
+    Ok(())
+}
+
+/// Show the summary of a closed round
+///
+/// example invocation: `/round_report 3`
+#[poise::command(
+    slash_command,
+    required_permissions = "ADMINISTRATOR",
+    ephemeral,
+    name_localized("de", "rundenbericht"),
+    name_localized("es-ES", "informe_de_ronda"),
+    description_localized("de", "Die Zusammenfassung einer geschlossenen Runde anzeigen"),
+    description_localized("es-ES", "Mostrar el resumen de una ronda cerrada")
+)]
+pub async fn round_report(
+    ctx: Context<'_>,
+    #[description = "Number of the round"]
+    #[description_localized("de", "Nummer der Runde")]
+    #[description_localized("es-ES", "Número de la ronda")]
+    round: i64,
+) -> Result<(), Error> {
+    let language = user_language(ctx).await;
+    let Some(report) = ctx.data().db.round_report(round).await? else {
+        let content = ctx
+            .data()
+            .render(
+                Template::NoRoundReport,
+                language,
+                &[("round", round.to_string())],
+            )
+            .await;
+        ctx.say(content).await?;
+        return Ok(());
+    };
+
+    let name = ctx.data().db.round_info(round).await?.and_then(|r| r.name);
+    let embed = reports::embed(
+        &report,
+        name.as_deref(),
+        &*ctx.data().config.lock().await,
+        language,
+    );
+    ctx.send(poise::CreateReply::default().embed(embed)).await?;
+
+    Ok(())
+}
+
+/// Take a key out of circulation, it won't be handed out again and doesn't count as a claim
+///
+/// example invocation: `/revoke_key AAAAA-BBBBB-CCCCC posted publicly`
+#[poise::command(
+    slash_command,
+    required_permissions = "ADMINISTRATOR",
+    ephemeral,
+    description_localized(
+        "de",
+        "Einen Schlüssel sperren, er wird nicht mehr vergeben und zählt nicht als Abruf"
+    ),
+    description_localized(
+        "es-ES",
+        "Revocar una clave, no se vuelve a entregar y no cuenta como obtenida"
+    )
+)]
+pub async fn revoke_key(
+    ctx: Context<'_>,
+    #[description = "Key to revoke"]
+    #[description_localized("de", "Zu sperrender Schlüssel")]
+    #[description_localized("es-ES", "Clave a revocar")]
+    key: String,
+    #[description = "Why the key is revoked, stored in the audit log"]
+    #[description_localized("de", "Grund für die Sperrung, wird im Audit-Log gespeichert")]
+    #[description_localized("es-ES", "Motivo de la revocación, se guarda en el registro")]
+    reason: Option<String>,
+) -> Result<(), Error> {
+    let language = user_language(ctx).await;
+
+    if let Err(e) = ctx.data().backup(Reason::Revoke).await {
+        let content = ctx
+            .data()
+            .render(
+                Template::RevokeBackupFailed,
+                language,
+                &[("reason", e.to_string())],
+            )
+            .await;
+        ctx.say(content).await?;
+        return Ok(());
+    }
+
+    let owner = match ctx.data().db.revoke_key(&key).await {
+        Ok(owner) => owner,
+        Err(e) => {
+            let content = ctx
+                .data()
+                .render(
+                    Template::RevokeFailed,
+                    language,
+                    &[("reason", e.to_string())],
+                )
+                .await;
+            ctx.say(content).await?;
+            return Ok(());
+        }
+    };
+
+    let mut event = Event::new(EventKind::Revoke)
+        .actor(ctx.author().name.clone())
+        .key(key);
+    if let Some(owner) = &owner {
+        event = event.target(owner.clone());
+    }
+    if let Some(reason) = reason {
+        event = event.details(reason);
+    }
+    ctx.data().audit(ctx.http(), event).await;
+
+    let reply = match owner {
+        Some(owner) => {
+            ctx.data()
+                .render(Template::KeyRevokedFrom, language, &[("user", owner)])
+                .await
+        }
+        None => {
+            ctx.data()
+                .render(Template::KeyRevokedUnclaimed, language, &[])
+                .await
+        }
+    };
+    ctx.say(reply).await?;
+
+    Ok(())
+}
+
+/// One line describing who has a key and whether it was delivered
+fn describe_key(info: &KeyInfo, conf: &HashMap<String, String>, language: Language) -> String {
+    let render =
+        |template: Template, values: &[(&str, String)]| template.render(conf, language, values);
+    let time = |at: &chrono::DateTime<chrono::Utc>| format!("<t:{}:f>", at.timestamp());
+
+    let mut line = format!("`{}`", info.key_val);
+    if info.pool != DEFAULT_POOL {
+        line += &format!(
+            " ({})",
+            render(Template::KeyPool, &[("pool", info.pool.clone())])
+        );
+    }
+
+    line.push(' ');
+    line += &match (&info.username, info.round) {
+        (Some(user), Some(round)) => render(
+            Template::KeyClaimedInRound,
+            &[("user", user.clone()), ("round", round.to_string())],
+        ),
+        (Some(user), None) => render(Template::KeyClaimedBy, &[("user", user.clone())]),
+        _ => render(Template::KeyNotClaimed, &[]),
+    };
+    if let Some(claimed_at) = &info.claimed_at {
+        line.push(' ');
+        line += &render(Template::KeyClaimedAt, &[("time", time(claimed_at))]);
+    }
+
+    let delivery = match (info.last_delivery.as_deref(), &info.last_delivery_at) {
+        (Some("delivery"), Some(at)) => Some(render(Template::KeyDmSent, &[("time", time(at))])),
+        (Some(_), Some(at)) => Some(render(Template::KeyDmFailed, &[("time", time(at))])),
+        _ if info.username.is_some() => Some(render(Template::KeyNoDm, &[])),
+        _ => None,
+    };
+    if let Some(delivery) = delivery {
+        line += ", ";
+        line += &delivery;
+    }
+
+    if let Some(added_at) = &info.added_at {
+        line += ", ";
+        line += &render(Template::KeyAddedAt, &[("time", time(added_at))]);
+    }
+    if info.revoked {
+        line += ", ";
+        line += &render(Template::KeyRevoked, &[]);
+    }
+
+    line
+}
+
+// discord messages are limited to 2000 characters, leave room for the header
+fn join_lines(lines: &[String], conf: &HashMap<String, String>, language: Language) -> String {
+    let mut out = String::new();
+
+    for (i, line) in lines.iter().enumerate() {
+        if out.len() + line.len() > 1800 {
+            out += &Template::MoreLines.render(
+                conf,
+                language,
+                &[("count", (lines.len() - i).to_string())],
+            );
+            break;
+        }
+        out += line;
+        out.push('\n');
+    }
+
+    out
+}
+
+/// Show who claimed a key, in which round and whether the DM was sent
+///
+/// example invocation: `/whois_key AAAAA-BBBBB-CCCCC`
+#[poise::command(
+    slash_command,
+    required_permissions = "ADMINISTRATOR",
+    ephemeral,
+    description_localized(
+        "de",
+        "Anzeigen, wer einen Schlüssel erhalten hat und ob die DM angekommen ist"
+    ),
+    description_localized("es-ES", "Mostrar quién obtuvo una clave y si se envió el MD")
+)]
+pub async fn whois_key(
+    ctx: Context<'_>,
+    #[description = "Key to look up"]
+    #[description_localized("de", "Gesuchter Schlüssel")]
+    #[description_localized("es-ES", "Clave a buscar")]
+    key: String,
+) -> Result<(), Error> {
+    let language = user_language(ctx).await;
+    let conf = ctx.data().config.lock().await.clone();
+
+    let reply = match ctx.data().db.key_info(key.trim()).await? {
+        Some(info) => describe_key(&info, &conf, language),
+        None => Template::UnknownKey.render(&conf, language, &[("key", key.trim().to_owned())]),
+    };
+    ctx.say(reply).await?;
+
+    Ok(())
+}
+
+// Context menu version of `/whois_key`, looks up every key in the message
+//
+// example invocation: Right click on a message -> apps -> Whois Key
+#[poise::command(
+    context_menu_command = "Whois Key",
+    required_permissions = "ADMINISTRATOR",
+    ephemeral
+)]
+pub async fn whois_key_message(
+    ctx: Context<'_>,
+    #[description = "Message containing the key"] msg: serenity::Message,
+) -> Result<(), Error> {
+    let language = user_language(ctx).await;
+    let conf = ctx.data().config.lock().await.clone();
+
+    let mut lines = Vec::new();
+    for word in msg.content.split_whitespace() {
+        let word = word.trim_matches(|c: char| !c.is_ascii_alphanumeric() && c != '-');
+        if let Some(info) = ctx.data().db.key_info(word).await? {
+            lines.push(describe_key(&info, &conf, language));
+        }
+    }
+
+    if lines.is_empty() {
+        ctx.say(Template::NoKeysInMessage.render(&conf, language, &[]))
+            .await?;
+    } else {
+        ctx.say(join_lines(&lines, &conf, language)).await?;
+    }
+
+    Ok(())
+}
+
+/// List every key a user has received, across all rounds
+///
+/// Works as a slash command and a context menu command
+/// example invocation: `/user_keys @user`
+/// example invocation: Right click on username -> apps -> User Keys
+#[poise::command(
+    slash_command,
+    required_permissions = "ADMINISTRATOR",
+    context_menu_command = "User Keys",
+    ephemeral,
+    description_localized("de", "Alle Schlüssel eines Nutzers aus allen Runden anzeigen"),
+    description_localized("es-ES", "Mostrar todas las claves de un usuario en todas las rondas")
+)]
+pub async fn user_keys(
+    ctx: Context<'_>,
+    #[description = "User to look up"]
+    #[description_localized("de", "Gesuchter Nutzer")]
+    #[description_localized("es-ES", "Usuario a buscar")]
+    user: serenity::User,
+) -> Result<(), Error> {
+    let keys = ctx.data().db.user_keys(&user.name).await?;
+    let language = user_language(ctx).await;
+    let conf = ctx.data().config.lock().await.clone();
+
+    if keys.is_empty() {
+        ctx.say(Template::UserNoKeys.render(&conf, language, &[("user", user.name)]))
+            .await?;
+        return Ok(());
+    }
+
+    let lines = keys
+        .iter()
+        .map(|key| describe_key(key, &conf, language))
+        .collect::<Vec<_>>();
+    let header = Template::UserKeys.render(
+        &conf,
+        language,
+        &[("user", user.name), ("count", keys.len().to_string())],
+    );
+    ctx.say(format!("{header}\n{}", join_lines(&lines, &conf, language)))
+        .await?;
+
+    Ok(())
+}
+
+async fn autocomplete_template<'a>(
+    _ctx: Context<'_>,
+    partial: &'a str,
+) -> impl Iterator<Item = &'static str> + 'a {
+    Template::ALL
+        .iter()
+        .map(|t| t.name())
+        .filter(move |name| name.starts_with(partial))
+}
+
+async fn autocomplete_pool<'a>(
+    ctx: Context<'_>,
+    partial: &'a str,
+) -> impl Iterator<Item = String> + 'a {
+    ctx.data()
+        .db
+        .pools()
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .filter(move |pool| pool.starts_with(partial))
+}
+
+async fn autocomplete_language<'a>(
+    _ctx: Context<'_>,
+    partial: &'a str,
+) -> impl Iterator<Item = &'static str> + 'a {
+    Language::ALL
+        .iter()
+        .map(|l| l.code())
+        .filter(move |code| code.starts_with(partial))
+}
+
+/// Show how a message template looks with example values
+///
+/// Templates are changed with `/config set template_<name> <text>` or in the config file
+/// example invocation: `/preview_template key_dm de`
+#[poise::command(
+    slash_command,
+    required_permissions = "ADMINISTRATOR",
+    ephemeral,
+    description_localized("de", "Eine Nachrichtenvorlage mit Beispielwerten anzeigen"),
+    description_localized("es-ES", "Mostrar una plantilla de mensaje con valores de ejemplo")
+)]
+pub async fn preview_template(
+    ctx: Context<'_>,
+    #[description = "Template to preview"]
+    #[description_localized("de", "Anzuzeigende Vorlage")]
+    #[description_localized("es-ES", "Plantilla a mostrar")]
+    #[autocomplete = "autocomplete_template"]
+    name: String,
+    #[description = "Language to preview, defaults to your own"]
+    #[description_localized("de", "Sprache der Vorschau, standardmäßig deine eigene")]
+    #[description_localized("es-ES", "Idioma de la vista previa, por defecto el tuyo")]
+    #[autocomplete = "autocomplete_language"]
+    language: Option<String>,
+) -> Result<(), Error> {
+    let Some(template) = Template::from_name(&name) else {
+        let content = ctx
+            .data()
+            .render(
+                Template::UnknownPreview,
+                user_language(ctx).await,
+                &[("template", name)],
+            )
+            .await;
+        ctx.say(content).await?;
+        return Ok(());
+    };
+
+    let language = match language {
+        Some(language) => match Language::from_locale(&language) {
+            Some(language) => language,
+            None => {
+                let content = ctx
+                    .data()
+                    .render(
+                        Template::UnknownLanguage,
+                        user_language(ctx).await,
+                        &[("language", language)],
+                    )
+                    .await;
+                ctx.say(content).await?;
+                return Ok(());
+            }
+        },
+        None => user_language(ctx).await,
+    };
+
+    let conf = ctx.data().config.lock().await;
+    let source = if conf.contains_key(&template.config_key(Scope::Language(language)))
+        || conf.contains_key(&template.config_key(Scope::All))
+    {
+        "custom"
+    } else {
+        "default"
+    };
+    let rendered = template.render(&conf, language, &template.sample_values());
+    drop(conf);
+
+    let placeholders = template
+        .placeholders()
+        .iter()
+        .map(|p| format!("`{{{p}}}`"))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    ctx.say(format!(
+        "**{}** ({}, {source}), placeholders: {placeholders}\n\n{rendered}",
+        template.name(),
+        language.code()
+    ))
+    .await?;
+
+    Ok(())
+}
+
+const DEFAULT_EMBED_IMAGE: &str = "https://upload.wikimedia.org/wikipedia/commons/thumb/8/83/Steam_icon_logo.svg/512px-Steam_icon_logo.svg.png";
+
+/// The `embed_*` values of a giveaway from `key_pool`, an option given to the command is used
+/// first, then `embed_<name>_<pool>` and then `embed_<name>`
+///
+/// `none` leaves a value out, the image only falls back to the Steam logo if it wasn't set at all.
+fn embed_values(
+    config: &HashMap<String, String>,
+    key_pool: &str,
+    options: Vec<(&'static str, Option<String>)>,
+) -> HashMap<&'static str, String> {
+    let mut values = HashMap::new();
+
+    for (key, option) in options {
+        let value = option.or_else(|| settings::pool_value(config, key, key_pool).cloned());
+        match value {
+            Some(value) if value == "none" || value.is_empty() => {}
+            Some(value) => {
+                values.insert(key, value);
+            }
+            None if key == "embed_image" => {
+                values.insert(key, DEFAULT_EMBED_IMAGE.to_owned());
+            }
+            None => {}
+        }
+    }
+
+    values
+}
+
+/// How the giveaway embed looks, built from the `/create_key_post` options and the `embed_*` settings
+struct GiveawayEmbed {
+    title: Option<String>,
+    description: Option<String>,
+    image: Option<String>,
+    thumbnail: Option<String>,
+    color: Option<u32>,
+    footer: Option<String>,
+    ends_label: String,
+    ended_label: String,
+    keys_left_label: String,
+}
+
+impl GiveawayEmbed {
+    fn build(
+        &self,
+        ends_at: chrono::DateTime<chrono::Utc>,
+        remaining: i32,
+        over: bool,
+    ) -> serenity::CreateEmbed {
+        let mut embed = serenity::CreateEmbed::default();
+
+        if let Some(title) = &self.title {
+            embed = embed.title(title);
+        }
+        if let Some(description) = &self.description {
+            embed = embed.description(description);
+        }
+        if let Some(image) = &self.image {
+            embed = embed.image(image);
+        }
+        if let Some(thumbnail) = &self.thumbnail {
+            embed = embed.thumbnail(thumbnail);
+        }
+        if let Some(color) = self.color {
+            embed = embed.color(color);
+        }
+        if let Some(footer) = &self.footer {
+            embed = embed.footer(serenity::CreateEmbedFooter::new(footer));
+        }
+
+        let ends = if over {
+            &self.ended_label
+        } else {
+            &self.ends_label
+        };
+        embed
+            .field(
+                ends,
+                format!("<t:{0}:R> (<t:{0}:f>)", ends_at.timestamp()),
+                true,
+            )
+            .field(&self.keys_left_label, remaining.to_string(), true)
+    }
+}
+
+/// Post a giveaway with a button users can click to get a key
+#[poise::command(
+    slash_command,
+    required_permissions = "ADMINISTRATOR",
+    track_edits,
+    name_localized("de", "gewinnspiel_erstellen"),
+    name_localized("es-ES", "crear_sorteo"),
+    description_localized(
+        "de",
+        "Ein Gewinnspiel mit einem Button posten, über den Nutzer einen Schlüssel erhalten"
+    ),
+    description_localized(
+        "es-ES",
+        "Publicar un sorteo con un botón para que los usuarios obtengan una clave"
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn create_key_post(
+    ctx: Context<'_>,
+    #[description = "Duration of the giveaway in seconds, defaults to the giveaway_duration setting"]
+    #[description_localized("de", "Dauer des Gewinnspiels in Sekunden")]
+    #[description_localized("es-ES", "Duración del sorteo en segundos")]
+    duration: Option<u64>,
+    #[description = "Text of the post, uses the same placeholders as the giveaway_post template"]
+    #[description_localized(
+        "de",
+        "Text des Posts, mit den Platzhaltern der Vorlage giveaway_post"
+    )]
+    #[description_localized(
+        "es-ES",
+        "Texto de la publicación, con los marcadores de la plantilla giveaway_post"
+    )]
+    message: Option<String>,
+    #[description = "Title of the embed, defaults to the embed_title setting"]
+    #[description_localized("de", "Titel des Embeds")]
+    #[description_localized("es-ES", "Título del embed")]
+    title: Option<String>,
+    #[description = "Text of the embed, defaults to the embed_description setting"]
+    #[description_localized("de", "Text des Embeds")]
+    #[description_localized("es-ES", "Texto del embed")]
+    description: Option<String>,
+    #[description = "Image URL of the embed or `none`, defaults to the embed_image setting"]
+    #[description_localized("de", "Bild-URL des Embeds oder `none`")]
+    #[description_localized("es-ES", "URL de la imagen del embed o `none`")]
+    image: Option<String>,
+    #[description = "Uploaded image to use in the embed instead of an image URL"]
+    #[description_localized("de", "Hochgeladenes Bild statt einer Bild-URL")]
+    #[description_localized("es-ES", "Imagen subida en lugar de una URL")]
+    image_upload: Option<serenity::Attachment>,
+    #[description = "Thumbnail URL of the embed or `none`, defaults to the embed_thumbnail setting"]
+    #[description_localized("de", "Vorschaubild-URL des Embeds oder `none`")]
+    #[description_localized("es-ES", "URL de la miniatura del embed o `none`")]
+    thumbnail: Option<String>,
+    #[description = "Hex color of the embed, defaults to the embed_color setting"]
+    #[description_localized("de", "Farbe des Embeds als Hex-Code")]
+    #[description_localized("es-ES", "Color del embed en hexadecimal")]
+    color: Option<String>,
+    #[description = "Footer text of the embed, defaults to the embed_footer setting"]
+    #[description_localized("de", "Fußzeile des Embeds")]
+    #[description_localized("es-ES", "Pie del embed")]
+    footer: Option<String>,
+    #[description = "Pool to take the keys from, defaults to the default pool"]
+    #[description_localized("de", "Pool, aus dem die Schlüssel kommen")]
+    #[description_localized("es-ES", "Grupo del que se toman las claves")]
+    #[autocomplete = "autocomplete_pool"]
+    pool: Option<String>,
+    #[description = "Keys the post hands out at most, defaults to the giveaway_key_cap setting"]
+    #[description_localized("de", "Schlüssel, die der Post höchstens vergibt")]
+    #[description_localized("es-ES", "Claves que la publicación reparte como máximo")]
+    cap: Option<u32>,
+) -> Result<(), Error> {
+    let key_pool = pool.unwrap_or_else(|| DEFAULT_POOL.to_owned());
+
+    // the post is public, so it uses the server's language rather than the admin's
+    let language = guild_language(ctx).await;
+    // replies only the admin sees are in their own language
+    let admin_language = user_language(ctx).await;
+
+    let options = [
+        ("embed_title", title),
+        ("embed_description", description),
+        ("embed_image", image_upload.map(|a| a.url).or(image)),
+        ("embed_thumbnail", thumbnail),
+        ("embed_color", color),
+        ("embed_footer", footer),
+    ];
+    let mut embed_values = {
+        let conf = ctx.data().config.lock().await;
+        let mut checked = Vec::new();
+        for (key, option) in options {
+            match option.map(|o| settings::validate(key, &o)).transpose() {
+                Ok(option) => checked.push((key, option)),
+                Err(e) => {
+                    let content = Template::InvalidValue.render(
+                        &conf,
+                        admin_language,
+                        &[("key", key.to_owned()), ("reason", e.to_string())],
+                    );
+                    ctx.say(content).await?;
+                    return Ok(());
+                }
+            }
+        }
+
+        embed_values(&conf, &key_pool, checked)
+    };
+    let giveaway_embed = GiveawayEmbed {
+        title: embed_values.remove("embed_title"),
+        description: embed_values.remove("embed_description"),
+        image: embed_values.remove("embed_image"),
+        thumbnail: embed_values.remove("embed_thumbnail"),
+        color: embed_values
+            .remove("embed_color")
+            .and_then(|c| settings::parse_color(&c).ok()),
+        footer: embed_values.remove("embed_footer"),
+        ends_label: ctx
+            .data()
+            .render_in_pool(Template::EmbedEnds, language, &key_pool, &[])
+            .await,
+        ended_label: ctx
+            .data()
+            .render_in_pool(Template::EmbedEnded, language, &key_pool, &[])
+            .await,
+        keys_left_label: ctx
+            .data()
+            .render_in_pool(Template::EmbedKeysLeft, language, &key_pool, &[])
+            .await,
+    };
+
+    let data_map = ctx.data().config.lock().await;
+    let role = data_map.get("role_id").cloned();
+
+    let role = if let Some(role) = role {
+        role
+    } else {
+        let content = Template::NoRoleSet.render(&data_map, admin_language, &[]);
+        ctx.say(content).await?;
+        return Ok(());
+    };
+
+    // the command line argument overrides the config value
+    let default_duration = ctx.data().args.giveaway_duration.unwrap_or_else(|| {
+        data_map
+            .get("giveaway_duration")
+            .and_then(|d| d.parse::<u64>().ok())
+            .unwrap_or(3600)
+    });
+    // 0 means the post hands out keys until the pool is empty
+    let cap = cap.unwrap_or_else(|| {
+        data_map
+            .get("giveaway_key_cap")
+            .and_then(|c| c.parse::<u32>().ok())
+            .unwrap_or(0)
+    });
+    // don't hold the lock for the whole giveaway, so the config can be changed while it runs
+    drop(data_map);
+
+    let message = match message.map(|m| Template::GiveawayPost.validate(&m)) {
+        Some(Ok(message)) => Some(message),
+        Some(Err(e)) => {
+            let content = ctx
+                .data()
+                .render(
+                    Template::InvalidValue,
+                    admin_language,
+                    &[("key", "message".to_owned()), ("reason", e.to_string())],
+                )
+                .await;
+            ctx.say(content).await?;
+            return Ok(());
+        }
+        None => None,
+    };
+
+    let duration = duration.unwrap_or(default_duration);
+    let ends_at = chrono::Utc::now() + chrono::Duration::seconds(duration as i64);
+    let round = ctx
+        .data()
+        .db
+        .get_round()
+        .await?
+        .map(|r| r.to_string())
+        .unwrap_or_default();
+
+    // the keys the post can still hand out, with a cap that's fewer than the pool has left
+    let mut given = 0;
+    let keys_left = |remaining: i32, given: u32| match cap {
+        0 => remaining,
+        cap => remaining.min(cap.saturating_sub(given) as i32),
+    };
+
+    let remaining = ctx.data().db.remaining_unclaimed(&key_pool).await?;
+    let values = [
+        ("role", format!("<@&{}>", role)),
+        ("round", round.clone()),
+        ("remaining", keys_left(remaining, given).to_string()),
+        ("ends_at", format!("<t:{}:R>", ends_at.timestamp())),
+    ];
+    let content = {
+        let conf = ctx.data().config.lock().await;
+        match &message {
+            Some(message) => {
+                let mut custom = conf.clone();
+                custom.insert(
+                    Template::GiveawayPost.config_key(Scope::Pool(&key_pool)),
+                    message.clone(),
+                );
+                Template::GiveawayPost.render_in_pool(&custom, language, &key_pool, &values)
+            }
+            None => Template::GiveawayPost.render_in_pool(&conf, language, &key_pool, &values),
+        }
+    };
+
+    let reply = {
+        let embed = giveaway_embed.build(ends_at, keys_left(remaining, given), false);
+
+        let components = vec![serenity::CreateActionRow::Buttons(vec![
+            serenity::CreateButton::new("get_key_comp")
+                .label(
+                    ctx.data()
+                        .render_in_pool(Template::ButtonGetKey, language, &key_pool, &[])
+                        .await,
+                )
+                .style(serenity::ButtonStyle::Primary),
+            serenity::CreateButton::new("resend_key_comp")
+                .label(
+                    ctx.data()
+                        .render_in_pool(Template::ButtonResendKey, language, &key_pool, &[])
+                        .await,
+                )
+                .style(serenity::ButtonStyle::Secondary),
+        ])];
+
+        poise::CreateReply::default()
+            .content(content)
+            .embed(embed)
+            .components(components)
+    };
+
+    let res = ctx.send(reply).await?;
+    // edit the message itself rather than the interaction response, the interaction token expires
+    // after 15 minutes which is often shorter than the giveaway
+    let mut post = res.message().await?.into_owned();
+
+    if cap > 0 && remaining < cap as i32 {
+        let content = ctx
+            .data()
+            .render(
+                Template::GiveawayCapWarning,
+                admin_language,
+                &[
+                    ("remaining", remaining.to_string()),
+                    ("pool", key_pool.clone()),
+                    ("cap", cap.to_string()),
+                ],
+            )
+            .await;
+        ctx.send(
+            poise::CreateReply::default()
+                .content(content)
+                .ephemeral(true),
+        )
+        .await?;
+    }
+    ctx.data().check_stock(ctx.http(), &key_pool).await;
+
+    // set when the bot stops before the giveaway is over
+    let mut paused = false;
+
+    loop {
+        // clicks don't extend the giveaway, it ends at the time shown in the post
+        let timeout = match (ends_at - chrono::Utc::now()).to_std() {
+            Ok(timeout) if !timeout.is_zero() => timeout,
+            _ => break,
+        };
+        let collector = serenity::ComponentInteractionCollector::new(ctx)
+            .message_id(post.id)
+            .timeout(timeout)
+            .filter(move |mci| {
+                mci.data.custom_id == "get_key_comp" || mci.data.custom_id == "resend_key_comp"
+            });
+
+        // a click that is being handled is always finished, the bot only stops between clicks
+        let mci = tokio::select! {
+            mci = collector.next() => mci,
+            _ = ctx.data().shutdown.requested() => {
+                paused = true;
+                None
+            }
+        };
+        let Some(mci) = mci else {
+            break;
+        };
+
+        let span = info_span!(
+            "click",
+            giveaway_id = %post.id,
+            user_id = %mci.user.id,
+            guild_id = mci.guild_id.map(|g| g.get()),
+            button = mci.data.custom_id.as_str(),
+            pool = key_pool.as_str(),
+            round = round.as_str(),
+        );
+
+        let handled = async {
+            if mci.data.custom_id == "resend_key_comp" {
+                let clicker_language = ctx
+                    .data()
+                    .language(Some(&mci.locale), mci.guild_locale.as_deref())
+                    .await;
+                let content =
+                    resend_keys(ctx, &mci.user, clicker_language, "the giveaway post").await?;
+
+                mci.create_response(
+                    ctx,
+                    serenity::CreateInteractionResponse::Message(
+                        serenity::CreateInteractionResponseMessage::new()
+                            .content(content)
+                            .ephemeral(true),
+                    ),
+                )
+                .await?;
+                METRICS.interaction_handled(mci.id.created_at());
+
+                return Ok(());
+            }
+
+            // check if interaction uer has permission to claim a key
+            let has_role = mci
+                .user
+                .has_role(
+                    ctx,
+                    ctx.guild_id().expect("Could not get the guildID"),
+                    serenity::RoleId::from_str(&role).expect("Could not parse role id"),
+                )
+                .await?;
+            let claimant = Claimant {
+                name: mci.user.name.clone(),
+                mention: mci.user.mention().to_string(),
+                created_at: *mci.user.created_at(),
+                has_role,
+                roles: mci
+                    .member
+                    .as_ref()
+                    .map(|m| m.roles.clone())
+                    .unwrap_or_default(),
+            };
+            // replies to a click are in the language of the user who clicked
+            let clicker_language = ctx
+                .data()
+                .language(Some(&mci.locale), mci.guild_locale.as_deref())
+                .await;
+
+            let giveaway = ctx.data().giveaway(ctx.http(), &key_pool);
+            let outcome = giveaway.claim(&claimant).await;
+
+            // the DM failed, so the user is told in a reply only they can see instead
+            let mut notice = None;
+
+            if let Outcome::Claimed { key } = &outcome {
+                given += 1;
+
+                // the key is claimed either way, a closed DM shouldn't end the giveaway for everyone
+                // else, the failure is in the audit log
+                if let Err(e) = send_key(ctx, &mci.user, key, &key_pool, clicker_language).await {
+                    error!("Could not send key to {}: {e}", mci.user.name);
+                    notice = Some(
+                        giveaway
+                            .render(
+                                e.template(),
+                                clicker_language,
+                                &[("user", claimant.mention.clone())],
+                            )
+                            .await,
+                    );
+                }
+
+                let remaining = ctx.data().db.remaining_unclaimed(&key_pool).await?;
+                post.edit(
+                    ctx,
+                    serenity::EditMessage::new().embed(giveaway_embed.build(
+                        ends_at,
+                        keys_left(remaining, given),
+                        false,
+                    )),
+                )
+                .await?;
+            } else if let Some(content) = giveaway
+                .rejection(&outcome, &claimant, &role, clicker_language)
+                .await
+            {
+                // with DMs closed the user still learns why, in a reply only they can see
+                if let Err(e) = mci
+                    .user
+                    .direct_message(ctx, serenity::CreateMessage::new().content(content.clone()))
+                    .await
+                {
+                    warn!("Could not send the rejection to {}: {e}", mci.user.name);
+                    notice = Some(content);
+                }
+            }
+
+            let response = match notice {
+                Some(notice) => serenity::CreateInteractionResponse::Message(
+                    serenity::CreateInteractionResponseMessage::new()
+                        .content(notice)
+                        .ephemeral(true),
+                ),
+                None => serenity::CreateInteractionResponse::Acknowledge,
+            };
+            mci.create_response(ctx, response).await?;
+            METRICS.interaction_handled(mci.id.created_at());
+
+            Ok::<_, Error>(())
+        }
+        .instrument(span)
+        .await;
+
+        // one failed click shouldn't end the giveaway for everyone else
+        if let Err(e) = handled {
+            error!("Could not handle the click of {}: {e}", mci.user.name);
+        }
+
+        if cap > 0 && given >= cap {
+            break;
+        }
+    }
+
+    let remaining = ctx.data().db.remaining_unclaimed(&key_pool).await?;
+    let (template, ends_at) = if paused {
+        info!("Giveaway paused, the bot is stopping");
+        (Template::GiveawayPaused, chrono::Utc::now())
+    } else {
+        (Template::GiveawayOver, ends_at)
+    };
+    let content = ctx
+        .data()
+        .render_in_pool(
+            template,
+            language,
+            &key_pool,
+            &[("round", round), ("remaining", remaining.to_string())],
+        )
+        .await;
+
+    post.edit(
+        ctx,
+        serenity::EditMessage::new()
+            .content(content)
+            .embed(giveaway_embed.build(ends_at, keys_left(remaining, given), true))
+            .components(vec![]),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn image(config: &HashMap<String, String>, option: Option<&str>) -> Option<String> {
+        embed_values(
+            config,
+            "vip",
+            vec![("embed_image", option.map(str::to_owned))],
+        )
+        .remove("embed_image")
+    }
+
+    #[test]
+    fn an_image_set_to_none_is_left_out() {
+        let default = Some(DEFAULT_EMBED_IMAGE.to_owned());
+        let url = "https://example.com/a.png";
+
+        assert_eq!(image(&config(&[]), None), default);
+        assert_eq!(image(&config(&[]), Some("none")), None);
+        assert_eq!(image(&config(&[("embed_image", "none")]), None), None);
+        assert_eq!(image(&config(&[("embed_image_vip", "none")]), None), None);
+        assert_eq!(
+            image(&config(&[("embed_image", "none")]), Some(url)),
+            Some(url.to_owned())
+        );
+    }
+
+    #[test]
+    fn pool_embed_settings_override_the_global_ones() {
+        let conf = config(&[
+            ("embed_title", "Giveaway"),
+            ("embed_title_vip", "VIP giveaway"),
+            ("embed_footer", "Good luck"),
+        ]);
+        let options = |title: Option<&str>| {
+            vec![
+                ("embed_title", title.map(str::to_owned)),
+                ("embed_footer", None),
+            ]
+        };
+
+        let values = embed_values(&conf, "vip", options(None));
+        assert_eq!(values["embed_title"], "VIP giveaway");
+        assert_eq!(values["embed_footer"], "Good luck");
+
+        let values = embed_values(&conf, "default", options(None));
+        assert_eq!(values["embed_title"], "Giveaway");
+
+        let values = embed_values(&conf, "vip", options(Some("Today only")));
+        assert_eq!(values["embed_title"], "Today only");
+    }
+}
@@ -1,284 +1,1028 @@
-use std::collections::HashMap;
+mod postgres;
+mod sqlite;
 
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use color_eyre::eyre::Result;
-use sqlx::{
-    migrate::MigrateDatabase,
-    sqlite::{Sqlite, SqlitePoolOptions},
-    Pool,
-};
 use tokio::io::AsyncBufReadExt;
 use tracing::debug;
 
-pub async fn connect_or_create(database_name: &str) -> Result<Pool<Sqlite>> {
-    Sqlite::database_exists(&database_name).await?;
+use crate::{error::ClaimError, limits::Limits};
 
-    if !Sqlite::database_exists(&database_name).await? {
-        Sqlite::create_database(&database_name).await?;
-    }
+pub use self::{postgres::PostgresStore, sqlite::SqliteStore};
 
-    let pool = SqlitePoolOptions::new()
-        .max_connections(4)
-        .connect(&database_name)
-        .await?;
+/// Pool keys are put in when the keys file doesn't name one
+pub const DEFAULT_POOL: &str = "default";
 
-    Ok(pool)
+/// Whether `name` can be the pool in front of a key in the keys file, letters, digits, `_` and `-`
+fn is_pool_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
 }
 
-pub async fn add_tables(pool: &Pool<Sqlite>) -> Result<()> {
-    sqlx::query!(
-        r#"
-CREATE TABLE IF NOT EXISTS keys (
-    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
-    key_val VARCHAR(255) NOT NULL,
-    claimed BOOLEAN DEFAULT FALSE NOT NULL,
-    user_claim VARCHAR(255),
-    claimed_at DATE,
-    added_at DATE DEFAULT (datetime('now', 'localtime')),
-    claim_round INTEGER,
-    UNIQUE (key_val),
-    FOREIGN KEY (user_claim) references users (id),
-    FOREIGN KEY (claim_round) REFERENCES giveaway_rounds (round_id)
-);"#
-    )
-    .execute(pool)
-    .await?;
-
-    sqlx::query!(
-        r#"
-CREATE TABLE IF NOT EXISTS config (
-    key VARCHAR(255) PRIMARY KEY NOT NULL,
-    value VARCHAR(255) NOT NULL
-);"#
-    )
-    .execute(pool)
-    .await?;
-
-    sqlx::query!(
-        r#"
-CREATE TABLE IF NOT EXISTS giveaway_rounds (
-    round_id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
-    status VARCHAR(255) NOT NULL -- e.g., 'active', 'completed'
-);"#
-    )
-    .execute(pool)
-    .await?;
-
-    sqlx::query!(
-        r#"
-CREATE TABLE IF NOT EXISTS users (
-    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
-    username VARCHAR(255) NOT NULL,
-    UNIQUE (username)
-);"#
-    )
-    .execute(pool)
-    .await?;
-
-    Ok(())
-}
+/// The database the bot runs on, see [`connect`]
+pub type Db = Arc<dyn Store>;
 
-pub async fn remaining_unclaimed(pool: &Pool<Sqlite>) -> Result<i32> {
-    let key = sqlx::query!(
-        r#"
-SELECT COUNT(*) AS unclaimed_keys_count
-FROM keys
-WHERE claimed = FALSE;"#
-    )
-    .fetch_one(pool)
-    .await?;
-
-    Ok(key.unclaimed_keys_count)
+/// Connects to the database at `url`, it is created if it doesn't exist yet
+///
+/// `postgres://` and `postgresql://` URLs connect to Postgres, anything else is the name of a SQLite
+/// file or a `sqlite:` URL.
+pub async fn connect(url: &str) -> Result<Db> {
+    if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+        Ok(Arc::new(PostgresStore::connect(url).await?))
+    } else {
+        Ok(Arc::new(SqliteStore::connect_or_create(url).await?))
+    }
 }
 
-pub async fn give_key_unchecked(pool: &Pool<Sqlite>, user: &str) -> Result<String> {
-    let mut transaction = pool.begin().await?;
-
-    // add user to user table if they don't exist
-    sqlx::query!(
-        r#"
-        INSERT OR IGNORE INTO users (username) VALUES (?);
-        "#,
-        user
-    )
-    .execute(&mut *transaction)
-    .await?;
-    let key = sqlx::query!(
-        r#"
-        SELECT key_val FROM keys WHERE claimed = FALSE LIMIT 1;
-        "#
-    )
-    .fetch_one(&mut *transaction)
-    .await?;
-
-    sqlx::query!(
-        r#"
-UPDATE keys SET claimed = TRUE, user_claim = (select id from users where username = ?), claimed_at = datetime('now', 'localtime'), claim_round = (select round_id from giveaway_rounds where status = 'active') WHERE key_val = ?;
-        "#,
-        user,
-        key.key_val
-    )
-    .execute(&mut *transaction)
-    .await?;
-
-    transaction.commit().await?;
-
-    Ok(key.key_val)
-}
+/// Everything the bot keeps in its database, implemented for SQLite and Postgres
+///
+/// Both run the same migrations, `migrations/postgres` holds the Postgres version of every migration
+/// in `migrations` under the same version. Timestamps are returned as `DateTime<Utc>`, SQLite stores
+/// them to the millisecond and Postgres to the microsecond.
+#[async_trait]
+pub trait Store: Send + Sync {
+    /// Runs every migration that hasn't been applied yet, the first migration only uses
+    /// `IF NOT EXISTS` so databases created before migrations were tracked are picked up as well
+    async fn add_tables(&self) -> Result<()>;
 
-// claims a key for a user and returns the key and marks the key as claimed
-pub async fn claim_key_with_user(pool: &Pool<Sqlite>, user: &str) -> Result<String> {
-    // add user to user table if they don't exist
-    sqlx::query!(
-        r#"
-        INSERT OR IGNORE INTO users (username) VALUES (?);
-        "#,
-        user
-    )
-    .execute(pool)
-    .await?;
-
-    let mut transaction = pool.begin().await?;
-
-    let key_maybe = sqlx::query!(
-        r#"
-SELECT k.key_val
-FROM keys k
-WHERE k.claimed = FALSE
-AND NOT EXISTS (
-    SELECT 1
-    FROM keys k2
-    INNER JOIN users u ON k2.user_claim = u.id
-    INNER JOIN giveaway_rounds gr ON k2.claim_round = gr.round_id
-    WHERE u.username = ?
-    AND k2.claimed = TRUE
-    AND gr.status = 'active'
-)
-LIMIT 1;"#,
-        user
-    )
-    .fetch_optional(&mut *transaction)
-    .await?;
-
-    let Some(key) = key_maybe else {
-        if remaining_unclaimed(pool).await? > 0 {
-            return Err(color_eyre::eyre::eyre!(
-                "You have already claimed a key for this round."
-            ));
-        } else {
-            return Err(color_eyre::eyre::eyre!("No keys available"));
-        };
-    };
+    /// How many migrations have not been applied to the database yet
+    async fn pending_migrations(&self) -> Result<usize>;
 
-    sqlx::query!(
-        r#"
-UPDATE keys SET claimed = TRUE, user_claim = (select id from users where username = ?), claimed_at = datetime('now', 'localtime'), claim_round = (select round_id from giveaway_rounds where status = 'active') WHERE key_val = ?;
-        "#,
-        user,
-        key.key_val
-    )
-    .execute(&mut *transaction)
-    .await?;
+    /// Whether the database has no tables at all, i.e. it was just created
+    async fn is_empty(&self) -> Result<bool>;
 
-    transaction.commit().await?;
+    /// Fails if the database can't be reached
+    async fn ping(&self) -> Result<()>;
 
-    Ok(key.key_val)
-}
+    /// Writes everything out and closes every connection, waits for queries that are still running
+    async fn close(&self) -> Result<()>;
+
+    /// The SQLite database behind this store, `None` for Postgres, backups and simulations need it
+    fn as_sqlite(&self) -> Option<&SqliteStore> {
+        None
+    }
+
+    /// Adds round `round` as planned unless it already exists
+    async fn add_round(&self, round: i64) -> Result<()>;
+
+    /// Adds a key to `key_pool`, returns false if the key was already known
+    async fn add_key(&self, key: &str, key_pool: &str) -> Result<bool>;
+
+    async fn remaining_unclaimed(&self, key_pool: &str) -> Result<i32>;
+
+    /// Gives a user a key without checking their claim limit, the claim is recorded as waived with `reason`
+    async fn give_key_unchecked(
+        &self,
+        user: &str,
+        key_pool: &str,
+        reason: &str,
+    ) -> Result<String, ClaimError>;
+
+    /// Claims a key from `key_pool` for a user and returns the key and marks the key as claimed, fails if
+    /// the user reached one of `limits` for the pool
+    ///
+    /// The limits are checked by the statement that claims the key, so claims that race each other
+    /// can't both slip past one of them.
+    async fn claim_key_with_user(
+        &self,
+        user: &str,
+        key_pool: &str,
+        limits: &Limits,
+    ) -> Result<String, ClaimError>;
+
+    async fn get_config_val(&self, key: &str) -> Result<String>;
+
+    /// Every value set with `/config set`, as key and value
+    async fn config_vals(&self) -> Result<Vec<(String, String)>>;
+
+    /// Adds a planned round, it has to be opened before keys can be claimed in it
+    async fn create_round(&self, name: &str, notes: Option<&str>) -> Result<i64>;
+
+    /// Makes `round` the active round and closes the one that was active before, which is returned
+    ///
+    /// a closed round is only opened again with `force`, otherwise claims from two rounds would mix
+    async fn open_round(&self, round: i64, force: bool) -> Result<Option<i64>>;
+
+    /// Closes `round`, planned rounds can be closed as well so they are never opened
+    async fn close_round(&self, round: i64) -> Result<()>;
+
+    async fn list_rounds(&self) -> Result<Vec<Round>>;
+
+    async fn round_info(&self, round: i64) -> Result<Option<Round>>;
+
+    /// How many keys were claimed in a round and by how many users, claims of keys that were
+    /// revoked later included
+    async fn round_claim_counts(&self, round: i64) -> Result<(i32, i32)>;
+
+    async fn get_round(&self) -> Result<Option<i64>>;
+
+    async fn set_config_val(&self, key: &str, value: &str) -> Result<()>;
 
-pub async fn get_config_val(pool: &Pool<Sqlite>, key: &str) -> Result<String> {
-    let val = sqlx::query!(
-        r#"
-        SELECT value FROM config WHERE key = ?;
-        "#,
-        key
-    )
-    .fetch_one(pool)
-    .await?;
-
-    Ok(val.value)
+    async fn delete_config_val(&self, key: &str) -> Result<()>;
+
+    /// The claims of a user in `key_pool` the limits count, claims of revoked keys are left out
+    async fn claim_stats(&self, user: &str, key_pool: &str) -> Result<ClaimStats>;
+
+    /// Takes the summary of `round` and stores it, a report that already exists is replaced
+    ///
+    /// failed deliveries are the failure events of the round that have a key, i.e. keys that were claimed
+    /// but could not be sent
+    async fn save_round_report(&self, round: i64) -> Result<RoundReport>;
+
+    async fn round_report(&self, round: i64) -> Result<Option<RoundReport>>;
+
+    /// Every pool that has ever had a key in it
+    async fn pools(&self) -> Result<Vec<String>>;
+
+    async fn pool_stats(&self) -> Result<Vec<PoolStats>>;
+
+    /// The keys a user has claimed in the active round, revoked keys are left out
+    async fn round_keys(&self, user: &str) -> Result<Vec<String>>;
+
+    /// How many events of `kind` about `target` were logged in the last `secs` seconds
+    async fn recent_events(&self, kind: &str, target: &str, secs: u64) -> Result<i32>;
+
+    /// Looks up a single key, returns None if the key is unknown
+    async fn key_info(&self, key: &str) -> Result<Option<KeyInfo>>;
+
+    /// Every key, optionally only the keys of one pool, in the order they were added
+    async fn all_keys(&self, key_pool: Option<&str>) -> Result<Vec<KeyInfo>>;
+
+    /// Every key a user has received in any round, oldest first, this includes unchecked and revoked keys
+    async fn user_keys(&self, user: &str) -> Result<Vec<KeyInfo>>;
+
+    /// Marks a key as revoked so it is never handed out again, returns the user that had claimed it
+    ///
+    /// The claim is flagged as revoked rather than deleted, so it no longer counts towards the user's
+    /// limits but still counts in the round reports.
+    async fn revoke_key(&self, key: &str) -> Result<Option<String>>;
+
+    /// Remembers that `key_pool` ran low, returns false if it was already marked since its last refill
+    async fn mark_low_stock(&self, key_pool: &str, remaining: i32) -> Result<bool>;
+
+    /// Forgets that `key_pool` ran low, so the next time it does an alert is sent again
+    async fn clear_low_stock(&self, key_pool: &str) -> Result<()>;
+
+    /// Appends an event to the audit log, `round` defaults to the active round
+    async fn insert_event(
+        &self,
+        kind: &str,
+        actor: Option<&str>,
+        target: Option<&str>,
+        key: Option<&str>,
+        round: Option<i64>,
+        details: Option<&str>,
+    ) -> Result<i64>;
+
+    /// Runs a statement as it is and returns how many rows it changed, for tests that have to get
+    /// past the methods above
+    #[cfg(test)]
+    async fn execute(&self, sql: &str) -> Result<u64>;
 }
 
+// opens `round`, creating it if it doesn't exist yet, the active round is closed
 pub async fn set_round_db(
-    pool: &Pool<Sqlite>,
+    db: &dyn Store,
     round: i64,
+    force: bool,
     config: &mut HashMap<String, String>,
-) -> Result<()> {
-    let mut transaction = pool.begin().await?;
-
-    sqlx::query!(
-        r#"
-        UPDATE giveaway_rounds SET status = 'completed' WHERE status = 'active';
-        "#
-    )
-    .execute(&mut *transaction)
-    .await?;
-
-    sqlx::query!(
-        r#"
-        INSERT OR REPLACE INTO giveaway_rounds (round_id, status) VALUES (?,'active');
-        "#,
-        round
-    )
-    .execute(&mut *transaction)
-    .await?;
-
-    if let Ok(_) = transaction.commit().await {
-        config.insert("claim_round".to_owned(), round.to_string());
-    } else {
-        return Err(color_eyre::eyre::eyre!("Failed to commit transaction"));
-    };
-
-    Ok(())
-}
+) -> Result<Option<i64>> {
+    db.add_round(round).await?;
 
-pub async fn get_round(pool: &Pool<Sqlite>) -> Result<Option<i64>> {
-    let round = sqlx::query!(
-        r#"
-        SELECT round_id FROM giveaway_rounds WHERE status = 'active';
-        "#
-    )
-    .fetch_optional(pool)
-    .await?;
-
-    return match round {
-        Some(round) => Ok(Some(round.round_id)),
-        None => Ok(None),
-    };
-}
+    let previous = db.open_round(round, force).await?;
+    config.insert("claim_round".to_owned(), round.to_string());
 
-pub async fn set_config_val(pool: &Pool<Sqlite>, key: &str, value: &str) -> Result<()> {
-    sqlx::query!(
-        r#"
-        INSERT OR REPLACE INTO config (key, value) VALUES (?, ?);
-        "#,
-        key,
-        value
-    )
-    .execute(pool)
-    .await?;
-
-    Ok(())
+    Ok(previous)
 }
 
-// read beta keys from a file and insert them into the database
-pub async fn read_beta_keys_file(pool: &Pool<Sqlite>, file: &str) -> Result<()> {
+/// Reads the keys in `file` into the database and returns how many of them were new, lines without a
+/// pool go into `default_pool`
+///
+/// A line is either just the key or `pool:key`. Only a pool name in front of the first `:` counts as
+/// a pool, see `is_pool_name`, so a key like `AB CD:EF` is read whole. A key whose text before its
+/// first `:` looks like a pool name has to be written with its pool, e.g. `default:AB:CD`.
+pub async fn read_beta_keys_file(db: &dyn Store, file: &str, default_pool: &str) -> Result<u64> {
     let file = tokio::fs::File::open(file).await?;
     let reader = tokio::io::BufReader::new(file);
 
     let mut lines = reader.lines();
+    let mut added = 0;
 
     while let Some(line) = lines.next_line().await? {
-        sqlx::query!(
-            r#"
-        INSERT OR IGNORE INTO keys (key_val) VALUES (?);
-        "#,
-            line
-        )
-        .execute(pool)
-        .await?;
+        let (key_pool, key) = match line.split_once(':') {
+            Some((key_pool, key)) if is_pool_name(key_pool.trim()) => (key_pool.trim(), key.trim()),
+            _ => (default_pool, line.trim()),
+        };
+        if key.is_empty() {
+            continue;
+        }
+
+        if db.add_key(key, key_pool).await? {
+            added += 1;
+        }
     }
     debug!("Done inserting keys into database");
-    // let contents = tokio::fs::read_to_string(file).await?;
-    // let s = contents.lines().map(String::from).into_iter();
 
-    Ok(())
+    Ok(added)
+}
+
+/// A giveaway round
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Round {
+    pub round_id: i64,
+    pub name: Option<String>,
+    /// `planned`, `active` or `closed`
+    pub status: String,
+    pub starts_at: Option<DateTime<Utc>>,
+    pub ends_at: Option<DateTime<Utc>>,
+    pub notes: Option<String>,
+}
+
+/// A key together with who claimed it and whether it reached them
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct KeyInfo {
+    pub key_val: String,
+    pub pool: String,
+    pub username: Option<String>,
+    pub round: Option<i64>,
+    pub added_at: Option<DateTime<Utc>>,
+    pub claimed_at: Option<DateTime<Utc>>,
+    pub revoked: bool,
+    /// `delivery` or `failure`, whichever was logged last for this key
+    pub last_delivery: Option<String>,
+    pub last_delivery_at: Option<DateTime<Utc>>,
+}
+
+/// How many keys a user has claimed from a pool, revoked keys don't count
+#[derive(Debug, Clone, Default, sqlx::FromRow)]
+pub struct ClaimStats {
+    /// Keys claimed in the active round
+    pub round: i32,
+    /// Keys claimed in every round
+    pub lifetime: i32,
+    pub last_claimed_at: Option<DateTime<Utc>>,
+}
+
+/// The summary of a closed round, see [`Store::save_round_report`]
+#[derive(Debug, Clone)]
+pub struct RoundReport {
+    pub round_id: i64,
+    pub keys_claimed: i32,
+    pub claimants: i32,
+    pub failed_deliveries: i32,
+    pub created_at: DateTime<Utc>,
+    pub pools: Vec<PoolReport>,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct PoolReport {
+    pub pool: String,
+    pub keys_claimed: i32,
+    /// Unclaimed keys left when the round closed
+    pub remaining: i32,
+    /// Seconds from the start of the round to the claim that took the last key
+    pub exhausted_after: Option<i64>,
+}
+
+/// How many keys of a pool are in each state
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct PoolStats {
+    pub pool: String,
+    pub total: i32,
+    pub claimed: i32,
+    pub revoked: i32,
+    pub remaining: i32,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, str::FromStr};
+
+    use sqlx::postgres::PgConnectOptions;
+
+    use super::*;
+
+    // every test of the shared suite gets its own in-memory SQLite database, shared between the
+    // connections of its pool
+    async fn sqlite_store(name: &str) -> Db {
+        let url = format!("sqlite:file:{name}?mode=memory&cache=shared");
+
+        Arc::new(SqliteStore::connect_or_create(&url).await.unwrap())
+    }
+
+    // and its own schema in the Postgres database `KEYBOT_TEST_POSTGRES` points to, the Postgres
+    // half of the suite is ignored unless it is run with `--ignored`
+    async fn postgres_store(name: &str) -> Db {
+        let url = std::env::var("KEYBOT_TEST_POSTGRES")
+            .expect("KEYBOT_TEST_POSTGRES has to point at a Postgres database");
+        let options = PgConnectOptions::from_str(&url).unwrap();
+        let schema = format!("test_{name}");
+
+        let admin = PostgresStore::connect_with(options.clone()).await.unwrap();
+        admin
+            .execute(&format!("DROP SCHEMA IF EXISTS {schema} CASCADE"))
+            .await
+            .unwrap();
+        admin
+            .execute(&format!("CREATE SCHEMA {schema}"))
+            .await
+            .unwrap();
+        admin.close().await.unwrap();
+
+        let options = options.options([("search_path", &schema)]);
+        Arc::new(PostgresStore::connect_with(options).await.unwrap())
+    }
+
+    // migrates the database and opens round 1
+    async fn prepare(db: Db) -> Db {
+        db.add_tables().await.unwrap();
+        set_round_db(&*db, 1, false, &mut HashMap::new())
+            .await
+            .unwrap();
+
+        db
+    }
+
+    // runs each test against SQLite and against Postgres
+    macro_rules! shared_tests {
+        ($($name:ident),* $(,)?) => {
+            mod sqlite {
+                $(
+                    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+                    async fn $name() {
+                        let db = super::sqlite_store(stringify!($name)).await;
+                        super::$name(super::prepare(db).await).await;
+                    }
+                )*
+            }
+
+            mod postgres {
+                $(
+                    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+                    #[ignore = "needs KEYBOT_TEST_POSTGRES"]
+                    async fn $name() {
+                        let db = super::postgres_store(stringify!($name)).await;
+                        super::$name(super::prepare(db).await).await;
+                    }
+                )*
+            }
+        };
+    }
+
+    shared_tests!(
+        a_user_gets_one_key_per_round,
+        a_new_round_allows_a_new_claim,
+        switching_rounds_keeps_one_round_active,
+        remaining_keys_leave_out_claimed_revoked_and_other_pools,
+        reading_keys_skips_blank_lines_and_duplicates,
+        keys_without_a_pool_go_into_the_given_one,
+        keys_with_a_colon_only_split_at_a_pool_name,
+        reading_a_missing_keys_file_fails,
+        concurrent_claims_never_share_a_key,
+        concurrent_claims_by_one_user_respect_the_limit,
+        concurrent_claims_respect_the_lifetime_limit_and_cooldown,
+        concurrently_created_rounds_get_their_own_ids,
+        claims_table_rejects_a_second_claim_in_the_same_slot,
+        unchecked_grants_are_waived_with_a_reason,
+        revoking_a_key_frees_its_slot_but_keeps_the_claim,
+        claiming_from_an_empty_pool_says_no_keys_left,
+        closed_rounds_stay_closed_unless_forced,
+        claims_racing_a_closing_round_never_outlive_it,
+        round_reports_count_claims_and_remaining_keys,
+        low_stock_is_marked_once_per_crossing,
+        claims_are_timestamped_in_utc,
+    );
+
+    fn per_round(limit: u32) -> Limits {
+        Limits {
+            per_round: limit,
+            ..Limits::default()
+        }
+    }
+
+    async fn add_keys(db: &dyn Store, count: usize) {
+        for i in 0..count {
+            db.add_key(&format!("KEY-{i}"), DEFAULT_POOL).await.unwrap();
+        }
+    }
+
+    // writes `contents` to a keys file of its own and returns its path
+    fn keys_file(name: &str, contents: &str) -> String {
+        let file = std::env::temp_dir().join(format!("keybot-{name}-{}.txt", std::process::id()));
+        std::fs::write(&file, contents).unwrap();
+
+        file.to_str().unwrap().to_owned()
+    }
+
+    async fn a_user_gets_one_key_per_round(db: Db) {
+        add_keys(&*db, 3).await;
+
+        let first = db
+            .claim_key_with_user("user", DEFAULT_POOL, &Limits::default())
+            .await
+            .unwrap();
+        assert!(matches!(
+            db.claim_key_with_user("user", DEFAULT_POOL, &Limits::default())
+                .await,
+            Err(ClaimError::AlreadyClaimed)
+        ));
+        let other = db
+            .claim_key_with_user("other", DEFAULT_POOL, &Limits::default())
+            .await
+            .unwrap();
+
+        assert_ne!(first, other);
+        assert_eq!(db.remaining_unclaimed(DEFAULT_POOL).await.unwrap(), 1);
+    }
+
+    async fn a_new_round_allows_a_new_claim(db: Db) {
+        add_keys(&*db, 2).await;
+        db.claim_key_with_user("user", DEFAULT_POOL, &Limits::default())
+            .await
+            .unwrap();
+
+        let mut config = HashMap::new();
+        assert_eq!(
+            set_round_db(&*db, 2, false, &mut config).await.unwrap(),
+            Some(1)
+        );
+        assert_eq!(db.get_round().await.unwrap(), Some(2));
+        assert_eq!(config.get("claim_round").map(String::as_str), Some("2"));
+
+        assert!(db
+            .claim_key_with_user("user", DEFAULT_POOL, &Limits::default())
+            .await
+            .is_ok());
+        assert_eq!(
+            db.claim_stats("user", DEFAULT_POOL).await.unwrap().lifetime,
+            2
+        );
+    }
+
+    async fn switching_rounds_keeps_one_round_active(db: Db) {
+        let mut config = HashMap::new();
+
+        // setting the active round again changes nothing
+        assert_eq!(
+            set_round_db(&*db, 1, false, &mut config).await.unwrap(),
+            Some(1)
+        );
+        set_round_db(&*db, 5, false, &mut config).await.unwrap();
+        assert!(set_round_db(&*db, 1, false, &mut config).await.is_err());
+        assert_eq!(db.get_round().await.unwrap(), Some(5));
+
+        let rounds = db.list_rounds().await.unwrap();
+        let active = rounds.iter().filter(|r| r.status == "active").count();
+        assert_eq!(active, 1);
+        assert_eq!(rounds.len(), 2);
+        assert!(rounds[0].ends_at.is_some());
+    }
+
+    async fn remaining_keys_leave_out_claimed_revoked_and_other_pools(db: Db) {
+        add_keys(&*db, 3).await;
+        db.add_key("VIP-1", "vip").await.unwrap();
+
+        db.claim_key_with_user("user", DEFAULT_POOL, &Limits::default())
+            .await
+            .unwrap();
+        db.revoke_key("KEY-2").await.unwrap();
+
+        assert_eq!(db.remaining_unclaimed(DEFAULT_POOL).await.unwrap(), 1);
+        assert_eq!(db.remaining_unclaimed("vip").await.unwrap(), 1);
+        assert_eq!(db.remaining_unclaimed("missing").await.unwrap(), 0);
+    }
+
+    async fn reading_keys_skips_blank_lines_and_duplicates(db: Db) {
+        // the file name has to differ between backends, their tests run side by side
+        let file = keys_file(
+            &format!("ingest-{}", db.as_sqlite().is_some()),
+            "KEY-A\n\n  KEY-B  \nKEY-A\nvip:VIP-A\nvip:\n\n",
+        );
+
+        assert_eq!(
+            read_beta_keys_file(&*db, &file, DEFAULT_POOL)
+                .await
+                .unwrap(),
+            3
+        );
+        // reading the same file again adds nothing
+        assert_eq!(
+            read_beta_keys_file(&*db, &file, DEFAULT_POOL)
+                .await
+                .unwrap(),
+            0
+        );
+        std::fs::remove_file(&file).unwrap();
+
+        assert_eq!(db.remaining_unclaimed(DEFAULT_POOL).await.unwrap(), 2);
+        assert_eq!(db.remaining_unclaimed("vip").await.unwrap(), 1);
+        assert_eq!(db.key_info("KEY-B").await.unwrap().unwrap().pool, "default");
+    }
+
+    async fn keys_without_a_pool_go_into_the_given_one(db: Db) {
+        let file = keys_file(
+            &format!("ingest-pool-{}", db.as_sqlite().is_some()),
+            "KEY-A\nvip:VIP-A\n",
+        );
+
+        read_beta_keys_file(&*db, &file, "beta").await.unwrap();
+        std::fs::remove_file(&file).unwrap();
+
+        assert_eq!(db.key_info("KEY-A").await.unwrap().unwrap().pool, "beta");
+        assert_eq!(db.key_info("VIP-A").await.unwrap().unwrap().pool, "vip");
+    }
+
+    async fn keys_with_a_colon_only_split_at_a_pool_name(db: Db) {
+        let file = keys_file(
+            &format!("ingest-colon-{}", db.as_sqlite().is_some()),
+            "AB CD:EF
+vip:GH:IJ
+default:KL:MN
+{OP}:QR
+",
+        );
+
+        assert_eq!(
+            read_beta_keys_file(&*db, &file, DEFAULT_POOL)
+                .await
+                .unwrap(),
+            4
+        );
+        std::fs::remove_file(&file).unwrap();
+
+        for (key, key_pool) in [
+            ("AB CD:EF", "default"),
+            ("GH:IJ", "vip"),
+            ("KL:MN", "default"),
+            ("{OP}:QR", "default"),
+        ] {
+            assert_eq!(
+                db.key_info(key).await.unwrap().unwrap().pool,
+                key_pool,
+                "{key}"
+            );
+        }
+    }
+
+    async fn reading_a_missing_keys_file_fails(db: Db) {
+        assert!(
+            read_beta_keys_file(&*db, "/nonexistent/keys.txt", DEFAULT_POOL)
+                .await
+                .is_err()
+        );
+    }
+
+    async fn concurrent_claims_never_share_a_key(db: Db) {
+        add_keys(&*db, 200).await;
+
+        let claims = (0..400).map(|i| {
+            let db = db.clone();
+            tokio::spawn(async move {
+                db.claim_key_with_user(&format!("user-{i}"), DEFAULT_POOL, &Limits::default())
+                    .await
+            })
+        });
+        let mut keys = Vec::new();
+        for claim in claims.collect::<Vec<_>>() {
+            if let Ok(key) = claim.await.unwrap() {
+                keys.push(key);
+            }
+        }
+
+        let unique = keys.iter().collect::<HashSet<_>>();
+        assert_eq!(keys.len(), 200, "every key should be claimed once");
+        assert_eq!(unique.len(), keys.len(), "a key was handed out twice");
+        assert_eq!(db.remaining_unclaimed(DEFAULT_POOL).await.unwrap(), 0);
+    }
+
+    async fn concurrent_claims_by_one_user_respect_the_limit(db: Db) {
+        add_keys(&*db, 50).await;
+
+        let claims = (0..200).map(|_| {
+            let db = db.clone();
+            tokio::spawn(async move {
+                db.claim_key_with_user("user", DEFAULT_POOL, &per_round(2))
+                    .await
+            })
+        });
+        let mut claimed = 0;
+        for claim in claims.collect::<Vec<_>>() {
+            if claim.await.unwrap().is_ok() {
+                claimed += 1;
+            }
+        }
+
+        assert_eq!(claimed, 2);
+        assert_eq!(db.remaining_unclaimed(DEFAULT_POOL).await.unwrap(), 48);
+    }
+
+    async fn concurrent_claims_respect_the_lifetime_limit_and_cooldown(db: Db) {
+        add_keys(&*db, 50).await;
+        // one key from an earlier round counts towards the lifetime limit
+        db.claim_key_with_user("user", DEFAULT_POOL, &Limits::default())
+            .await
+            .unwrap();
+        let next = db.create_round("second", None).await.unwrap();
+        db.open_round(next, false).await.unwrap();
+
+        let claim_all = |user: &'static str, limits: Limits| {
+            let claims = (0..50)
+                .map(|_| {
+                    let db = db.clone();
+                    tokio::spawn(async move {
+                        db.claim_key_with_user(user, DEFAULT_POOL, &limits).await
+                    })
+                })
+                .collect::<Vec<_>>();
+            async move {
+                let mut claimed = 0;
+                for claim in claims {
+                    if claim.await.unwrap().is_ok() {
+                        claimed += 1;
+                    }
+                }
+                claimed
+            }
+        };
+
+        let lifetime = Limits {
+            per_round: 0,
+            lifetime: 3,
+            cooldown: 0,
+        };
+        assert_eq!(claim_all("user", lifetime).await, 2);
+
+        let cooldown = Limits {
+            per_round: 0,
+            lifetime: 0,
+            cooldown: 3600,
+        };
+        assert_eq!(claim_all("user", cooldown).await, 0);
+        assert_eq!(claim_all("other", cooldown).await, 1);
+        assert_eq!(db.remaining_unclaimed(DEFAULT_POOL).await.unwrap(), 46);
+    }
+
+    async fn concurrently_created_rounds_get_their_own_ids(db: Db) {
+        let rounds = (0..20).map(|i| {
+            let db = db.clone();
+            tokio::spawn(async move { db.create_round(&format!("round {i}"), None).await })
+        });
+        let mut ids = HashSet::new();
+        for round in rounds.collect::<Vec<_>>() {
+            assert!(
+                ids.insert(round.await.unwrap().unwrap()),
+                "a round id was taken twice"
+            );
+        }
+
+        // round 1 is created when the store is prepared
+        assert_eq!(db.list_rounds().await.unwrap().len(), 21);
+    }
+
+    async fn claims_table_rejects_a_second_claim_in_the_same_slot(db: Db) {
+        add_keys(&*db, 2).await;
+        db.claim_key_with_user("user", DEFAULT_POOL, &Limits::default())
+            .await
+            .unwrap();
+
+        // bypass claim_key_with_user, the schema alone has to stop this
+        let duplicate = db
+            .execute(
+                "INSERT INTO claims (key_id, user_id, round_id, pool, slot)
+                SELECT k.id, u.id, 1, 'default', 1 FROM keys k, users u
+                WHERE k.claimed = FALSE AND u.username = 'user' LIMIT 1",
+            )
+            .await;
+        assert!(duplicate.is_err());
+
+        let unexplained_waiver = db
+            .execute(
+                "INSERT INTO claims (key_id, user_id, round_id, pool, slot, waiver_reason)
+                SELECT k.id, u.id, 1, 'default', NULL, NULL FROM keys k, users u
+                WHERE k.claimed = FALSE AND u.username = 'user' LIMIT 1",
+            )
+            .await;
+        assert!(unexplained_waiver.is_err());
+    }
+
+    async fn unchecked_grants_are_waived_with_a_reason(db: Db) {
+        add_keys(&*db, 3).await;
+        db.claim_key_with_user("user", DEFAULT_POOL, &Limits::default())
+            .await
+            .unwrap();
+
+        db.give_key_unchecked("user", DEFAULT_POOL, "replacement key")
+            .await
+            .unwrap();
+        assert!(matches!(
+            db.claim_key_with_user("user", DEFAULT_POOL, &Limits::default())
+                .await,
+            Err(ClaimError::AlreadyClaimed)
+        ));
+
+        // a no-op update that only matches the waived claim
+        let waived = db
+            .execute(
+                "UPDATE claims SET waiver_reason = waiver_reason
+                WHERE slot IS NULL AND waiver_reason = 'replacement key'",
+            )
+            .await
+            .unwrap();
+        assert_eq!(waived, 1);
+
+        // a blank reason is refused by the database and leaves the key unclaimed
+        assert!(matches!(
+            db.give_key_unchecked("user", DEFAULT_POOL, "  ").await,
+            Err(ClaimError::Database(_))
+        ));
+        assert_eq!(db.remaining_unclaimed(DEFAULT_POOL).await.unwrap(), 1);
+    }
+
+    async fn revoking_a_key_frees_its_slot_but_keeps_the_claim(db: Db) {
+        add_keys(&*db, 2).await;
+        let limits = Limits {
+            lifetime: 1,
+            ..Limits::default()
+        };
+        let key = db
+            .claim_key_with_user("user", DEFAULT_POOL, &limits)
+            .await
+            .unwrap();
+
+        assert_eq!(db.revoke_key(&key).await.unwrap().as_deref(), Some("user"));
+        let stats = db.claim_stats("user", DEFAULT_POOL).await.unwrap();
+        assert_eq!((stats.round, stats.lifetime), (0, 0));
+
+        let replacement = db
+            .claim_key_with_user("user", DEFAULT_POOL, &limits)
+            .await
+            .unwrap();
+        assert_eq!(db.round_keys("user").await.unwrap(), vec![replacement]);
+
+        // the revoked key was still handed out, so the report counts it
+        db.close_round(1).await.unwrap();
+        let report = db.save_round_report(1).await.unwrap();
+        assert_eq!((report.keys_claimed, report.claimants), (2, 1));
+    }
+
+    async fn claiming_from_an_empty_pool_says_no_keys_left(db: Db) {
+        assert!(matches!(
+            db.claim_key_with_user("user", DEFAULT_POOL, &Limits::default())
+                .await,
+            Err(ClaimError::NoKeysLeft)
+        ));
+        assert!(matches!(
+            db.give_key_unchecked("user", DEFAULT_POOL, "test").await,
+            Err(ClaimError::NoKeysLeft)
+        ));
+    }
+
+    async fn closed_rounds_stay_closed_unless_forced(db: Db) {
+        add_keys(&*db, 2).await;
+        let next = db.create_round("second", None).await.unwrap();
+
+        assert_eq!(db.open_round(next, false).await.unwrap(), Some(1));
+        assert!(db.open_round(1, false).await.is_err());
+
+        db.close_round(next).await.unwrap();
+        assert!(matches!(
+            db.claim_key_with_user("user", DEFAULT_POOL, &Limits::default())
+                .await,
+            Err(ClaimError::NotEligible(_))
+        ));
+
+        assert_eq!(db.open_round(1, true).await.unwrap(), None);
+        assert!(db
+            .claim_key_with_user("user", DEFAULT_POOL, &Limits::default())
+            .await
+            .is_ok());
+    }
+
+    async fn claims_racing_a_closing_round_never_outlive_it(db: Db) {
+        add_keys(&*db, 100).await;
+
+        let claims = (0..100)
+            .map(|i| {
+                let db = db.clone();
+                tokio::spawn(async move {
+                    if i % 2 == 0 {
+                        db.claim_key_with_user(
+                            &format!("user-{i}"),
+                            DEFAULT_POOL,
+                            &Limits::default(),
+                        )
+                        .await
+                    } else {
+                        db.give_key_unchecked(&format!("user-{i}"), DEFAULT_POOL, "test")
+                            .await
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        db.close_round(1).await.unwrap();
+
+        let mut claimed = 0;
+        for claim in claims {
+            match claim.await.unwrap() {
+                Ok(_) => claimed += 1,
+                Err(ClaimError::NotEligible(_)) => {}
+                Err(e) => panic!("claim failed with {e}"),
+            }
+        }
+
+        // every key handed out belongs to the round, none was claimed after it closed
+        assert_eq!(
+            db.remaining_unclaimed(DEFAULT_POOL).await.unwrap(),
+            100 - claimed
+        );
+        let report = db.save_round_report(1).await.unwrap();
+        assert_eq!(report.keys_claimed, claimed);
+        assert!(matches!(
+            db.claim_key_with_user("late", DEFAULT_POOL, &Limits::default())
+                .await,
+            Err(ClaimError::NotEligible(_))
+        ));
+    }
+
+    async fn round_reports_count_claims_and_remaining_keys(db: Db) {
+        add_keys(&*db, 2).await;
+        for user in ["a", "b", "c"] {
+            let _ = db
+                .claim_key_with_user(user, DEFAULT_POOL, &Limits::default())
+                .await;
+        }
+        db.close_round(1).await.unwrap();
+
+        let report = db.save_round_report(1).await.unwrap();
+        assert_eq!((report.keys_claimed, report.claimants), (2, 2));
+        assert_eq!(report.pools.len(), 1);
+        assert_eq!(report.pools[0].remaining, 0);
+        assert!(report.pools[0].exhausted_after.is_some());
+
+        // saving it again replaces the report instead of adding a second one
+        db.save_round_report(1).await.unwrap();
+        assert_eq!(db.round_report(1).await.unwrap().unwrap().pools.len(), 1);
+    }
+
+    async fn low_stock_is_marked_once_per_crossing(db: Db) {
+        assert!(db.mark_low_stock(DEFAULT_POOL, 3).await.unwrap());
+        assert!(!db.mark_low_stock(DEFAULT_POOL, 2).await.unwrap());
+        assert!(db.mark_low_stock("vip", 0).await.unwrap());
+
+        db.clear_low_stock(DEFAULT_POOL).await.unwrap();
+        assert!(db.mark_low_stock(DEFAULT_POOL, 1).await.unwrap());
+    }
+
+    async fn claims_are_timestamped_in_utc(db: Db) {
+        add_keys(&*db, 1).await;
+        // sqlite keeps milliseconds, so the claim can look up to a millisecond older
+        let before = Utc::now() - chrono::Duration::milliseconds(1);
+        let key = db
+            .claim_key_with_user("user", DEFAULT_POOL, &Limits::default())
+            .await
+            .unwrap();
+        let after = Utc::now();
+
+        let claimed_at = db
+            .key_info(&key)
+            .await
+            .unwrap()
+            .unwrap()
+            .claimed_at
+            .unwrap();
+        assert!(before <= claimed_at && claimed_at <= after);
+        let last_claimed_at = db
+            .claim_stats("user", DEFAULT_POOL)
+            .await
+            .unwrap()
+            .last_claimed_at
+            .unwrap();
+        assert!(before <= last_claimed_at && last_claimed_at <= after);
+    }
+
+    #[test]
+    fn both_backends_have_the_same_migrations() {
+        let versions = |migrator: sqlx::migrate::Migrator| {
+            migrator
+                .iter()
+                .map(|m| (m.version, m.description.to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            versions(sqlx::migrate!("./migrations")),
+            versions(sqlx::migrate!("./migrations/postgres"))
+        );
+    }
+
+    #[tokio::test]
+    async fn memory_copies_leave_the_file_alone() {
+        let file = std::env::temp_dir().join(format!("keybot-copy-{}.db", std::process::id()));
+        let file = file.to_str().unwrap();
+        let db: Db = Arc::new(SqliteStore::connect_or_create(file).await.unwrap());
+        let db = prepare(db).await;
+        add_keys(&*db, 1).await;
+
+        let copy = db.as_sqlite().unwrap().memory_copy().await.unwrap();
+        copy.claim_key_with_user("user", DEFAULT_POOL, &Limits::default())
+            .await
+            .unwrap();
+
+        assert_eq!(copy.remaining_unclaimed(DEFAULT_POOL).await.unwrap(), 0);
+        assert_eq!(db.remaining_unclaimed(DEFAULT_POOL).await.unwrap(), 1);
+
+        db.close().await.unwrap();
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{file}{suffix}"));
+        }
+    }
+
+    #[tokio::test]
+    async fn backups_are_intact_copies() {
+        // an in-memory database would write its backup to memory as well
+        let dir = std::env::temp_dir();
+        let file = dir.join(format!("keybot-live-{}.db", std::process::id()));
+        let file = file.to_str().unwrap();
+        let db = SqliteStore::connect_or_create(file).await.unwrap();
+        assert!(db.is_empty().await.unwrap());
+        let db = prepare(Arc::new(db)).await;
+        add_keys(&*db, 2).await;
+        db.claim_key_with_user("user", DEFAULT_POOL, &Limits::default())
+            .await
+            .unwrap();
+
+        let copy = dir.join(format!("keybot-backup-{}.db", std::process::id()));
+        let copy = copy.to_str().unwrap();
+        let live = db.as_sqlite().unwrap();
+        live.vacuum_into(copy).await.unwrap();
+        assert!(live.vacuum_into(copy).await.is_err());
+
+        let backup = SqliteStore::connect_or_create(copy).await.unwrap();
+        backup.integrity_check().await.unwrap();
+        assert!(!backup.is_empty().await.unwrap());
+        assert_eq!(backup.remaining_unclaimed(DEFAULT_POOL).await.unwrap(), 1);
+
+        backup.close().await.unwrap();
+        db.close().await.unwrap();
+        for name in [file, copy] {
+            for suffix in ["", "-wal", "-shm"] {
+                let _ = std::fs::remove_file(format!("{name}{suffix}"));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn local_timestamps_are_migrated_to_utc() {
+        let url = "sqlite:file:local_timestamps?mode=memory&cache=shared";
+        let db: Db = Arc::new(SqliteStore::connect_or_create(url).await.unwrap());
+
+        // the schema as it was before timestamps were UTC
+        let migrator = sqlx::migrate!("./migrations");
+        let old = sqlx::migrate::Migrator {
+            migrations: migrator
+                .iter()
+                .filter(|m| m.version < 20261018120700)
+                .cloned()
+                .collect::<Vec<_>>()
+                .into(),
+            ignore_missing: false,
+            locking: true,
+        };
+        let pool = sqlx::SqlitePool::connect(url).await.unwrap();
+        old.run(&pool).await.unwrap();
+        pool.close().await;
+
+        db.execute(
+            "INSERT INTO giveaway_rounds (round_id, status, starts_at) VALUES (1, 'active', '2024-07-01 12:00:00')",
+        )
+        .await
+        .unwrap();
+        db.execute("INSERT INTO keys (key_val, added_at) VALUES ('key', '2024-01-15 10:30:00')")
+            .await
+            .unwrap();
+        db.add_tables().await.unwrap();
+
+        // converted with the offset the host had at the time, daylight saving included
+        let utc = |local: &str| {
+            chrono::NaiveDateTime::parse_from_str(local, "%Y-%m-%d %H:%M:%S")
+                .unwrap()
+                .and_local_timezone(chrono::Local)
+                .unwrap()
+                .with_timezone(&Utc)
+        };
+        let round = db.round_info(1).await.unwrap().unwrap();
+        assert_eq!(round.starts_at, Some(utc("2024-07-01 12:00:00")));
+        let key = db.key_info("key").await.unwrap().unwrap();
+        assert_eq!(key.added_at, Some(utc("2024-01-15 10:30:00")));
+
+        // rows added afterwards are UTC already
+        add_keys(&*db, 1).await;
+        let added_at = db.all_keys(None).await.unwrap()[1].added_at.unwrap();
+        assert!((Utc::now() - added_at).num_seconds().abs() < 5);
+    }
 }
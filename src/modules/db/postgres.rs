@@ -0,0 +1,925 @@
+use std::{collections::HashSet, str::FromStr};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::Result;
+use sqlx::{
+    migrate::{Migrate, MigrateDatabase},
+    postgres::{PgConnectOptions, PgPoolOptions, Postgres},
+    Pool,
+};
+
+use super::{ClaimStats, KeyInfo, Limits, PoolReport, PoolStats, Round, RoundReport, Store};
+use crate::error::{ClaimError, Ineligible};
+
+/// A Postgres database
+///
+/// The queries are checked at runtime instead of at compile time, the offline query data in `.sqlx`
+/// only covers SQLite.
+pub struct PostgresStore {
+    pool: Pool<Postgres>,
+}
+
+impl PostgresStore {
+    pub async fn connect(url: &str) -> Result<Self> {
+        if !Postgres::database_exists(url).await? {
+            Postgres::create_database(url).await?;
+        }
+
+        Self::connect_with(PgConnectOptions::from_str(url)?).await
+    }
+
+    pub async fn connect_with(options: PgConnectOptions) -> Result<Self> {
+        let pool = PgPoolOptions::new()
+            .max_connections(4)
+            .connect_with(options)
+            .await?;
+
+        Ok(Self { pool })
+    }
+}
+
+async fn add_user(pool: &Pool<Postgres>, user: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO users (username) VALUES ($1) ON CONFLICT DO NOTHING;")
+        .bind(user)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+// keys can only be claimed while a round is open, claims always belong to a round
+//
+// the claim queries check for the round themselves, this only tells a claim that got no key why
+async fn has_active_round(pool: &Pool<Postgres>) -> Result<bool, sqlx::Error> {
+    let active: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM giveaway_rounds WHERE status = 'active';")
+            .fetch_one(pool)
+            .await?;
+
+    Ok(active > 0)
+}
+
+#[async_trait]
+impl Store for PostgresStore {
+    async fn add_tables(&self) -> Result<()> {
+        sqlx::migrate!("./migrations/postgres")
+            .run(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn pending_migrations(&self) -> Result<usize> {
+        let mut conn = self.pool.acquire().await?;
+        conn.ensure_migrations_table().await?;
+
+        let applied = conn
+            .list_applied_migrations()
+            .await?
+            .into_iter()
+            .map(|m| m.version)
+            .collect::<HashSet<_>>();
+
+        Ok(sqlx::migrate!("./migrations/postgres")
+            .iter()
+            .filter(|m| !applied.contains(&m.version))
+            .count())
+    }
+
+    async fn is_empty(&self) -> Result<bool> {
+        let tables: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM information_schema.tables WHERE table_schema = current_schema();",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(tables == 0)
+    }
+
+    async fn ping(&self) -> Result<()> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+
+        Ok(())
+    }
+
+    async fn close(&self) -> Result<()> {
+        self.pool.close().await;
+
+        Ok(())
+    }
+
+    async fn add_round(&self, round: i64) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO giveaway_rounds (round_id, status) VALUES ($1, 'planned') ON CONFLICT DO NOTHING;",
+        )
+        .bind(round)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn add_key(&self, key: &str, key_pool: &str) -> Result<bool> {
+        let added =
+            sqlx::query("INSERT INTO keys (key_val, pool) VALUES ($1, $2) ON CONFLICT DO NOTHING;")
+                .bind(key)
+                .bind(key_pool)
+                .execute(&self.pool)
+                .await?
+                .rows_affected();
+
+        Ok(added > 0)
+    }
+
+    async fn remaining_unclaimed(&self, key_pool: &str) -> Result<i32> {
+        let count = sqlx::query_scalar(
+            r#"
+SELECT COUNT(*)::INT4
+FROM keys
+WHERE claimed = FALSE AND revoked = FALSE AND pool = $1;"#,
+        )
+        .bind(key_pool)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count)
+    }
+
+    async fn give_key_unchecked(
+        &self,
+        user: &str,
+        key_pool: &str,
+        reason: &str,
+    ) -> Result<String, ClaimError> {
+        let pool = &self.pool;
+        add_user(pool, user).await?;
+
+        let mut transaction = pool.begin().await?;
+
+        // keys locked by another claim are skipped, so two claims never wait on the same key. The
+        // active round is locked until the claim is done, so it can't be closed in between
+        let key: Option<(i64, String)> = sqlx::query_as(
+            r#"
+UPDATE keys
+SET claimed = TRUE,
+    user_claim = (SELECT id FROM users WHERE username = $1),
+    claimed_at = now(),
+    claim_round = (SELECT round_id FROM giveaway_rounds WHERE status = 'active')
+WHERE id = (
+    SELECT id FROM keys WHERE claimed = FALSE AND revoked = FALSE AND pool = $2
+    LIMIT 1 FOR UPDATE SKIP LOCKED
+)
+AND EXISTS (SELECT 1 FROM giveaway_rounds WHERE status = 'active' FOR SHARE)
+RETURNING id, key_val;"#,
+        )
+        .bind(user)
+        .bind(key_pool)
+        .fetch_optional(&mut *transaction)
+        .await?;
+
+        let Some((key_id, key)) = key else {
+            // nothing was claimed, roll back before looking up why
+            drop(transaction);
+            if !has_active_round(pool).await? {
+                return Err(ClaimError::NotEligible(Ineligible::NoRoundOpen));
+            }
+            return Err(ClaimError::NoKeysLeft);
+        };
+
+        sqlx::query(
+            r#"
+INSERT INTO claims (key_id, user_id, round_id, pool, slot, waiver_reason)
+VALUES (
+    $1,
+    (SELECT id FROM users WHERE username = $2),
+    (SELECT round_id FROM giveaway_rounds WHERE status = 'active'),
+    $3,
+    NULL,
+    $4
+);"#,
+        )
+        .bind(key_id)
+        .bind(user)
+        .bind(key_pool)
+        .bind(reason)
+        .execute(&mut *transaction)
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(key)
+    }
+
+    async fn claim_key_with_user(
+        &self,
+        user: &str,
+        key_pool: &str,
+        limits: &Limits,
+    ) -> Result<String, ClaimError> {
+        let pool = &self.pool;
+        add_user(pool, user).await?;
+
+        let key = async {
+            let mut transaction = pool.begin().await?;
+
+            // claims of the same user wait for each other, so the limit checks, picking a key and
+            // claiming it can't interleave, claims of different users still run side by side. The
+            // active round is locked until the claim is done, so it can't be closed in between
+            sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1));")
+                .bind(user)
+                .execute(&mut *transaction)
+                .await?;
+
+            let key: Option<(i64, String)> = sqlx::query_as(
+                r#"
+UPDATE keys
+SET claimed = TRUE,
+    user_claim = (SELECT id FROM users WHERE username = $2),
+    claimed_at = now(),
+    claim_round = (SELECT round_id FROM giveaway_rounds WHERE status = 'active')
+WHERE id = (
+    SELECT k.id
+    FROM keys k
+    WHERE k.claimed = FALSE
+    AND k.revoked = FALSE
+    AND k.pool = $1
+    AND (
+        SELECT COUNT(*)
+        FROM claims c
+        INNER JOIN users u ON c.user_id = u.id
+        INNER JOIN giveaway_rounds gr ON c.round_id = gr.round_id
+        WHERE u.username = $2
+        AND c.pool = $1
+    AND c.revoked = FALSE
+        AND gr.status = 'active'
+    ) < $3
+    AND (
+        SELECT COUNT(*)
+        FROM claims c
+        INNER JOIN users u ON c.user_id = u.id
+        WHERE u.username = $2
+        AND c.pool = $1
+    AND c.revoked = FALSE
+    ) < $4
+    AND NOT EXISTS (
+        SELECT 1
+        FROM claims c
+        INNER JOIN users u ON c.user_id = u.id
+        WHERE u.username = $2
+        AND c.pool = $1
+    AND c.revoked = FALSE
+        AND c.claimed_at > now() - $5 * INTERVAL '1 second'
+    )
+    LIMIT 1 FOR UPDATE SKIP LOCKED
+)
+AND EXISTS (SELECT 1 FROM giveaway_rounds WHERE status = 'active' FOR SHARE)
+RETURNING id, key_val;"#,
+            )
+            .bind(key_pool)
+            .bind(user)
+            .bind(limits.round_limit())
+            .bind(limits.lifetime_limit())
+            .bind(limits.cooldown as f64)
+            .fetch_optional(&mut *transaction)
+            .await?;
+
+            let Some((key_id, key)) = key else {
+                return Ok(None);
+            };
+
+            // takes the lowest free slot, the unique constraint on claims rejects a second claim for
+            // the same slot even if the limit check above was bypassed
+            let claim = sqlx::query(
+                r#"
+WITH taken AS (
+    SELECT c.slot
+    FROM claims c
+    INNER JOIN users u ON c.user_id = u.id
+    INNER JOIN giveaway_rounds gr ON c.round_id = gr.round_id
+    WHERE u.username = $2
+    AND c.pool = $3
+    AND gr.status = 'active'
+    AND c.slot IS NOT NULL
+    AND c.revoked = FALSE
+)
+INSERT INTO claims (key_id, user_id, round_id, pool, slot)
+VALUES (
+    $1,
+    (SELECT id FROM users WHERE username = $2),
+    (SELECT round_id FROM giveaway_rounds WHERE status = 'active'),
+    $3,
+    (
+        SELECT MIN(t.slot + 1)
+        FROM (SELECT 0 AS slot UNION ALL SELECT slot FROM taken) t
+        WHERE t.slot + 1 NOT IN (SELECT slot FROM taken)
+    )
+);"#,
+            )
+            .bind(key_id)
+            .bind(user)
+            .bind(key_pool)
+            .execute(&mut *transaction)
+            .await;
+
+            match claim {
+                Ok(_) => {}
+                // dropping the transaction rolls the key back to unclaimed
+                Err(sqlx::Error::Database(e)) if e.is_unique_violation() => return Ok(None),
+                Err(e) => return Err(e),
+            }
+
+            transaction.commit().await?;
+
+            Ok::<_, sqlx::Error>(Some(key))
+        }
+        .await?;
+
+        let Some(key) = key else {
+            // no key was picked, because no round is open, the pool is empty or because of the limit
+            if !has_active_round(pool).await? {
+                return Err(ClaimError::NotEligible(Ineligible::NoRoundOpen));
+            }
+
+            let unclaimed: i64 = sqlx::query_scalar(
+                "SELECT COUNT(*) FROM keys WHERE claimed = FALSE AND revoked = FALSE AND pool = $1;",
+            )
+            .bind(key_pool)
+            .fetch_one(pool)
+            .await?;
+
+            return Err(if unclaimed > 0 {
+                ClaimError::AlreadyClaimed
+            } else {
+                ClaimError::NoKeysLeft
+            });
+        };
+
+        Ok(key)
+    }
+
+    async fn get_config_val(&self, key: &str) -> Result<String> {
+        let value = sqlx::query_scalar("SELECT value FROM config WHERE key = $1;")
+            .bind(key)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(value)
+    }
+
+    async fn config_vals(&self) -> Result<Vec<(String, String)>> {
+        let values = sqlx::query_as("SELECT key, value FROM config ORDER BY key;")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(values)
+    }
+
+    async fn create_round(&self, name: &str, notes: Option<&str>) -> Result<i64> {
+        // round ids are also picked by hand, so they can't come from a sequence, the lock keeps two
+        // rounds created at once from both taking the same MAX(round_id) + 1
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query("LOCK TABLE giveaway_rounds IN EXCLUSIVE MODE;")
+            .execute(&mut *transaction)
+            .await?;
+
+        let id = sqlx::query_scalar(
+            r#"
+INSERT INTO giveaway_rounds (round_id, status, name, notes)
+SELECT COALESCE(MAX(round_id), 0) + 1, 'planned', $1, $2
+FROM giveaway_rounds
+RETURNING round_id;"#,
+        )
+        .bind(name)
+        .bind(notes)
+        .fetch_one(&mut *transaction)
+        .await?;
+        transaction.commit().await?;
+
+        Ok(id)
+    }
+
+    async fn open_round(&self, round: i64, force: bool) -> Result<Option<i64>> {
+        let mut transaction = self.pool.begin().await?;
+
+        let status: Option<String> =
+            sqlx::query_scalar("SELECT status FROM giveaway_rounds WHERE round_id = $1;")
+                .bind(round)
+                .fetch_optional(&mut *transaction)
+                .await?;
+
+        match status.as_deref() {
+            None => return Err(color_eyre::eyre::eyre!("Round {round} does not exist")),
+            Some("active") => return Ok(Some(round)),
+            Some("closed") if !force => {
+                return Err(color_eyre::eyre::eyre!(
+                    "Round {round} is closed, use force to open it again"
+                ))
+            }
+            _ => {}
+        }
+
+        let previous = sqlx::query_scalar(
+            r#"
+UPDATE giveaway_rounds
+SET status = 'closed', ends_at = now()
+WHERE status = 'active'
+RETURNING round_id;"#,
+        )
+        .fetch_optional(&mut *transaction)
+        .await?;
+
+        sqlx::query(
+            r#"
+UPDATE giveaway_rounds
+SET status = 'active', starts_at = COALESCE(starts_at, now()), ends_at = NULL
+WHERE round_id = $1;"#,
+        )
+        .bind(round)
+        .execute(&mut *transaction)
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(previous)
+    }
+
+    async fn close_round(&self, round: i64) -> Result<()> {
+        let closed = sqlx::query(
+            r#"
+UPDATE giveaway_rounds
+SET status = 'closed', ends_at = now()
+WHERE round_id = $1 AND status != 'closed';"#,
+        )
+        .bind(round)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        if closed == 0 {
+            return Err(color_eyre::eyre::eyre!(
+                "Round {round} does not exist or is already closed"
+            ));
+        }
+
+        Ok(())
+    }
+
+    async fn list_rounds(&self) -> Result<Vec<Round>> {
+        let rounds = sqlx::query_as(
+            r#"
+SELECT
+    round_id,
+    name,
+    status,
+    starts_at,
+    ends_at,
+    notes
+FROM giveaway_rounds
+ORDER BY round_id;"#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rounds)
+    }
+
+    async fn round_info(&self, round: i64) -> Result<Option<Round>> {
+        let round = sqlx::query_as(
+            r#"
+SELECT
+    round_id,
+    name,
+    status,
+    starts_at,
+    ends_at,
+    notes
+FROM giveaway_rounds
+WHERE round_id = $1;"#,
+        )
+        .bind(round)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(round)
+    }
+
+    async fn round_claim_counts(&self, round: i64) -> Result<(i32, i32)> {
+        let counts = sqlx::query_as(
+            r#"
+SELECT COUNT(*)::INT4, COUNT(DISTINCT user_id)::INT4
+FROM claims
+WHERE round_id = $1;"#,
+        )
+        .bind(round)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(counts)
+    }
+
+    async fn get_round(&self) -> Result<Option<i64>> {
+        let round =
+            sqlx::query_scalar("SELECT round_id FROM giveaway_rounds WHERE status = 'active';")
+                .fetch_optional(&self.pool)
+                .await?;
+
+        Ok(round)
+    }
+
+    async fn set_config_val(&self, key: &str, value: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+INSERT INTO config (key, value) VALUES ($1, $2)
+ON CONFLICT (key) DO UPDATE SET value = EXCLUDED.value;"#,
+        )
+        .bind(key)
+        .bind(value)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_config_val(&self, key: &str) -> Result<()> {
+        sqlx::query("DELETE FROM config WHERE key = $1;")
+            .bind(key)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn claim_stats(&self, user: &str, key_pool: &str) -> Result<ClaimStats> {
+        let stats = sqlx::query_as(
+            r#"
+SELECT
+    COUNT(CASE WHEN gr.status = 'active' THEN 1 END)::INT4 AS round,
+    COUNT(c.id)::INT4 AS lifetime,
+    MAX(c.claimed_at) AS last_claimed_at
+FROM claims c
+INNER JOIN users u ON c.user_id = u.id
+INNER JOIN giveaway_rounds gr ON c.round_id = gr.round_id
+WHERE u.username = $1
+AND c.pool = $2
+AND c.revoked = FALSE;"#,
+        )
+        .bind(user)
+        .bind(key_pool)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(stats)
+    }
+
+    async fn save_round_report(&self, round: i64) -> Result<RoundReport> {
+        let mut transaction = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM round_report_pools WHERE round_id = $1;")
+            .bind(round)
+            .execute(&mut *transaction)
+            .await?;
+
+        sqlx::query(
+            r#"
+INSERT INTO round_reports (round_id, keys_claimed, claimants, failed_deliveries)
+VALUES (
+    $1,
+    (SELECT COUNT(*) FROM claims WHERE round_id = $1),
+    (SELECT COUNT(DISTINCT user_id) FROM claims WHERE round_id = $1),
+    (SELECT COUNT(*) FROM events WHERE kind = 'failure' AND round_id = $1 AND key_id IS NOT NULL)
+)
+ON CONFLICT (round_id) DO UPDATE SET
+    keys_claimed = EXCLUDED.keys_claimed,
+    claimants = EXCLUDED.claimants,
+    failed_deliveries = EXCLUDED.failed_deliveries,
+    created_at = now();"#,
+        )
+        .bind(round)
+        .execute(&mut *transaction)
+        .await?;
+
+        sqlx::query(
+            r#"
+INSERT INTO round_report_pools (round_id, pool, keys_claimed, remaining, exhausted_after)
+SELECT
+    $1,
+    p.pool,
+    p.keys_claimed,
+    p.remaining,
+    CASE WHEN p.remaining = 0 AND p.keys_claimed > 0 THEN
+        EXTRACT(EPOCH FROM date_trunc('second', p.last_claimed_at)
+            - date_trunc('second', (SELECT starts_at FROM giveaway_rounds WHERE round_id = $1)))::BIGINT
+    END
+FROM (
+    SELECT
+        k.pool,
+        (SELECT COUNT(*) FROM claims c WHERE c.round_id = $1 AND c.pool = k.pool) AS keys_claimed,
+        SUM(CASE WHEN k.claimed = FALSE AND k.revoked = FALSE THEN 1 ELSE 0 END) AS remaining,
+        (SELECT MAX(c.claimed_at) FROM claims c WHERE c.round_id = $1 AND c.pool = k.pool)
+            AS last_claimed_at
+    FROM keys k
+    GROUP BY k.pool
+) p;"#,
+        )
+        .bind(round)
+        .execute(&mut *transaction)
+        .await?;
+
+        transaction.commit().await?;
+
+        self.round_report(round)
+            .await?
+            .ok_or_else(|| color_eyre::eyre::eyre!("Report of round {round} was not saved"))
+    }
+
+    async fn round_report(&self, round: i64) -> Result<Option<RoundReport>> {
+        let report: Option<(i64, i32, i32, i32, DateTime<Utc>)> = sqlx::query_as(
+            r#"
+SELECT
+    round_id,
+    keys_claimed,
+    claimants,
+    failed_deliveries,
+    created_at
+FROM round_reports
+WHERE round_id = $1;"#,
+        )
+        .bind(round)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some((round_id, keys_claimed, claimants, failed_deliveries, created_at)) = report
+        else {
+            return Ok(None);
+        };
+
+        let pools = sqlx::query_as::<_, PoolReport>(
+            r#"
+SELECT pool, keys_claimed, remaining, exhausted_after
+FROM round_report_pools
+WHERE round_id = $1
+ORDER BY pool;"#,
+        )
+        .bind(round)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(Some(RoundReport {
+            round_id,
+            keys_claimed,
+            claimants,
+            failed_deliveries,
+            created_at,
+            pools,
+        }))
+    }
+
+    async fn pools(&self) -> Result<Vec<String>> {
+        let pools = sqlx::query_scalar("SELECT DISTINCT pool FROM keys ORDER BY pool;")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(pools)
+    }
+
+    async fn pool_stats(&self) -> Result<Vec<PoolStats>> {
+        let stats = sqlx::query_as(
+            r#"
+SELECT
+    pool,
+    COUNT(*)::INT4 AS total,
+    SUM(CASE WHEN claimed = TRUE AND revoked = FALSE THEN 1 ELSE 0 END)::INT4 AS claimed,
+    SUM(CASE WHEN revoked = TRUE THEN 1 ELSE 0 END)::INT4 AS revoked,
+    SUM(CASE WHEN claimed = FALSE AND revoked = FALSE THEN 1 ELSE 0 END)::INT4 AS remaining
+FROM keys
+GROUP BY pool
+ORDER BY pool;"#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(stats)
+    }
+
+    async fn round_keys(&self, user: &str) -> Result<Vec<String>> {
+        let keys = sqlx::query_scalar(
+            r#"
+SELECT k.key_val
+FROM claims c
+INNER JOIN keys k ON c.key_id = k.id
+INNER JOIN users u ON c.user_id = u.id
+INNER JOIN giveaway_rounds gr ON c.round_id = gr.round_id
+WHERE u.username = $1
+AND gr.status = 'active'
+AND c.revoked = FALSE
+ORDER BY c.id;"#,
+        )
+        .bind(user)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(keys)
+    }
+
+    async fn recent_events(&self, kind: &str, target: &str, secs: u64) -> Result<i32> {
+        let count = sqlx::query_scalar(
+            r#"
+SELECT COUNT(*)::INT4
+FROM events
+WHERE kind = $1
+AND target = $2
+AND created_at > now() - make_interval(secs => $3);"#,
+        )
+        .bind(kind)
+        .bind(target)
+        .bind(secs as f64)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count)
+    }
+
+    async fn key_info(&self, key: &str) -> Result<Option<KeyInfo>> {
+        let info = sqlx::query_as(
+            r#"
+SELECT
+    k.key_val,
+    k.pool,
+    u.username,
+    k.claim_round AS round,
+    k.added_at,
+    k.claimed_at,
+    k.revoked,
+    (SELECT e.kind FROM events e WHERE e.key_id = k.id AND e.kind IN ('delivery', 'failure')
+        ORDER BY e.id DESC LIMIT 1) AS last_delivery,
+    (SELECT e.created_at FROM events e
+        WHERE e.key_id = k.id AND e.kind IN ('delivery', 'failure')
+        ORDER BY e.id DESC LIMIT 1) AS last_delivery_at
+FROM keys k
+LEFT JOIN users u ON k.user_claim = u.id
+WHERE k.key_val = $1;"#,
+        )
+        .bind(key)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(info)
+    }
+
+    async fn all_keys(&self, key_pool: Option<&str>) -> Result<Vec<KeyInfo>> {
+        let keys = sqlx::query_as(
+            r#"
+SELECT
+    k.key_val,
+    k.pool,
+    u.username,
+    k.claim_round AS round,
+    k.added_at,
+    k.claimed_at,
+    k.revoked,
+    (SELECT e.kind FROM events e WHERE e.key_id = k.id AND e.kind IN ('delivery', 'failure')
+        ORDER BY e.id DESC LIMIT 1) AS last_delivery,
+    (SELECT e.created_at FROM events e
+        WHERE e.key_id = k.id AND e.kind IN ('delivery', 'failure')
+        ORDER BY e.id DESC LIMIT 1) AS last_delivery_at
+FROM keys k
+LEFT JOIN users u ON k.user_claim = u.id
+WHERE $1::VARCHAR IS NULL OR k.pool = $1
+ORDER BY k.id;"#,
+        )
+        .bind(key_pool)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(keys)
+    }
+
+    async fn user_keys(&self, user: &str) -> Result<Vec<KeyInfo>> {
+        let keys = sqlx::query_as(
+            r#"
+SELECT
+    k.key_val,
+    k.pool,
+    u.username,
+    k.claim_round AS round,
+    k.added_at,
+    k.claimed_at,
+    k.revoked,
+    (SELECT e.kind FROM events e WHERE e.key_id = k.id AND e.kind IN ('delivery', 'failure')
+        ORDER BY e.id DESC LIMIT 1) AS last_delivery,
+    (SELECT e.created_at FROM events e
+        WHERE e.key_id = k.id AND e.kind IN ('delivery', 'failure')
+        ORDER BY e.id DESC LIMIT 1) AS last_delivery_at
+FROM keys k
+INNER JOIN users u ON k.user_claim = u.id
+WHERE u.username = $1
+ORDER BY k.claimed_at, k.id;"#,
+        )
+        .bind(user)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(keys)
+    }
+
+    async fn revoke_key(&self, key: &str) -> Result<Option<String>> {
+        let mut transaction = self.pool.begin().await?;
+
+        let owner: Option<Option<String>> = sqlx::query_scalar(
+            r#"
+SELECT u.username
+FROM keys k
+LEFT JOIN users u ON k.user_claim = u.id
+WHERE k.key_val = $1 AND k.revoked = FALSE
+FOR UPDATE OF k;"#,
+        )
+        .bind(key)
+        .fetch_optional(&mut *transaction)
+        .await?;
+
+        let Some(owner) = owner else {
+            return Err(color_eyre::eyre::eyre!(
+                "Key does not exist or is already revoked"
+            ));
+        };
+
+        sqlx::query("UPDATE keys SET revoked = TRUE WHERE key_val = $1;")
+            .bind(key)
+            .execute(&mut *transaction)
+            .await?;
+
+        // the key no longer counts towards the user's limits, the claim is kept for the reports
+        sqlx::query(
+            "UPDATE claims SET revoked = TRUE WHERE key_id = (SELECT id FROM keys WHERE key_val = $1);",
+        )
+            .bind(key)
+            .execute(&mut *transaction)
+            .await?;
+
+        transaction.commit().await?;
+
+        Ok(owner)
+    }
+
+    async fn mark_low_stock(&self, key_pool: &str, remaining: i32) -> Result<bool> {
+        let marked = sqlx::query(
+            "INSERT INTO low_stock_alerts (pool, remaining) VALUES ($1, $2) ON CONFLICT DO NOTHING;",
+        )
+        .bind(key_pool)
+        .bind(remaining)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(marked > 0)
+    }
+
+    async fn clear_low_stock(&self, key_pool: &str) -> Result<()> {
+        sqlx::query("DELETE FROM low_stock_alerts WHERE pool = $1;")
+            .bind(key_pool)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn insert_event(
+        &self,
+        kind: &str,
+        actor: Option<&str>,
+        target: Option<&str>,
+        key: Option<&str>,
+        round: Option<i64>,
+        details: Option<&str>,
+    ) -> Result<i64> {
+        let id = sqlx::query_scalar(
+            r#"
+INSERT INTO events (kind, actor, target, key_id, round_id, details)
+VALUES (
+    $1,
+    $2,
+    $3,
+    (SELECT id FROM keys WHERE key_val = $4),
+    COALESCE($5, (SELECT round_id FROM giveaway_rounds WHERE status = 'active')),
+    $6
+)
+RETURNING id;"#,
+        )
+        .bind(kind)
+        .bind(actor)
+        .bind(target)
+        .bind(key)
+        .bind(round)
+        .bind(details)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(id)
+    }
+
+    #[cfg(test)]
+    async fn execute(&self, sql: &str) -> Result<u64> {
+        Ok(sqlx::query(sql).execute(&self.pool).await?.rows_affected())
+    }
+}
@@ -0,0 +1,1145 @@
+use std::collections::HashSet;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::Result;
+use sqlx::{
+    migrate::{Migrate, MigrateDatabase},
+    sqlite::{Sqlite, SqliteConnectOptions, SqlitePoolOptions},
+    Pool,
+};
+use tracing::debug;
+
+use super::{ClaimStats, KeyInfo, Limits, PoolReport, PoolStats, Round, RoundReport, Store};
+use crate::error::{ClaimError, Ineligible};
+
+/// A SQLite database file
+pub struct SqliteStore {
+    pool: Pool<Sqlite>,
+    /// The file name, without the `sqlite:` in front
+    path: String,
+}
+
+// runs every migration in ./migrations that hasn't been applied yet
+async fn migrate(pool: &Pool<Sqlite>) -> Result<()> {
+    // migrations that rebuild a table drop it while other tables still point at it, which sqlite
+    // only allows with foreign keys off, and they can't be turned off inside the migration's
+    // transaction
+    let mut conn = pool.acquire().await?;
+    sqlx::query("PRAGMA foreign_keys = OFF")
+        .execute(&mut *conn)
+        .await?;
+    let migrated = sqlx::migrate!("./migrations").run_direct(&mut *conn).await;
+    let violations = sqlx::query("PRAGMA foreign_key_check")
+        .fetch_all(&mut *conn)
+        .await?
+        .len();
+    sqlx::query("PRAGMA foreign_keys = ON")
+        .execute(&mut *conn)
+        .await?;
+    migrated?;
+
+    if violations > 0 {
+        return Err(color_eyre::eyre::eyre!(
+            "{violations} row(s) point at rows that don't exist after migrating"
+        ));
+    }
+
+    Ok(())
+}
+
+// copied by `memory_copy` in this order, so foreign keys point at rows that are already there
+const COPIED_TABLES: [&str; 9] = [
+    "users",
+    "giveaway_rounds",
+    "keys",
+    "claims",
+    "config",
+    "events",
+    "round_reports",
+    "round_report_pools",
+    "low_stock_alerts",
+];
+
+impl SqliteStore {
+    pub async fn connect_or_create(database_name: &str) -> Result<Self> {
+        if !Sqlite::database_exists(database_name).await? {
+            Sqlite::create_database(database_name).await?;
+        }
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(4)
+            .connect(database_name)
+            .await?;
+        let path = database_name
+            .strip_prefix("sqlite://")
+            .or_else(|| database_name.strip_prefix("sqlite:"))
+            .unwrap_or(database_name);
+
+        Ok(Self {
+            pool,
+            path: path.to_owned(),
+        })
+    }
+
+    /// The database file
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// An in-memory database with everything in this one, changes to it are lost when it is dropped,
+    /// the file has to be migrated
+    pub async fn memory_copy(&self) -> Result<Self> {
+        // every connection to `:memory:` is a database of its own, so the pool keeps exactly one
+        let copy = SqlitePoolOptions::new()
+            .min_connections(1)
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            // sqlx opens `sqlite::memory:` with a flag that puts attached databases in memory as well,
+            // so the file has to be opened as `:memory:` directly for the ATTACH below to see it
+            .connect_with(SqliteConnectOptions::new().filename(":memory:"))
+            .await?;
+        migrate(&copy).await?;
+
+        let mut conn = copy.acquire().await?;
+        sqlx::query("ATTACH DATABASE ? AS source")
+            .bind(&self.path)
+            .execute(&mut *conn)
+            .await?;
+        for table in COPIED_TABLES {
+            sqlx::query(&format!(
+                "INSERT INTO main.{table} SELECT * FROM source.{table}"
+            ))
+            .execute(&mut *conn)
+            .await?;
+        }
+        sqlx::query("DETACH DATABASE source")
+            .execute(&mut *conn)
+            .await?;
+        drop(conn);
+
+        Ok(Self {
+            pool: copy,
+            path: ":memory:".to_owned(),
+        })
+    }
+
+    /// Writes a consistent copy of the database to `path` while it stays in use, `path` must not exist
+    pub async fn vacuum_into(&self, path: &str) -> Result<()> {
+        sqlx::query("VACUUM INTO ?")
+            .bind(path)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Fails unless SQLite finds the database intact
+    pub async fn integrity_check(&self) -> Result<()> {
+        let result: String = sqlx::query_scalar("PRAGMA integrity_check")
+            .fetch_one(&self.pool)
+            .await?;
+
+        if result != "ok" {
+            return Err(color_eyre::eyre::eyre!("integrity check failed: {result}"));
+        }
+
+        Ok(())
+    }
+}
+
+// sqlite reports a write that collides with another one as busy or locked, both go away once the
+// other writer is done
+fn is_busy(e: &sqlx::Error) -> bool {
+    match e {
+        sqlx::Error::Database(e) => matches!(e.code().as_deref(), Some("5" | "6" | "262" | "517")),
+        _ => false,
+    }
+}
+
+// runs `query` again if the database was busy, sqlite's busy timeout doesn't cover every case
+async fn retry_busy<T, F, Fut>(mut query: F) -> Result<T, sqlx::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T, sqlx::Error>>,
+{
+    let mut attempt = 0;
+
+    loop {
+        match query().await {
+            Err(e) if is_busy(&e) && attempt < 10 => {
+                attempt += 1;
+                debug!("Database busy, retrying ({attempt})");
+                tokio::time::sleep(std::time::Duration::from_millis(10 * attempt)).await;
+            }
+            res => return res,
+        }
+    }
+}
+
+async fn add_user(pool: &Pool<Sqlite>, user: &str) -> Result<(), sqlx::Error> {
+    retry_busy(|| {
+        sqlx::query!(
+            r#"
+        INSERT OR IGNORE INTO users (username) VALUES (?);
+        "#,
+            user
+        )
+        .execute(pool)
+    })
+    .await?;
+
+    Ok(())
+}
+
+// keys can only be claimed while a round is open, claims always belong to a round
+//
+// the claim queries check for the round themselves, this only tells a claim that got no key why
+async fn has_active_round(pool: &Pool<Sqlite>) -> Result<bool, sqlx::Error> {
+    let active = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) FROM giveaway_rounds WHERE status = 'active';
+        "#
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(active > 0)
+}
+
+#[async_trait]
+impl Store for SqliteStore {
+    async fn add_tables(&self) -> Result<()> {
+        migrate(&self.pool).await
+    }
+
+    async fn pending_migrations(&self) -> Result<usize> {
+        let mut conn = self.pool.acquire().await?;
+        conn.ensure_migrations_table().await?;
+
+        let applied = conn
+            .list_applied_migrations()
+            .await?
+            .into_iter()
+            .map(|m| m.version)
+            .collect::<HashSet<_>>();
+
+        Ok(sqlx::migrate!("./migrations")
+            .iter()
+            .filter(|m| !applied.contains(&m.version))
+            .count())
+    }
+
+    async fn is_empty(&self) -> Result<bool> {
+        let tables: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table'")
+                .fetch_one(&self.pool)
+                .await?;
+
+        Ok(tables == 0)
+    }
+
+    async fn ping(&self) -> Result<()> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+
+        Ok(())
+    }
+
+    // writes everything in the write-ahead log back to the database file first
+    async fn close(&self) -> Result<()> {
+        sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+            .execute(&self.pool)
+            .await?;
+        self.pool.close().await;
+
+        Ok(())
+    }
+
+    fn as_sqlite(&self) -> Option<&SqliteStore> {
+        Some(self)
+    }
+
+    async fn add_round(&self, round: i64) -> Result<()> {
+        sqlx::query!(
+            r#"
+        INSERT OR IGNORE INTO giveaway_rounds (round_id, status) VALUES (?, 'planned');
+        "#,
+            round
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn add_key(&self, key: &str, key_pool: &str) -> Result<bool> {
+        let added = sqlx::query!(
+            r#"
+        INSERT OR IGNORE INTO keys (key_val, pool) VALUES (?, ?);
+        "#,
+            key,
+            key_pool
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(added > 0)
+    }
+
+    async fn remaining_unclaimed(&self, key_pool: &str) -> Result<i32> {
+        let pool = &self.pool;
+        let key = sqlx::query!(
+            r#"
+    SELECT COUNT(*) AS unclaimed_keys_count
+    FROM keys
+    WHERE claimed = FALSE AND revoked = FALSE AND pool = ?;"#,
+            key_pool
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(key.unclaimed_keys_count)
+    }
+
+    async fn give_key_unchecked(
+        &self,
+        user: &str,
+        key_pool: &str,
+        reason: &str,
+    ) -> Result<String, ClaimError> {
+        let pool = &self.pool;
+        add_user(pool, user).await?;
+
+        let key = retry_busy(|| async {
+            let mut transaction = pool.begin().await?;
+
+            // picking and claiming the key is a single statement, so two claims can never pick the same key
+            let key = sqlx::query!(
+                r#"
+    UPDATE keys
+    SET claimed = TRUE,
+        user_claim = (SELECT id FROM users WHERE username = ?1),
+        claimed_at = strftime('%Y-%m-%d %H:%M:%f', 'now'),
+        claim_round = (SELECT round_id FROM giveaway_rounds WHERE status = 'active')
+    WHERE id = (
+        SELECT id FROM keys WHERE claimed = FALSE AND revoked = FALSE AND pool = ?2 LIMIT 1
+    )
+    AND claimed = FALSE
+    AND EXISTS (SELECT 1 FROM giveaway_rounds WHERE status = 'active')
+    RETURNING id, key_val;"#,
+                user,
+                key_pool
+            )
+            .fetch_optional(&mut *transaction)
+            .await?;
+
+            let Some(key) = key else {
+                return Ok(None);
+            };
+
+            sqlx::query!(
+                r#"
+    INSERT INTO claims (key_id, user_id, round_id, pool, slot, waiver_reason)
+    VALUES (
+        ?1,
+        (SELECT id FROM users WHERE username = ?2),
+        (SELECT round_id FROM giveaway_rounds WHERE status = 'active'),
+        ?3,
+        NULL,
+        ?4
+    );"#,
+                key.id,
+                user,
+                key_pool,
+                reason
+            )
+            .execute(&mut *transaction)
+            .await?;
+
+            transaction.commit().await?;
+
+            Ok(Some(key.key_val))
+        })
+        .await?;
+
+        match key {
+            Some(key) => Ok(key),
+            None if !has_active_round(pool).await? => {
+                Err(ClaimError::NotEligible(Ineligible::NoRoundOpen))
+            }
+            None => Err(ClaimError::NoKeysLeft),
+        }
+    }
+
+    async fn claim_key_with_user(
+        &self,
+        user: &str,
+        key_pool: &str,
+        limits: &Limits,
+    ) -> Result<String, ClaimError> {
+        let pool = &self.pool;
+        add_user(pool, user).await?;
+        let round_limit = limits.round_limit();
+        let lifetime_limit = limits.lifetime_limit();
+        let cooldown = format!("-{} seconds", limits.cooldown);
+
+        let key = retry_busy(|| async {
+            let mut transaction = pool.begin().await?;
+
+            // the first statement takes sqlite's write lock, so the round check, the round, lifetime
+            // and cooldown limits, picking a key and claiming it can't interleave with another claim
+            // or the round being closed
+            let key = sqlx::query!(
+                r#"
+    UPDATE keys
+    SET claimed = TRUE,
+        user_claim = (SELECT id FROM users WHERE username = ?2),
+        claimed_at = strftime('%Y-%m-%d %H:%M:%f', 'now'),
+        claim_round = (SELECT round_id FROM giveaway_rounds WHERE status = 'active')
+    WHERE id = (
+        SELECT k.id
+        FROM keys k
+        WHERE k.claimed = FALSE
+        AND k.revoked = FALSE
+        AND k.pool = ?1
+        AND (
+            SELECT COUNT(*)
+            FROM claims c
+            INNER JOIN users u ON c.user_id = u.id
+            INNER JOIN giveaway_rounds gr ON c.round_id = gr.round_id
+            WHERE u.username = ?2
+            AND c.pool = ?1
+        AND c.revoked = FALSE
+            AND gr.status = 'active'
+        ) < ?3
+        AND (
+            SELECT COUNT(*)
+            FROM claims c
+            INNER JOIN users u ON c.user_id = u.id
+            WHERE u.username = ?2
+            AND c.pool = ?1
+        AND c.revoked = FALSE
+        ) < ?4
+        AND NOT EXISTS (
+            SELECT 1
+            FROM claims c
+            INNER JOIN users u ON c.user_id = u.id
+            WHERE u.username = ?2
+            AND c.pool = ?1
+        AND c.revoked = FALSE
+            AND c.claimed_at > strftime('%Y-%m-%d %H:%M:%f', 'now', ?5)
+        )
+        LIMIT 1
+    )
+    AND claimed = FALSE
+    AND EXISTS (SELECT 1 FROM giveaway_rounds WHERE status = 'active')
+    RETURNING id, key_val;"#,
+                key_pool,
+                user,
+                round_limit,
+                lifetime_limit,
+                cooldown
+            )
+            .fetch_optional(&mut *transaction)
+            .await?;
+
+            let Some(key) = key else {
+                return Ok(None);
+            };
+
+            // takes the lowest free slot, the unique constraint on claims rejects a second claim for
+            // the same slot even if the limit check above was bypassed
+            let claim = sqlx::query!(
+                r#"
+    WITH taken AS (
+        SELECT c.slot
+        FROM claims c
+        INNER JOIN users u ON c.user_id = u.id
+        INNER JOIN giveaway_rounds gr ON c.round_id = gr.round_id
+        WHERE u.username = ?2
+        AND c.pool = ?3
+        AND gr.status = 'active'
+        AND c.slot IS NOT NULL
+        AND c.revoked = FALSE
+    )
+    INSERT INTO claims (key_id, user_id, round_id, pool, slot)
+    VALUES (
+        ?1,
+        (SELECT id FROM users WHERE username = ?2),
+        (SELECT round_id FROM giveaway_rounds WHERE status = 'active'),
+        ?3,
+        (
+            SELECT MIN(t.slot + 1)
+            FROM (SELECT 0 AS slot UNION ALL SELECT slot FROM taken) t
+            WHERE t.slot + 1 NOT IN (SELECT slot FROM taken)
+        )
+    );"#,
+                key.id,
+                user,
+                key_pool
+            )
+            .execute(&mut *transaction)
+            .await;
+
+            match claim {
+                Ok(_) => {}
+                // dropping the transaction rolls the key back to unclaimed
+                Err(sqlx::Error::Database(e)) if e.is_unique_violation() => return Ok(None),
+                Err(e) => return Err(e),
+            }
+
+            transaction.commit().await?;
+
+            Ok(Some(key.key_val))
+        })
+        .await?;
+
+        let Some(key) = key else {
+            // no key was picked, because no round is open, the pool is empty or because of the limit
+            if !has_active_round(pool).await? {
+                return Err(ClaimError::NotEligible(Ineligible::NoRoundOpen));
+            }
+
+            let unclaimed = sqlx::query_scalar!(
+                r#"
+            SELECT COUNT(*) FROM keys WHERE claimed = FALSE AND revoked = FALSE AND pool = ?;
+            "#,
+                key_pool
+            )
+            .fetch_one(pool)
+            .await?;
+
+            return Err(if unclaimed > 0 {
+                ClaimError::AlreadyClaimed
+            } else {
+                ClaimError::NoKeysLeft
+            });
+        };
+
+        Ok(key)
+    }
+
+    async fn get_config_val(&self, key: &str) -> Result<String> {
+        let pool = &self.pool;
+        let val = sqlx::query!(
+            r#"
+            SELECT value FROM config WHERE key = ?;
+            "#,
+            key
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(val.value)
+    }
+
+    async fn config_vals(&self) -> Result<Vec<(String, String)>> {
+        let pool = &self.pool;
+        let values = sqlx::query!(
+            r#"
+            SELECT key AS "key!", value FROM config ORDER BY key;
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(values.into_iter().map(|v| (v.key, v.value)).collect())
+    }
+
+    async fn create_round(&self, name: &str, notes: Option<&str>) -> Result<i64> {
+        let pool = &self.pool;
+        let id = sqlx::query!(
+            r#"
+            INSERT INTO giveaway_rounds (status, name, notes) VALUES ('planned', ?, ?);
+            "#,
+            name,
+            notes
+        )
+        .execute(pool)
+        .await?
+        .last_insert_rowid();
+
+        Ok(id)
+    }
+
+    async fn open_round(&self, round: i64, force: bool) -> Result<Option<i64>> {
+        let pool = &self.pool;
+        let mut transaction = pool.begin().await?;
+
+        let status = sqlx::query_scalar!(
+            r#"
+            SELECT status FROM giveaway_rounds WHERE round_id = ?;
+            "#,
+            round
+        )
+        .fetch_optional(&mut *transaction)
+        .await?;
+
+        match status.as_deref() {
+            None => return Err(color_eyre::eyre::eyre!("Round {round} does not exist")),
+            Some("active") => return Ok(Some(round)),
+            Some("closed") if !force => {
+                return Err(color_eyre::eyre::eyre!(
+                    "Round {round} is closed, use force to open it again"
+                ))
+            }
+            _ => {}
+        }
+
+        let previous = sqlx::query_scalar!(
+            r#"
+    UPDATE giveaway_rounds
+    SET status = 'closed', ends_at = strftime('%Y-%m-%d %H:%M:%f', 'now')
+    WHERE status = 'active'
+    RETURNING round_id AS "round_id!: i64";"#
+        )
+        .fetch_optional(&mut *transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+    UPDATE giveaway_rounds
+    SET status = 'active', starts_at = COALESCE(starts_at, strftime('%Y-%m-%d %H:%M:%f', 'now')), ends_at = NULL
+    WHERE round_id = ?;"#,
+            round
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(previous)
+    }
+
+    async fn close_round(&self, round: i64) -> Result<()> {
+        let pool = &self.pool;
+        let closed = sqlx::query!(
+            r#"
+    UPDATE giveaway_rounds
+    SET status = 'closed', ends_at = strftime('%Y-%m-%d %H:%M:%f', 'now')
+    WHERE round_id = ? AND status != 'closed';"#,
+            round
+        )
+        .execute(pool)
+        .await?
+        .rows_affected();
+
+        if closed == 0 {
+            return Err(color_eyre::eyre::eyre!(
+                "Round {round} does not exist or is already closed"
+            ));
+        }
+
+        Ok(())
+    }
+
+    async fn list_rounds(&self) -> Result<Vec<Round>> {
+        let pool = &self.pool;
+        let rounds = sqlx::query_as!(
+            Round,
+            r#"
+    SELECT
+        round_id,
+        name,
+        status,
+        starts_at AS "starts_at?: DateTime<Utc>",
+        ends_at AS "ends_at?: DateTime<Utc>",
+        notes
+    FROM giveaway_rounds
+    ORDER BY round_id;"#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rounds)
+    }
+
+    async fn round_info(&self, round: i64) -> Result<Option<Round>> {
+        let pool = &self.pool;
+        let round = sqlx::query_as!(
+            Round,
+            r#"
+    SELECT
+        round_id,
+        name,
+        status,
+        starts_at AS "starts_at?: DateTime<Utc>",
+        ends_at AS "ends_at?: DateTime<Utc>",
+        notes
+    FROM giveaway_rounds
+    WHERE round_id = ?;"#,
+            round
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(round)
+    }
+
+    async fn round_claim_counts(&self, round: i64) -> Result<(i32, i32)> {
+        let pool = &self.pool;
+        let counts = sqlx::query!(
+            r#"
+    SELECT COUNT(*) AS "claims!: i32", COUNT(DISTINCT user_id) AS "users!: i32"
+    FROM claims
+    WHERE round_id = ?;"#,
+            round
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok((counts.claims, counts.users))
+    }
+
+    async fn get_round(&self) -> Result<Option<i64>> {
+        let pool = &self.pool;
+        let round = sqlx::query!(
+            r#"
+            SELECT round_id FROM giveaway_rounds WHERE status = 'active';
+            "#
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(round.map(|round| round.round_id))
+    }
+
+    async fn set_config_val(&self, key: &str, value: &str) -> Result<()> {
+        let pool = &self.pool;
+        sqlx::query!(
+            r#"
+            INSERT OR REPLACE INTO config (key, value) VALUES (?, ?);
+            "#,
+            key,
+            value
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_config_val(&self, key: &str) -> Result<()> {
+        let pool = &self.pool;
+        sqlx::query!(
+            r#"
+            DELETE FROM config WHERE key = ?;
+            "#,
+            key
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn claim_stats(&self, user: &str, key_pool: &str) -> Result<ClaimStats> {
+        let pool = &self.pool;
+        let stats = sqlx::query_as!(
+            ClaimStats,
+            r#"
+    SELECT
+        COUNT(CASE WHEN gr.status = 'active' THEN 1 END) AS "round!: i32",
+        COUNT(c.id) AS "lifetime!: i32",
+        MAX(c.claimed_at) AS "last_claimed_at?: DateTime<Utc>"
+    FROM claims c
+    INNER JOIN users u ON c.user_id = u.id
+    INNER JOIN giveaway_rounds gr ON c.round_id = gr.round_id
+    WHERE u.username = ?
+    AND c.pool = ?
+    AND c.revoked = FALSE;"#,
+            user,
+            key_pool
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(stats)
+    }
+
+    async fn save_round_report(&self, round: i64) -> Result<RoundReport> {
+        let pool = &self.pool;
+        let mut transaction = pool.begin().await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM round_report_pools WHERE round_id = ?;
+            "#,
+            round
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+    INSERT OR REPLACE INTO round_reports (round_id, keys_claimed, claimants, failed_deliveries)
+    VALUES (
+        ?1,
+        (SELECT COUNT(*) FROM claims WHERE round_id = ?1),
+        (SELECT COUNT(DISTINCT user_id) FROM claims WHERE round_id = ?1),
+        (SELECT COUNT(*) FROM events WHERE kind = 'failure' AND round_id = ?1 AND key_id IS NOT NULL)
+    );"#,
+            round
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+    INSERT INTO round_report_pools (round_id, pool, keys_claimed, remaining, exhausted_after)
+    SELECT
+        ?1,
+        p.pool,
+        p.keys_claimed,
+        p.remaining,
+        CASE WHEN p.remaining = 0 AND p.keys_claimed > 0 THEN
+            CAST(strftime('%s', p.last_claimed_at) AS INTEGER)
+            - CAST(strftime('%s', (SELECT starts_at FROM giveaway_rounds WHERE round_id = ?1)) AS INTEGER)
+        END
+    FROM (
+        SELECT
+            k.pool,
+            (SELECT COUNT(*) FROM claims c WHERE c.round_id = ?1 AND c.pool = k.pool) AS keys_claimed,
+            SUM(CASE WHEN k.claimed = FALSE AND k.revoked = FALSE THEN 1 ELSE 0 END) AS remaining,
+            (SELECT MAX(c.claimed_at) FROM claims c WHERE c.round_id = ?1 AND c.pool = k.pool)
+                AS last_claimed_at
+        FROM keys k
+        GROUP BY k.pool
+    ) p;"#,
+            round
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        transaction.commit().await?;
+
+        self.round_report(round)
+            .await?
+            .ok_or_else(|| color_eyre::eyre::eyre!("Report of round {round} was not saved"))
+    }
+
+    async fn round_report(&self, round: i64) -> Result<Option<RoundReport>> {
+        let pool = &self.pool;
+        let report = sqlx::query!(
+            r#"
+    SELECT
+        round_id,
+        keys_claimed AS "keys_claimed!: i32",
+        claimants AS "claimants!: i32",
+        failed_deliveries AS "failed_deliveries!: i32",
+        created_at AS "created_at!: DateTime<Utc>"
+    FROM round_reports
+    WHERE round_id = ?;"#,
+            round
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        let Some(report) = report else {
+            return Ok(None);
+        };
+
+        let pools = sqlx::query_as!(
+            PoolReport,
+            r#"
+    SELECT
+        pool,
+        keys_claimed AS "keys_claimed!: i32",
+        remaining AS "remaining!: i32",
+        exhausted_after
+    FROM round_report_pools
+    WHERE round_id = ?
+    ORDER BY pool;"#,
+            round
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(Some(RoundReport {
+            round_id: report.round_id,
+            keys_claimed: report.keys_claimed,
+            claimants: report.claimants,
+            failed_deliveries: report.failed_deliveries,
+            created_at: report.created_at,
+            pools,
+        }))
+    }
+
+    async fn pools(&self) -> Result<Vec<String>> {
+        let pool = &self.pool;
+        let pools = sqlx::query!(
+            r#"
+            SELECT DISTINCT pool FROM keys ORDER BY pool;
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(pools.into_iter().map(|p| p.pool).collect())
+    }
+
+    async fn pool_stats(&self) -> Result<Vec<PoolStats>> {
+        let pool = &self.pool;
+        let stats = sqlx::query_as!(
+            PoolStats,
+            r#"
+    SELECT
+        pool,
+        COUNT(*) AS "total!: i32",
+        SUM(CASE WHEN claimed = TRUE AND revoked = FALSE THEN 1 ELSE 0 END) AS "claimed!: i32",
+        SUM(CASE WHEN revoked = TRUE THEN 1 ELSE 0 END) AS "revoked!: i32",
+        SUM(CASE WHEN claimed = FALSE AND revoked = FALSE THEN 1 ELSE 0 END) AS "remaining!: i32"
+    FROM keys
+    GROUP BY pool
+    ORDER BY pool;"#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(stats)
+    }
+
+    async fn round_keys(&self, user: &str) -> Result<Vec<String>> {
+        let pool = &self.pool;
+        let keys = sqlx::query!(
+            r#"
+    SELECT k.key_val
+    FROM claims c
+    INNER JOIN keys k ON c.key_id = k.id
+    INNER JOIN users u ON c.user_id = u.id
+    INNER JOIN giveaway_rounds gr ON c.round_id = gr.round_id
+    WHERE u.username = ?
+    AND gr.status = 'active'
+    AND c.revoked = FALSE
+    ORDER BY c.id;"#,
+            user
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(keys.into_iter().map(|k| k.key_val).collect())
+    }
+
+    async fn recent_events(&self, kind: &str, target: &str, secs: u64) -> Result<i32> {
+        let pool = &self.pool;
+        let since = format!("-{secs} seconds");
+        let count = sqlx::query!(
+            r#"
+    SELECT COUNT(*) AS count
+    FROM events
+    WHERE kind = ?
+    AND target = ?
+    AND created_at > strftime('%Y-%m-%d %H:%M:%f', 'now', ?);"#,
+            kind,
+            target,
+            since
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(count.count)
+    }
+
+    async fn key_info(&self, key: &str) -> Result<Option<KeyInfo>> {
+        let pool = &self.pool;
+        let info = sqlx::query_as!(
+            KeyInfo,
+            r#"
+    SELECT
+        k.key_val,
+        k.pool,
+        u.username AS "username?",
+        k.claim_round AS round,
+        k.added_at AS "added_at?: DateTime<Utc>",
+        k.claimed_at AS "claimed_at?: DateTime<Utc>",
+        k.revoked,
+        (SELECT e.kind FROM events e WHERE e.key_id = k.id AND e.kind IN ('delivery', 'failure')
+            ORDER BY e.id DESC LIMIT 1) AS "last_delivery?: String",
+        (SELECT e.created_at FROM events e WHERE e.key_id = k.id AND e.kind IN ('delivery', 'failure')
+            ORDER BY e.id DESC LIMIT 1) AS "last_delivery_at?: DateTime<Utc>"
+    FROM keys k
+    LEFT JOIN users u ON k.user_claim = u.id
+    WHERE k.key_val = ?;"#,
+            key
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(info)
+    }
+
+    async fn all_keys(&self, key_pool: Option<&str>) -> Result<Vec<KeyInfo>> {
+        let pool = &self.pool;
+        let keys = sqlx::query_as!(
+            KeyInfo,
+            r#"
+    SELECT
+        k.key_val,
+        k.pool,
+        u.username AS "username?",
+        k.claim_round AS round,
+        k.added_at AS "added_at?: DateTime<Utc>",
+        k.claimed_at AS "claimed_at?: DateTime<Utc>",
+        k.revoked,
+        (SELECT e.kind FROM events e WHERE e.key_id = k.id AND e.kind IN ('delivery', 'failure')
+            ORDER BY e.id DESC LIMIT 1) AS "last_delivery?: String",
+        (SELECT e.created_at FROM events e WHERE e.key_id = k.id AND e.kind IN ('delivery', 'failure')
+            ORDER BY e.id DESC LIMIT 1) AS "last_delivery_at?: DateTime<Utc>"
+    FROM keys k
+    LEFT JOIN users u ON k.user_claim = u.id
+    WHERE ?1 IS NULL OR k.pool = ?1
+    ORDER BY k.id;"#,
+            key_pool
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(keys)
+    }
+
+    async fn user_keys(&self, user: &str) -> Result<Vec<KeyInfo>> {
+        let pool = &self.pool;
+        let keys = sqlx::query_as!(
+            KeyInfo,
+            r#"
+    SELECT
+        k.key_val,
+        k.pool,
+        u.username AS "username?",
+        k.claim_round AS round,
+        k.added_at AS "added_at?: DateTime<Utc>",
+        k.claimed_at AS "claimed_at?: DateTime<Utc>",
+        k.revoked,
+        (SELECT e.kind FROM events e WHERE e.key_id = k.id AND e.kind IN ('delivery', 'failure')
+            ORDER BY e.id DESC LIMIT 1) AS "last_delivery?: String",
+        (SELECT e.created_at FROM events e WHERE e.key_id = k.id AND e.kind IN ('delivery', 'failure')
+            ORDER BY e.id DESC LIMIT 1) AS "last_delivery_at?: DateTime<Utc>"
+    FROM keys k
+    INNER JOIN users u ON k.user_claim = u.id
+    WHERE u.username = ?
+    ORDER BY k.claimed_at, k.id;"#,
+            user
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(keys)
+    }
+
+    async fn revoke_key(&self, key: &str) -> Result<Option<String>> {
+        let pool = &self.pool;
+        let mut transaction = pool.begin().await?;
+
+        let owner = sqlx::query!(
+            r#"
+    SELECT u.username AS "username?"
+    FROM keys k
+    LEFT JOIN users u ON k.user_claim = u.id
+    WHERE k.key_val = ? AND k.revoked = FALSE;"#,
+            key
+        )
+        .fetch_optional(&mut *transaction)
+        .await?;
+
+        let Some(owner) = owner else {
+            return Err(color_eyre::eyre::eyre!(
+                "Key does not exist or is already revoked"
+            ));
+        };
+
+        sqlx::query!(
+            r#"
+            UPDATE keys SET revoked = TRUE WHERE key_val = ?;
+            "#,
+            key
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        // the key no longer counts towards the user's limits, the claim is kept for the reports
+        sqlx::query!(
+            r#"
+            UPDATE claims SET revoked = TRUE WHERE key_id = (SELECT id FROM keys WHERE key_val = ?);
+            "#,
+            key
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(owner.username)
+    }
+
+    async fn mark_low_stock(&self, key_pool: &str, remaining: i32) -> Result<bool> {
+        let pool = &self.pool;
+        let marked = sqlx::query!(
+            r#"
+            INSERT OR IGNORE INTO low_stock_alerts (pool, remaining) VALUES (?, ?);
+            "#,
+            key_pool,
+            remaining
+        )
+        .execute(pool)
+        .await?
+        .rows_affected();
+
+        Ok(marked > 0)
+    }
+
+    async fn clear_low_stock(&self, key_pool: &str) -> Result<()> {
+        let pool = &self.pool;
+        sqlx::query!(
+            r#"
+            DELETE FROM low_stock_alerts WHERE pool = ?;
+            "#,
+            key_pool
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn insert_event(
+        &self,
+        kind: &str,
+        actor: Option<&str>,
+        target: Option<&str>,
+        key: Option<&str>,
+        round: Option<i64>,
+        details: Option<&str>,
+    ) -> Result<i64> {
+        let pool = &self.pool;
+        let id = sqlx::query!(
+            r#"
+    INSERT INTO events (kind, actor, target, key_id, round_id, details)
+    VALUES (
+        ?,
+        ?,
+        ?,
+        (SELECT id FROM keys WHERE key_val = ?),
+        COALESCE(?, (SELECT round_id FROM giveaway_rounds WHERE status = 'active')),
+        ?
+    );"#,
+            kind,
+            actor,
+            target,
+            key,
+            round,
+            details
+        )
+        .execute(pool)
+        .await?
+        .last_insert_rowid();
+
+        Ok(id)
+    }
+
+    #[cfg(test)]
+    async fn execute(&self, sql: &str) -> Result<u64> {
+        Ok(sqlx::query(sql).execute(&self.pool).await?.rows_affected())
+    }
+}
@@ -0,0 +1,108 @@
+use poise::serenity_prelude as serenity;
+
+use crate::{limits::LimitReached, templates::Template};
+
+/// Why claiming or delivering a key failed
+///
+/// `Display` gives the full cause for admins and the audit log, users get the message of
+/// [`ClaimError::template`] instead, which never contains database or discord errors.
+#[derive(Debug)]
+pub enum ClaimError {
+    /// The pool has no unclaimed keys
+    NoKeysLeft,
+    /// The user already has as many keys in this round as they are allowed
+    AlreadyClaimed,
+    /// The user may not claim a key, e.g. because of a claim limit
+    NotEligible(Ineligible),
+    Database(sqlx::Error),
+    /// Anything else that went wrong on our side, e.g. reading the claim limits
+    Internal(color_eyre::Report),
+    /// The key was claimed but the DM with it could not be sent
+    DeliveryFailed(serenity::Error),
+}
+
+impl ClaimError {
+    /// The template of the message users see for this error
+    pub fn template(&self) -> Template {
+        match self {
+            ClaimError::NoKeysLeft => Template::NoKeysLeft,
+            ClaimError::AlreadyClaimed => Template::AlreadyClaimed,
+            ClaimError::NotEligible(_) => Template::NotEligible,
+            ClaimError::Database(_) | ClaimError::Internal(_) => Template::ClaimFailed,
+            ClaimError::DeliveryFailed(_) => Template::DeliveryFailed,
+        }
+    }
+
+    /// A name for the cause that never changes, used as a metrics label
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ClaimError::NoKeysLeft => "no_keys_left",
+            ClaimError::AlreadyClaimed => "already_claimed",
+            ClaimError::NotEligible(_) => "not_eligible",
+            ClaimError::Database(_) => "database",
+            ClaimError::Internal(_) => "internal",
+            ClaimError::DeliveryFailed(_) => "delivery_failed",
+        }
+    }
+
+    /// The template of a short description that is safe to show to users, used for the
+    /// `{reason}` placeholder
+    pub fn reason_template(&self) -> Template {
+        match self {
+            ClaimError::NoKeysLeft => Template::ReasonNoKeysLeft,
+            ClaimError::AlreadyClaimed => Template::ReasonAlreadyClaimed,
+            ClaimError::NotEligible(Ineligible::NoRoundOpen) => Template::ReasonNoRoundOpen,
+            ClaimError::NotEligible(Ineligible::LimitReached(_)) => Template::ReasonLimitReached,
+            ClaimError::Database(_) | ClaimError::Internal(_) => Template::ReasonInternal,
+            ClaimError::DeliveryFailed(_) => Template::ReasonDeliveryFailed,
+        }
+    }
+}
+
+/// Why a user may not claim a key right now
+#[derive(Debug, Clone, Copy)]
+pub enum Ineligible {
+    NoRoundOpen,
+    LimitReached(LimitReached),
+}
+
+impl std::fmt::Display for Ineligible {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Ineligible::NoRoundOpen => write!(f, "no round is open"),
+            Ineligible::LimitReached(reached) => write!(f, "{reached}"),
+        }
+    }
+}
+
+impl std::fmt::Display for ClaimError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClaimError::NoKeysLeft => write!(f, "no keys left in the pool"),
+            ClaimError::AlreadyClaimed => {
+                write!(f, "already claimed as many keys as allowed this round")
+            }
+            ClaimError::NotEligible(reason) => write!(f, "not eligible: {reason}"),
+            ClaimError::Database(e) => write!(f, "database error: {e}"),
+            ClaimError::Internal(e) => write!(f, "internal error: {e:#}"),
+            ClaimError::DeliveryFailed(e) => write!(f, "could not send the key as a DM: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ClaimError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClaimError::Database(e) => Some(e),
+            ClaimError::Internal(e) => Some(&**e),
+            ClaimError::DeliveryFailed(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<sqlx::Error> for ClaimError {
+    fn from(e: sqlx::Error) -> Self {
+        ClaimError::Database(e)
+    }
+}
@@ -0,0 +1,316 @@
+use std::{collections::HashMap, future::Future};
+
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::Result;
+use poise::serenity_prelude::{self as serenity, RoleId};
+use tokio::sync::Mutex;
+use tracing::error;
+
+use crate::{
+    audit::{self, Event, EventKind},
+    db::Store,
+    error::ClaimError,
+    limits::{LimitReached, Limits},
+    metrics::METRICS,
+    stock,
+    templates::{Language, Template},
+};
+
+/// Accounts younger than this many days can't claim a key if `age_bound` isn't set
+const DEFAULT_AGE_BOUND: i64 = 5;
+
+/// Someone who clicked the button of a giveaway post, real or simulated
+#[derive(Debug, Clone)]
+pub struct Claimant {
+    pub name: String,
+    /// How the user is addressed in messages, a mention on discord
+    pub mention: String,
+    pub created_at: DateTime<Utc>,
+    /// Whether the user has the role set with `/set_key_role`
+    pub has_role: bool,
+    /// Every role of the user, used for `claim_limit_roles`
+    pub roles: Vec<RoleId>,
+}
+
+/// What happened when a user tried to claim a key
+#[derive(Debug)]
+pub enum Outcome {
+    Claimed { key: String },
+    TooNew { age_bound: i64 },
+    MissingRole,
+    LimitReached(LimitReached),
+    Failed(ClaimError),
+}
+
+/// Claiming, eligibility and delivery of keys for one pool, without anything that needs a guild
+///
+/// The giveaway post drives this with the users that click its button, `keybot simulate` with
+/// synthetic users.
+pub struct Giveaway<'a> {
+    pub db: &'a dyn Store,
+    pub config: &'a Mutex<HashMap<String, String>>,
+    /// Used to post events to the log channel, `None` when the bot isn't connected
+    pub http: Option<&'a serenity::Http>,
+    pub key_pool: &'a str,
+}
+
+impl Giveaway<'_> {
+    async fn audit(&self, event: Event) {
+        match self.http {
+            Some(http) => audit::record(http, self.db, self.config, event).await,
+            None => audit::store(self.db, &event).await,
+        }
+    }
+
+    /// Renders `template` with the overrides for the pool of this giveaway
+    pub async fn render(
+        &self,
+        template: Template,
+        language: Language,
+        values: &[(&str, String)],
+    ) -> String {
+        template.render_in_pool(&*self.config.lock().await, language, self.key_pool, values)
+    }
+
+    async fn round(&self) -> String {
+        self.db
+            .get_round()
+            .await
+            .ok()
+            .flatten()
+            .map(|r| r.to_string())
+            .unwrap_or_default()
+    }
+
+    /// Works out the claim limits of a user with `roles`, and which of them is reached if any
+    pub async fn check_limits(
+        &self,
+        user: &str,
+        roles: &[RoleId],
+    ) -> Result<(Limits, Option<LimitReached>), ClaimError> {
+        let limits = Limits::from_config(&*self.config.lock().await, self.key_pool, roles);
+        let stats = self
+            .db
+            .claim_stats(user, self.key_pool)
+            .await
+            .map_err(ClaimError::Internal)?;
+
+        Ok((limits, limits.check(&stats)))
+    }
+
+    /// Checks whether `claimant` may have a key and claims one for them, every step is logged
+    pub async fn claim(&self, claimant: &Claimant) -> Outcome {
+        let age_bound = self
+            .config
+            .lock()
+            .await
+            .get("age_bound")
+            .and_then(|a| a.parse::<i64>().ok())
+            .unwrap_or(DEFAULT_AGE_BOUND);
+
+        if Utc::now()
+            .signed_duration_since(claimant.created_at)
+            .num_days()
+            <= age_bound
+        {
+            let event = Event::new(EventKind::Rejected)
+                .target(claimant.name.clone())
+                .details(format!("Account is younger than {age_bound} days"));
+            self.audit(event).await;
+            METRICS.failed("too_new");
+
+            return Outcome::TooNew { age_bound };
+        }
+
+        if !claimant.has_role {
+            let event = Event::new(EventKind::Rejected)
+                .target(claimant.name.clone())
+                .details("Missing the key role");
+            self.audit(event).await;
+            METRICS.failed("missing_role");
+
+            return Outcome::MissingRole;
+        }
+
+        let key = match self.check_limits(&claimant.name, &claimant.roles).await {
+            Ok((_, Some(reached))) => {
+                let event = Event::new(EventKind::Rejected)
+                    .target(claimant.name.clone())
+                    .details(format!("Claim limit reached: {reached}"));
+                self.audit(event).await;
+                METRICS.failed("limit_reached");
+
+                return Outcome::LimitReached(reached);
+            }
+            Ok((limits, None)) => {
+                self.db
+                    .claim_key_with_user(&claimant.name, self.key_pool, &limits)
+                    .await
+            }
+            Err(e) => {
+                // users only see "internal error", the details stay in the log
+                error!("Could not check the claim limits of {}: {e}", claimant.name);
+                Err(e)
+            }
+        };
+
+        match key {
+            Ok(key) => {
+                let event = Event::new(EventKind::Claim)
+                    .target(claimant.name.clone())
+                    .key(key.clone());
+                self.audit(event).await;
+                METRICS.claimed();
+                stock::check(self.http, self.db, self.config, self.key_pool).await;
+
+                Outcome::Claimed { key }
+            }
+            Err(e) => {
+                let event = Event::new(EventKind::Failure)
+                    .target(claimant.name.clone())
+                    .details(format!("Could not claim key: {e}"));
+                self.audit(event).await;
+                METRICS.failed(e.kind());
+
+                Outcome::Failed(e)
+            }
+        }
+    }
+
+    /// Renders the key_dm template and hands it to `send`, the result is logged as a delivery or
+    /// a failure
+    pub async fn deliver<F, Fut>(
+        &self,
+        user: &str,
+        mention: &str,
+        key: &str,
+        language: Language,
+        send: F,
+    ) -> Result<(), ClaimError>
+    where
+        F: FnOnce(String) -> Fut,
+        Fut: Future<Output = Result<(), serenity::Error>>,
+    {
+        // the key is already claimed at this point, a missing round number shouldn't stop it from
+        // being sent
+        let content = self
+            .render(
+                Template::KeyDm,
+                language,
+                &[
+                    ("key", key.to_owned()),
+                    ("user", mention.to_owned()),
+                    ("round", self.round().await),
+                ],
+            )
+            .await;
+
+        let sent = send(content).await;
+
+        let event = match &sent {
+            Ok(_) => Event::new(EventKind::Delivery),
+            Err(e) => {
+                METRICS.dm_failed();
+                Event::new(EventKind::Failure).details(format!("Could not send key as DM: {e}"))
+            }
+        };
+        self.audit(event.target(user).key(key)).await;
+
+        sent.map_err(ClaimError::DeliveryFailed)
+    }
+
+    /// Renders the message telling a user which limit they reached and when they can claim again
+    pub async fn render_limit(
+        &self,
+        reached: LimitReached,
+        mention: &str,
+        language: Language,
+    ) -> String {
+        let user = mention.to_owned();
+
+        match reached {
+            LimitReached::Round { claimed, limit } => {
+                let round = self.round().await;
+                self.render(
+                    Template::RoundLimitReached,
+                    language,
+                    &[
+                        ("user", user),
+                        ("claimed", claimed.to_string()),
+                        ("limit", limit.to_string()),
+                        ("round", round),
+                    ],
+                )
+                .await
+            }
+            LimitReached::Lifetime { claimed, limit } => {
+                self.render(
+                    Template::LifetimeLimitReached,
+                    language,
+                    &[
+                        ("user", user),
+                        ("claimed", claimed.to_string()),
+                        ("limit", limit.to_string()),
+                    ],
+                )
+                .await
+            }
+            LimitReached::Cooldown { until } => {
+                self.render(
+                    Template::ClaimCooldown,
+                    language,
+                    &[
+                        ("user", user),
+                        ("next_claim", format!("<t:{}:R>", until.timestamp())),
+                    ],
+                )
+                .await
+            }
+        }
+    }
+
+    /// The message a user gets when they didn't get a key, `None` if they did
+    ///
+    /// `role` is the role users need, as set with `/set_key_role`.
+    pub async fn rejection(
+        &self,
+        outcome: &Outcome,
+        claimant: &Claimant,
+        role: &str,
+        language: Language,
+    ) -> Option<String> {
+        let user = claimant.mention.clone();
+
+        let content = match outcome {
+            Outcome::Claimed { .. } => return None,
+            Outcome::TooNew { age_bound } => {
+                self.render(
+                    Template::AccountTooNew,
+                    language,
+                    &[("user", user), ("age_bound", age_bound.to_string())],
+                )
+                .await
+            }
+            Outcome::MissingRole => {
+                self.render(
+                    Template::MissingRole,
+                    language,
+                    &[("user", user), ("role", format!("<@&{role}>"))],
+                )
+                .await
+            }
+            Outcome::LimitReached(reached) => self.render_limit(*reached, &user, language).await,
+            Outcome::Failed(e) => {
+                let reason = self.render(e.reason_template(), language, &[]).await;
+                self.render(
+                    e.template(),
+                    language,
+                    &[("user", user), ("reason", reason)],
+                )
+                .await
+            }
+        };
+
+        Some(content)
+    }
+}
@@ -0,0 +1,112 @@
+use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use poise::serenity_prelude::{ConnectionStage, ShardManager};
+use tracing::{error, info};
+
+use crate::{
+    db::Db,
+    metrics::{ShardStatus, METRICS},
+};
+
+/// Serves `/healthz`, `/readyz` and `/metrics` on `addr`
+///
+/// `/healthz` answers as long as the process runs, `/readyz` only while the database is reachable
+/// and every shard is connected to the gateway.
+pub async fn serve(addr: SocketAddr, db: Db, manager: Arc<ShardManager>) {
+    let make_service = make_service_fn(move |_| {
+        let db = db.clone();
+        let manager = manager.clone();
+
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                handle(req, db.clone(), manager.clone())
+            }))
+        }
+    });
+
+    let server = match Server::try_bind(&addr) {
+        Ok(server) => server,
+        Err(e) => {
+            error!("Could not start the health endpoint on {addr}: {e}");
+            return;
+        }
+    };
+
+    info!("Health endpoint listening on http://{addr}");
+    if let Err(e) = server.serve(make_service).await {
+        error!("Health endpoint stopped: {e}");
+    }
+}
+
+async fn shard_statuses(manager: &ShardManager) -> Vec<ShardStatus> {
+    let mut shards = manager
+        .runners
+        .lock()
+        .await
+        .iter()
+        .map(|(id, runner)| ShardStatus {
+            id: id.0,
+            connected: runner.stage == ConnectionStage::Connected,
+            latency: runner.latency,
+        })
+        .collect::<Vec<_>>();
+    shards.sort_by_key(|s| s.id);
+
+    shards
+}
+
+fn text(status: StatusCode, body: String) -> Response<Body> {
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status;
+    response
+}
+
+async fn handle(
+    req: Request<Body>,
+    db: Db,
+    manager: Arc<ShardManager>,
+) -> Result<Response<Body>, Infallible> {
+    let response = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/healthz") => text(StatusCode::OK, "ok\n".to_owned()),
+        (&Method::GET, "/readyz") => {
+            let database = db.ping().await.is_ok();
+            let shards = shard_statuses(&manager).await;
+            let connected = shards.iter().filter(|s| s.connected).count();
+            let ready = database && connected > 0 && connected == shards.len();
+
+            let body = format!(
+                "database: {}\nshards: {connected}/{} connected\n",
+                if database { "ok" } else { "unreachable" },
+                shards.len()
+            );
+            let status = if ready {
+                StatusCode::OK
+            } else {
+                StatusCode::SERVICE_UNAVAILABLE
+            };
+            text(status, body)
+        }
+        (&Method::GET, "/metrics") => {
+            // a broken database shows up in /readyz, the other metrics are still worth serving
+            let pools = db.pool_stats().await.unwrap_or_default();
+            let shards = shard_statuses(&manager).await;
+
+            let mut response = text(StatusCode::OK, METRICS.render(&pools, &shards));
+            response.headers_mut().insert(
+                CONTENT_TYPE,
+                "text/plain; version=0.0.4"
+                    .parse()
+                    .expect("valid header value"),
+            );
+            response
+        }
+        _ => text(StatusCode::NOT_FOUND, "not found\n".to_owned()),
+    };
+
+    Ok(response)
+}
@@ -0,0 +1,335 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{eyre, Result};
+use poise::serenity_prelude::RoleId;
+
+use crate::{db::ClaimStats, settings};
+
+/// How many keys a user may claim from a pool, 0 means there is no limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub per_round: u32,
+    pub lifetime: u32,
+    /// Seconds a user has to wait between two claims
+    pub cooldown: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            per_round: 1,
+            lifetime: 0,
+            cooldown: 0,
+        }
+    }
+}
+
+/// Why a user can't claim another key right now
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitReached {
+    Round { claimed: i32, limit: u32 },
+    Lifetime { claimed: i32, limit: u32 },
+    Cooldown { until: DateTime<Utc> },
+}
+
+impl std::fmt::Display for LimitReached {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LimitReached::Round { claimed, limit } => {
+                write!(f, "claimed {claimed} of {limit} keys allowed this round")
+            }
+            LimitReached::Lifetime { claimed, limit } => {
+                write!(f, "claimed {claimed} of {limit} keys allowed in total")
+            }
+            LimitReached::Cooldown { until } => {
+                write!(f, "can claim again <t:{}:R>", until.timestamp())
+            }
+        }
+    }
+}
+
+/// An entry of the `claim_limit_roles` setting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RoleLimit {
+    pub role: u64,
+    pub per_round: u32,
+    pub lifetime: Option<u32>,
+}
+
+impl Limits {
+    /// The limits for a user with `roles` claiming from `key_pool`, taken from the `claim_limit_*`
+    /// settings, a setting for the pool like `claim_limit_per_round_vip` comes before the plain one
+    ///
+    /// If one of the roles has an override in `claim_limit_roles` it replaces the per round limit,
+    /// and the lifetime limit if the override has one. With several overrides the most generous wins.
+    pub fn from_config(
+        config: &HashMap<String, String>,
+        key_pool: &str,
+        roles: &[RoleId],
+    ) -> Limits {
+        let get = |key: &str| {
+            settings::pool_value(config, key, key_pool).and_then(|v| v.parse::<u64>().ok())
+        };
+        let defaults = Limits::default();
+
+        let mut limits = Limits {
+            per_round: get("claim_limit_per_round").map_or(defaults.per_round, |v| v as u32),
+            lifetime: get("claim_limit_lifetime").map_or(defaults.lifetime, |v| v as u32),
+            cooldown: get("claim_cooldown").unwrap_or(defaults.cooldown),
+        };
+
+        let overrides = settings::pool_value(config, "claim_limit_roles", key_pool)
+            .and_then(|v| parse_role_limits(v).ok())
+            .unwrap_or_default();
+
+        // 0 is no limit, so it has to sort above every other limit
+        let generosity = |limit: u32| if limit == 0 { u32::MAX } else { limit };
+        let best = overrides
+            .into_iter()
+            .filter(|o| roles.contains(&RoleId::new(o.role)))
+            .max_by_key(|o| generosity(o.per_round));
+
+        if let Some(best) = best {
+            limits.per_round = best.per_round;
+            if let Some(lifetime) = best.lifetime {
+                limits.lifetime = lifetime;
+            }
+        }
+
+        limits
+    }
+
+    /// The per round limit in the form the claim query takes it
+    pub fn round_limit(&self) -> i64 {
+        match self.per_round {
+            0 => i64::MAX,
+            limit => limit as i64,
+        }
+    }
+
+    /// The lifetime limit in the form the claim query takes it
+    pub fn lifetime_limit(&self) -> i64 {
+        match self.lifetime {
+            0 => i64::MAX,
+            limit => limit as i64,
+        }
+    }
+
+    /// Checks whether a user with `stats` may claim another key
+    pub fn check(&self, stats: &ClaimStats) -> Option<LimitReached> {
+        if self.lifetime > 0 && stats.lifetime >= self.lifetime as i32 {
+            return Some(LimitReached::Lifetime {
+                claimed: stats.lifetime,
+                limit: self.lifetime,
+            });
+        }
+
+        if self.per_round > 0 && stats.round >= self.per_round as i32 {
+            return Some(LimitReached::Round {
+                claimed: stats.round,
+                limit: self.per_round,
+            });
+        }
+
+        if self.cooldown > 0 {
+            let until = stats
+                .last_claimed_at
+                .map(|last| last + chrono::Duration::seconds(self.cooldown as i64));
+
+            if let Some(until) = until.filter(|until| *until > Utc::now()) {
+                return Some(LimitReached::Cooldown { until });
+            }
+        }
+
+        None
+    }
+}
+
+/// Parses `role=round[/lifetime]` entries separated by commas, e.g. `123=3, <@&456>=2/5`
+pub fn parse_role_limits(value: &str) -> Result<Vec<RoleLimit>> {
+    let mut limits = Vec::new();
+
+    for entry in value.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (role, limit) = entry
+            .split_once('=')
+            .ok_or_else(|| eyre!("`{entry}` must look like role=limit"))?;
+
+        let role = role.trim();
+        let role = role
+            .strip_prefix("<@&")
+            .and_then(|r| r.strip_suffix('>'))
+            .unwrap_or(role)
+            .parse::<u64>()
+            .ok()
+            .filter(|r| *r != 0)
+            .ok_or_else(|| eyre!("`{role}` is not a role id or mention"))?;
+
+        let (per_round, lifetime) = match limit.split_once('/') {
+            Some((per_round, lifetime)) => (per_round, Some(lifetime)),
+            None => (limit, None),
+        };
+        let parse = |v: &str| {
+            v.trim()
+                .parse::<u32>()
+                .map_err(|_| eyre!("`{}` is not a whole number", v.trim()))
+        };
+
+        limits.push(RoleLimit {
+            role,
+            per_round: parse(per_round)?,
+            lifetime: lifetime.map(parse).transpose()?,
+        });
+    }
+
+    Ok(limits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn stats(round: i32, lifetime: i32, seconds_ago: Option<i64>) -> ClaimStats {
+        ClaimStats {
+            round,
+            lifetime,
+            last_claimed_at: seconds_ago.map(|s| Utc::now() - chrono::Duration::seconds(s)),
+        }
+    }
+
+    #[test]
+    fn role_limits_parse_ids_mentions_and_lifetimes() {
+        assert_eq!(
+            parse_role_limits("123=3, <@&456>=2/5,").unwrap(),
+            vec![
+                RoleLimit {
+                    role: 123,
+                    per_round: 3,
+                    lifetime: None
+                },
+                RoleLimit {
+                    role: 456,
+                    per_round: 2,
+                    lifetime: Some(5)
+                },
+            ]
+        );
+        assert!(parse_role_limits("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn malformed_role_limits_are_rejected() {
+        for value in [
+            "123", "123:3", "abc=3", "0=3", "<@456>=3", "123=x", "123=-1", "123=3/", "123=3/x",
+        ] {
+            assert!(parse_role_limits(value).is_err(), "{value} was accepted");
+        }
+    }
+
+    #[test]
+    fn a_zero_limit_is_no_limit() {
+        let limits = Limits {
+            per_round: 0,
+            lifetime: 0,
+            cooldown: 0,
+        };
+
+        assert_eq!(limits.check(&stats(1000, 1000, Some(0))), None);
+        assert_eq!(limits.round_limit(), i64::MAX);
+        assert_eq!(limits.lifetime_limit(), i64::MAX);
+    }
+
+    #[test]
+    fn round_and_lifetime_limits_stop_claims_once_reached() {
+        let limits = Limits {
+            per_round: 2,
+            lifetime: 3,
+            cooldown: 0,
+        };
+
+        assert_eq!(limits.check(&stats(1, 1, None)), None);
+        assert_eq!(
+            limits.check(&stats(2, 2, None)),
+            Some(LimitReached::Round {
+                claimed: 2,
+                limit: 2
+            })
+        );
+        // the lifetime limit is checked first
+        assert_eq!(
+            limits.check(&stats(2, 3, None)),
+            Some(LimitReached::Lifetime {
+                claimed: 3,
+                limit: 3
+            })
+        );
+    }
+
+    #[test]
+    fn the_cooldown_ends_after_the_given_seconds() {
+        let limits = Limits {
+            per_round: 0,
+            lifetime: 0,
+            cooldown: 60,
+        };
+
+        assert!(matches!(
+            limits.check(&stats(1, 1, Some(55))),
+            Some(LimitReached::Cooldown { .. })
+        ));
+        assert_eq!(limits.check(&stats(1, 1, Some(61))), None);
+        assert_eq!(limits.check(&stats(0, 0, None)), None);
+    }
+
+    #[test]
+    fn role_limits_override_the_global_ones() {
+        let conf = config(&[
+            ("claim_limit_per_round", "1"),
+            ("claim_limit_lifetime", "2"),
+            ("claim_limit_roles", "10=3, 20=0/10, 30=2"),
+        ]);
+
+        let limits = Limits::from_config(&conf, "default", &[]);
+        assert_eq!((limits.per_round, limits.lifetime), (1, 2));
+
+        // an override without a lifetime limit keeps the global one
+        let limits = Limits::from_config(&conf, "default", &[RoleId::new(10)]);
+        assert_eq!((limits.per_round, limits.lifetime), (3, 2));
+
+        // 0 is no limit, so it beats every other override
+        let limits = Limits::from_config(&conf, "default", &[RoleId::new(10), RoleId::new(20)]);
+        assert_eq!((limits.per_round, limits.lifetime), (0, 10));
+
+        let limits = Limits::from_config(&conf, "default", &[RoleId::new(30), RoleId::new(99)]);
+        assert_eq!((limits.per_round, limits.lifetime), (2, 2));
+    }
+
+    #[test]
+    fn pool_limits_override_the_global_ones() {
+        let conf = config(&[
+            ("claim_limit_per_round", "1"),
+            ("claim_limit_per_round_vip", "4"),
+            ("claim_cooldown_vip", "30"),
+            ("claim_limit_roles", "10=3"),
+            ("claim_limit_roles_vip", "10=5/9"),
+        ]);
+
+        let limits = Limits::from_config(&conf, "default", &[]);
+        assert_eq!((limits.per_round, limits.cooldown), (1, 0));
+        let limits = Limits::from_config(&conf, "vip", &[]);
+        assert_eq!((limits.per_round, limits.cooldown), (4, 30));
+
+        // role overrides of the pool replace the plain ones
+        let limits = Limits::from_config(&conf, "default", &[RoleId::new(10)]);
+        assert_eq!((limits.per_round, limits.lifetime), (3, 0));
+        let limits = Limits::from_config(&conf, "vip", &[RoleId::new(10)]);
+        assert_eq!((limits.per_round, limits.lifetime), (5, 9));
+    }
+}
@@ -0,0 +1,120 @@
+use std::{fs::OpenOptions, path::Path, sync::Mutex};
+
+use clap::ValueEnum;
+use color_eyre::eyre::{eyre, Result};
+use poise::serenity_prelude as serenity;
+use tracing::{field::Empty, info_span, Instrument, Span};
+use tracing_subscriber::{
+    fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer, Registry,
+};
+
+/// Filter used when `RUST_LOG` isn't set
+const DEFAULT_FILTER: &str = "warn,keybot=info";
+
+/// How log lines are written
+#[derive(ValueEnum, Debug, Clone, Copy, Default)]
+pub enum LogFormat {
+    /// One line per event with the fields of every span it happened in
+    #[default]
+    Full,
+    /// Several lines per event, easy to read in a terminal
+    Pretty,
+    /// One short line per event
+    Compact,
+    /// One JSON object per event, for log collectors
+    Json,
+}
+
+fn layer<W>(format: LogFormat, writer: W, ansi: bool) -> Box<dyn Layer<Registry> + Send + Sync>
+where
+    W: for<'w> fmt::MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = fmt::layer().with_writer(writer).with_ansi(ansi);
+
+    match format {
+        LogFormat::Full => layer.boxed(),
+        LogFormat::Pretty => layer.pretty().boxed(),
+        LogFormat::Compact => layer.compact().boxed(),
+        LogFormat::Json => layer
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .boxed(),
+    }
+}
+
+/// Sets up logging to stdout in `format`, and to the end of `file` as well if one is given
+///
+/// The command line logs to stderr instead, so what it prints can be piped somewhere else.
+/// The filter is taken from `RUST_LOG`, without it the bot logs its own events from the info level
+/// and those of its dependencies from the warn level.
+pub fn init(format: LogFormat, file: Option<&Path>, stderr: bool) -> Result<()> {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER));
+
+    let mut layers = vec![if stderr {
+        layer(format, std::io::stderr, true)
+    } else {
+        layer(format, std::io::stdout, true)
+    }];
+    if let Some(file) = file {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(file)
+            .map_err(|e| eyre!("Could not open log file {}: {e}", file.display()))?;
+        layers.push(layer(format, Mutex::new(file), false));
+    }
+
+    tracing_subscriber::registry()
+        .with(layers)
+        .with(filter)
+        .try_init()?;
+
+    Ok(())
+}
+
+/// The span a command runs in, `command` and `round` are filled in by
+/// [`pre_command`](crate::commands::pre_command) once poise found the command
+fn command_span(user: serenity::UserId, guild: Option<serenity::GuildId>) -> Span {
+    info_span!(
+        "command",
+        user_id = %user,
+        guild_id = guild.map(|g| g.get()),
+        command = Empty,
+        round = Empty,
+    )
+}
+
+/// Wraps the poise framework so that every command runs in its own span
+///
+/// Poise has no hook that surrounds a whole command, so the span is created here for every event
+/// that can invoke one.
+pub struct Traced<F>(pub F);
+
+#[serenity::async_trait]
+impl<F: serenity::Framework> serenity::Framework for Traced<F> {
+    async fn init(&mut self, client: &serenity::Client) {
+        self.0.init(client).await;
+    }
+
+    async fn dispatch(&self, ctx: serenity::Context, event: serenity::FullEvent) {
+        let span = match &event {
+            serenity::FullEvent::InteractionCreate {
+                interaction:
+                    serenity::Interaction::Command(command)
+                    | serenity::Interaction::Autocomplete(command),
+            } => Some(command_span(command.user.id, command.guild_id)),
+            // could be a prefix command, the span stays without a command if it isn't one
+            serenity::FullEvent::Message { new_message } if !new_message.author.bot => {
+                Some(command_span(new_message.author.id, new_message.guild_id))
+            }
+            _ => None,
+        };
+
+        match span {
+            Some(span) => self.0.dispatch(ctx, event).instrument(span).await,
+            None => self.0.dispatch(ctx, event).await,
+        }
+    }
+}
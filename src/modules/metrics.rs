@@ -0,0 +1,220 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use poise::serenity_prelude as serenity;
+
+use crate::db::PoolStats;
+
+/// Upper bounds of the interaction latency buckets in seconds
+const LATENCY_BUCKETS: [f64; 8] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Counters since the bot started, served in the Prometheus text format by the health endpoint
+pub static METRICS: Metrics = Metrics::new();
+
+pub struct Metrics {
+    claims: AtomicU64,
+    dm_failures: AtomicU64,
+    /// Claims that were refused or failed, by reason
+    failures: Mutex<BTreeMap<&'static str, u64>>,
+    latency: Mutex<Histogram>,
+}
+
+struct Histogram {
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+/// How one shard is doing, read from the shard manager when the endpoint is asked
+#[derive(Debug, Clone)]
+pub struct ShardStatus {
+    pub id: u32,
+    pub connected: bool,
+    pub latency: Option<Duration>,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Self {
+            claims: AtomicU64::new(0),
+            dm_failures: AtomicU64::new(0),
+            failures: Mutex::new(BTreeMap::new()),
+            latency: Mutex::new(Histogram {
+                buckets: [0; LATENCY_BUCKETS.len()],
+                sum: 0.0,
+                count: 0,
+            }),
+        }
+    }
+
+    pub fn claimed(&self) {
+        self.claims.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A claim was refused or failed, `reason` is e.g. [`ClaimError::kind`](crate::error::ClaimError::kind)
+    pub fn failed(&self, reason: &'static str) {
+        *self
+            .failures
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(reason)
+            .or_default() += 1;
+    }
+
+    pub fn dm_failed(&self) {
+        self.dm_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// An interaction that was created at `created_at` has been answered
+    pub fn interaction_handled(&self, created_at: serenity::Timestamp) {
+        let latency = (chrono::Utc::now() - *created_at)
+            .to_std()
+            .unwrap_or_default()
+            .as_secs_f64();
+
+        let mut histogram = self.latency.lock().unwrap_or_else(|e| e.into_inner());
+        for (bucket, bound) in histogram.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if latency <= bound {
+                *bucket += 1;
+            }
+        }
+        histogram.sum += latency;
+        histogram.count += 1;
+    }
+
+    /// Renders every metric in the Prometheus text format
+    pub fn render(&self, pools: &[PoolStats], shards: &[ShardStatus]) -> String {
+        let mut out = String::new();
+
+        // writing to a String can't fail
+        let _ = writeln!(
+            out,
+            "# HELP keybot_claims_total Keys claimed since the bot started"
+        );
+        let _ = writeln!(out, "# TYPE keybot_claims_total counter");
+        let _ = writeln!(
+            out,
+            "keybot_claims_total {}",
+            self.claims.load(Ordering::Relaxed)
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP keybot_claim_failures_total Claims that were refused or failed, by reason"
+        );
+        let _ = writeln!(out, "# TYPE keybot_claim_failures_total counter");
+        for (reason, count) in self
+            .failures
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+        {
+            let _ = writeln!(
+                out,
+                "keybot_claim_failures_total{{reason=\"{reason}\"}} {count}"
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP keybot_dm_failures_total Claimed keys that could not be sent as a DM"
+        );
+        let _ = writeln!(out, "# TYPE keybot_dm_failures_total counter");
+        let _ = writeln!(
+            out,
+            "keybot_dm_failures_total {}",
+            self.dm_failures.load(Ordering::Relaxed)
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP keybot_keys_remaining Unclaimed keys that aren't revoked, by pool"
+        );
+        let _ = writeln!(out, "# TYPE keybot_keys_remaining gauge");
+        for pool in pools {
+            let _ = writeln!(
+                out,
+                "keybot_keys_remaining{{pool=\"{}\"}} {}",
+                escape(&pool.pool),
+                pool.remaining
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP keybot_interaction_latency_seconds Time from an interaction to the bot's answer"
+        );
+        let _ = writeln!(out, "# TYPE keybot_interaction_latency_seconds histogram");
+        {
+            let histogram = self.latency.lock().unwrap_or_else(|e| e.into_inner());
+            for (bucket, bound) in histogram.buckets.iter().zip(LATENCY_BUCKETS) {
+                let _ = writeln!(
+                    out,
+                    "keybot_interaction_latency_seconds_bucket{{le=\"{bound}\"}} {bucket}"
+                );
+            }
+            let _ = writeln!(
+                out,
+                "keybot_interaction_latency_seconds_bucket{{le=\"+Inf\"}} {}",
+                histogram.count
+            );
+            let _ = writeln!(
+                out,
+                "keybot_interaction_latency_seconds_sum {}",
+                histogram.sum
+            );
+            let _ = writeln!(
+                out,
+                "keybot_interaction_latency_seconds_count {}",
+                histogram.count
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP keybot_shard_connected Whether a shard is connected to the gateway"
+        );
+        let _ = writeln!(out, "# TYPE keybot_shard_connected gauge");
+        for shard in shards {
+            let _ = writeln!(
+                out,
+                "keybot_shard_connected{{shard=\"{}\"}} {}",
+                shard.id,
+                u8::from(shard.connected)
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP keybot_shard_latency_seconds Heartbeat latency of a shard"
+        );
+        let _ = writeln!(out, "# TYPE keybot_shard_latency_seconds gauge");
+        for shard in shards {
+            if let Some(latency) = shard.latency {
+                let _ = writeln!(
+                    out,
+                    "keybot_shard_latency_seconds{{shard=\"{}\"}} {}",
+                    shard.id,
+                    latency.as_secs_f64()
+                );
+            }
+        }
+
+        out
+    }
+}
+
+// label values are quoted, pool names come from the keys file and could contain anything
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -1,2 +1,16 @@
+pub mod audit;
+pub mod backup;
+pub mod cli;
 pub mod commands;
 pub mod db;
+pub mod error;
+pub mod giveaway;
+pub mod health;
+pub mod limits;
+pub mod logging;
+pub mod metrics;
+pub mod reports;
+pub mod settings;
+pub mod shutdown;
+pub mod stock;
+pub mod templates;
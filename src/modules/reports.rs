@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+
+use poise::serenity_prelude as serenity;
+use tokio::sync::Mutex;
+use tracing::error;
+
+use crate::{
+    db::{RoundReport, Store},
+    templates::{Language, Template},
+};
+
+/// Formats seconds as e.g. `2h 5m`, or `45s` below a minute
+pub fn format_duration(seconds: i64) -> String {
+    let (hours, minutes) = (seconds / 3600, seconds % 3600 / 60);
+
+    match (hours, minutes) {
+        (0, 0) => format!("{seconds}s"),
+        (0, minutes) => format!("{minutes}m"),
+        (hours, minutes) => format!("{hours}h {minutes}m"),
+    }
+}
+
+/// The report as an embed, `name` is the name of the round if it has one
+pub fn embed(
+    report: &RoundReport,
+    name: Option<&str>,
+    config: &HashMap<String, String>,
+    language: Language,
+) -> serenity::CreateEmbed {
+    let render =
+        |template: Template, values: &[(&str, String)]| template.render(config, language, values);
+
+    let round = ("round", report.round_id.to_string());
+    let title = match name {
+        Some(name) => render(
+            Template::ReportTitleNamed,
+            &[round, ("name", name.to_owned())],
+        ),
+        None => render(Template::ReportTitle, &[round]),
+    };
+
+    let mut embed = serenity::CreateEmbed::new()
+        .title(title)
+        .color(0x5865f2)
+        .field(
+            render(Template::ReportKeysClaimed, &[]),
+            report.keys_claimed.to_string(),
+            true,
+        )
+        .field(
+            render(Template::ReportClaimants, &[]),
+            report.claimants.to_string(),
+            true,
+        )
+        .field(
+            render(Template::ReportFailedDeliveries, &[]),
+            report.failed_deliveries.to_string(),
+            true,
+        )
+        .footer(serenity::CreateEmbedFooter::new(render(
+            Template::ReportTaken,
+            &[],
+        )))
+        .timestamp(report.created_at);
+
+    for pool in &report.pools {
+        let claimed = ("claimed", pool.keys_claimed.to_string());
+        let remaining = ("remaining", pool.remaining.to_string());
+        let value = match pool.exhausted_after {
+            Some(seconds) => render(
+                Template::ReportPoolRanOut,
+                &[claimed, remaining, ("duration", format_duration(seconds))],
+            ),
+            None => render(Template::ReportPoolKeys, &[claimed, remaining]),
+        };
+        embed = embed.field(
+            render(Template::ReportPool, &[("pool", pool.pool.clone())]),
+            value,
+            false,
+        );
+    }
+
+    embed
+}
+
+/// Stores the report of a round that was just closed and posts it to the report channel
+///
+/// The report goes to `report_channel_id`, or to `log_channel_id` if that isn't set. Like the audit
+/// log, errors are logged and otherwise ignored.
+pub async fn publish(
+    http: &serenity::Http,
+    db: &dyn Store,
+    config: &Mutex<HashMap<String, String>>,
+    round: i64,
+) {
+    let report = match db.save_round_report(round).await {
+        Ok(report) => report,
+        Err(e) => {
+            error!("Could not save the report of round {round}: {e}");
+            return;
+        }
+    };
+
+    let channel = {
+        let config = config.lock().await;
+        config
+            .get("report_channel_id")
+            .or_else(|| config.get("log_channel_id"))
+            .and_then(|c| c.parse::<u64>().ok())
+    };
+
+    let Some(channel) = channel else {
+        return;
+    };
+
+    let name = db
+        .round_info(round)
+        .await
+        .ok()
+        .flatten()
+        .and_then(|r| r.name);
+    // the report channel is shared, so it uses the default language rather than anyone's own
+    let embed = {
+        let config = config.lock().await;
+        let language = Language::from_config(&config).unwrap_or_default();
+        embed(&report, name.as_deref(), &config, language)
+    };
+    let msg = serenity::CreateMessage::new().embed(embed);
+
+    if let Err(e) = serenity::ChannelId::new(channel)
+        .send_message(http, msg)
+        .await
+    {
+        error!("Could not post the report of round {round}: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_are_formatted() {
+        for (seconds, expected) in [
+            (0, "0s"),
+            (45, "45s"),
+            (59, "59s"),
+            (60, "1m"),
+            (61, "1m"),
+            (3599, "59m"),
+            (3600, "1h 0m"),
+            (7500, "2h 5m"),
+            (90000, "25h 0m"),
+        ] {
+            assert_eq!(format_duration(seconds), expected, "{seconds}");
+        }
+    }
+}
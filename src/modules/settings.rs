@@ -0,0 +1,626 @@
+use std::{collections::HashMap, sync::Arc, time::SystemTime};
+
+use color_eyre::eyre::{eyre, Result};
+use config::{Config, FileFormat};
+use poise::serenity_prelude as serenity;
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+
+use crate::{
+    audit::{self, Event, EventKind},
+    db::{Db, Store},
+    limits::parse_role_limits,
+    shutdown::Shutdown,
+    stock::parse_thresholds,
+    templates::{Language, Scope, Template},
+};
+
+pub const CONFIG_FILE: &str = "config.json5";
+
+const DEFAULT_CONFIG: &[u8] = br#"{
+  // Default key giveaway duration in seconds
+  // This can be overridden by the giveaway_duration argument
+  giveaway_duration: 3600,
+  // The age of the account required to claim a key
+  // given in days
+  age_bound: 5
+}"#;
+
+/// A config value that can be changed at runtime with `/config set`
+pub struct Setting {
+    pub key: &'static str,
+    pub description: &'static str,
+    validate: fn(&str) -> Result<String>,
+    /// Whether the setting can also be set for a single pool as `<key>_<pool>`
+    pub per_pool: bool,
+}
+
+impl Setting {
+    /// Checks that `value` is valid for this setting and returns it in the form it should be stored
+    pub fn validate(&self, value: &str) -> Result<String> {
+        (self.validate)(value.trim())
+    }
+}
+
+pub const SETTINGS: &[Setting] = &[
+    Setting {
+        key: "age_bound",
+        description: "The age of the account required to claim a key, given in days",
+        validate: validate_age_bound,
+        per_pool: false,
+    },
+    Setting {
+        key: "giveaway_duration",
+        description: "Default key giveaway duration in seconds",
+        validate: validate_giveaway_duration,
+        per_pool: false,
+    },
+    Setting {
+        key: "role_id",
+        description: "Role users need to claim a key",
+        validate: validate_role_id,
+        per_pool: false,
+    },
+    Setting {
+        key: "default_locale",
+        description:
+            "Language used when neither the user's nor the server's locale is known (en, de or es)",
+        validate: validate_locale,
+        per_pool: false,
+    },
+    Setting {
+        key: "claim_limit_per_round",
+        description: "Keys a user can claim from a pool in one round, 0 for no limit",
+        validate: validate_limit,
+        per_pool: true,
+    },
+    Setting {
+        key: "claim_limit_lifetime",
+        description: "Keys a user can claim from a pool over all rounds, 0 for no limit",
+        validate: validate_limit,
+        per_pool: true,
+    },
+    Setting {
+        key: "claim_cooldown",
+        description: "Seconds a user has to wait between two claims from a pool, 0 to not wait",
+        validate: validate_claim_cooldown,
+        per_pool: true,
+    },
+    Setting {
+        key: "claim_limit_roles",
+        description:
+            "Limits for members of a role as `role=per_round[/lifetime]`, separated by commas",
+        validate: validate_role_limits,
+        per_pool: true,
+    },
+    Setting {
+        key: "resend_cooldown",
+        description: "Seconds a user has to wait between two resends of their key, 0 to not limit",
+        validate: validate_resend_cooldown,
+        per_pool: false,
+    },
+    Setting {
+        key: "log_channel_id",
+        description:
+            "Channel every key, round and config event is posted to, `none` to turn it off",
+        validate: validate_channel_id,
+        per_pool: false,
+    },
+    Setting {
+        key: "report_channel_id",
+        description: "Channel the summary of a round is posted to when it closes, defaults to the log channel, `none` to turn it off",
+        validate: validate_channel_id,
+        per_pool: false,
+    },
+    Setting {
+        key: "low_stock_thresholds",
+        description: "Alert when a pool has fewer keys left than this, as `pool=threshold` separated by commas",
+        validate: validate_thresholds,
+        per_pool: false,
+    },
+    Setting {
+        key: "low_stock_channel_id",
+        description: "Channel low-stock alerts are posted to, defaults to the log channel, `none` to turn them off",
+        validate: validate_channel_id,
+        per_pool: false,
+    },
+    Setting {
+        key: "low_stock_role_id",
+        description: "Role pinged by low-stock alerts, `none` to not ping anyone",
+        validate: validate_alert_role,
+        per_pool: false,
+    },
+    Setting {
+        key: "giveaway_key_cap",
+        description: "Keys a giveaway post hands out at most, 0 for no cap",
+        validate: validate_limit,
+        per_pool: false,
+    },
+    Setting {
+        key: "backup_dir",
+        description: "Directory database backups are written to, defaults to `backups`",
+        validate: validate_backup_dir,
+        per_pool: false,
+    },
+    Setting {
+        key: "backup_interval_hours",
+        description: "Hours between two scheduled database backups, 0 to turn them off",
+        validate: validate_backup_interval,
+        per_pool: false,
+    },
+    Setting {
+        key: "backup_keep",
+        description: "Backups kept of each kind, older ones are removed",
+        validate: validate_backup_keep,
+        per_pool: false,
+    },
+    Setting {
+        key: "embed_title",
+        description: "Title of the giveaway embed",
+        validate: validate_embed_title,
+        per_pool: true,
+    },
+    Setting {
+        key: "embed_description",
+        description: "Text shown in the giveaway embed",
+        validate: validate_embed_description,
+        per_pool: true,
+    },
+    Setting {
+        key: "embed_image",
+        description: "Image URL of the giveaway embed, `none` to not show an image",
+        validate: validate_image_url,
+        per_pool: true,
+    },
+    Setting {
+        key: "embed_thumbnail",
+        description: "Thumbnail URL of the giveaway embed, `none` to not show a thumbnail",
+        validate: validate_image_url,
+        per_pool: true,
+    },
+    Setting {
+        key: "embed_color",
+        description: "Color of the giveaway embed as a hex code, e.g. `#1b2838`",
+        validate: validate_color,
+        per_pool: true,
+    },
+    Setting {
+        key: "embed_footer",
+        description: "Footer text of the giveaway embed",
+        validate: validate_embed_footer,
+        per_pool: true,
+    },
+];
+
+/// Finds the setting of `key`, a pool-scoped key like `embed_title_vip` finds `embed_title`
+pub fn find(key: &str) -> Option<&'static Setting> {
+    SETTINGS
+        .iter()
+        .find(|s| s.key == key)
+        .or_else(|| split_pool_key(key).map(|(setting, _)| setting))
+}
+
+/// Splits a pool-scoped key like `embed_title_vip` into its setting and the pool
+pub fn split_pool_key(key: &str) -> Option<(&'static Setting, &str)> {
+    SETTINGS.iter().filter(|s| s.per_pool).find_map(|s| {
+        let key_pool = key.strip_prefix(s.key)?.strip_prefix('_')?;
+        (!key_pool.is_empty()).then_some((s, key_pool))
+    })
+}
+
+/// The value of `key` for `key_pool`, `<key>_<pool>` if it is set and `key` otherwise
+pub fn pool_value<'a>(
+    config: &'a HashMap<String, String>,
+    key: &str,
+    key_pool: &str,
+) -> Option<&'a String> {
+    config
+        .get(&format!("{key}_{key_pool}"))
+        .or_else(|| config.get(key))
+}
+
+/// Every key that can be changed with `/config set`, this includes the message templates
+pub fn known_keys() -> impl Iterator<Item = String> {
+    SETTINGS
+        .iter()
+        .map(|s| s.key.to_owned())
+        .chain(Template::ALL.iter().flat_map(|&t| {
+            std::iter::once(t.config_key(Scope::All)).chain(
+                Language::ALL
+                    .iter()
+                    .map(move |&l| t.config_key(Scope::Language(l))),
+            )
+        }))
+}
+
+/// Puts the values changed with `/config set` over `config`, they take precedence over the file
+pub async fn apply_db_overrides(db: &dyn Store, config: &mut HashMap<String, String>) {
+    let values = match db.config_vals().await {
+        Ok(values) => values,
+        Err(e) => {
+            warn!("Could not read the stored config values: {e}");
+            return;
+        }
+    };
+
+    for (key, value) in values {
+        if is_known(&key) {
+            config.insert(key, value);
+        }
+    }
+}
+
+/// The config the bot would start with, the config file with the values changed with `/config set`
+/// on top, used by the command line
+pub async fn load(db: &dyn Store) -> Result<HashMap<String, String>> {
+    let mut config = if std::path::Path::new(CONFIG_FILE).exists() {
+        load_file(CONFIG_FILE)?
+    } else {
+        HashMap::new()
+    };
+    apply_db_overrides(db, &mut config).await;
+
+    Ok(config)
+}
+
+/// Whether `key` can be changed with `/config set`, this includes the message templates
+pub fn is_known(key: &str) -> bool {
+    find(key).is_some() || Template::from_config_key(key).is_some()
+}
+
+/// Checks a config value and returns it in the form it should be stored
+pub fn validate(key: &str, value: &str) -> Result<String> {
+    if let Some(setting) = find(key) {
+        return setting.validate(value);
+    }
+
+    if let Some((template, _)) = Template::from_config_key(key) {
+        return template.validate(value);
+    }
+
+    Err(eyre!("unknown setting {key}"))
+}
+
+fn validate_age_bound(value: &str) -> Result<String> {
+    let days = value
+        .parse::<i64>()
+        .map_err(|_| eyre!("age_bound must be a whole number of days"))?;
+
+    if days < 0 {
+        return Err(eyre!("age_bound can not be negative"));
+    }
+
+    Ok(days.to_string())
+}
+
+fn validate_giveaway_duration(value: &str) -> Result<String> {
+    let secs = value
+        .parse::<u64>()
+        .map_err(|_| eyre!("giveaway_duration must be a whole number of seconds"))?;
+
+    if secs == 0 {
+        return Err(eyre!("giveaway_duration must be at least 1 second"));
+    }
+
+    Ok(secs.to_string())
+}
+
+fn validate_limit(value: &str) -> Result<String> {
+    let limit = value
+        .parse::<u32>()
+        .map_err(|_| eyre!("must be a whole number, 0 for no limit"))?;
+
+    Ok(limit.to_string())
+}
+
+fn validate_claim_cooldown(value: &str) -> Result<String> {
+    let secs = value
+        .parse::<u64>()
+        .map_err(|_| eyre!("claim_cooldown must be a whole number of seconds"))?;
+
+    Ok(secs.to_string())
+}
+
+fn validate_role_limits(value: &str) -> Result<String> {
+    let limits = parse_role_limits(value)?;
+
+    Ok(limits
+        .iter()
+        .map(|l| match l.lifetime {
+            Some(lifetime) => format!("{}={}/{lifetime}", l.role, l.per_round),
+            None => format!("{}={}", l.role, l.per_round),
+        })
+        .collect::<Vec<_>>()
+        .join(","))
+}
+
+fn validate_resend_cooldown(value: &str) -> Result<String> {
+    let secs = value
+        .parse::<u64>()
+        .map_err(|_| eyre!("resend_cooldown must be a whole number of seconds"))?;
+
+    Ok(secs.to_string())
+}
+
+fn validate_thresholds(value: &str) -> Result<String> {
+    let thresholds = parse_thresholds(value)?;
+
+    Ok(thresholds
+        .iter()
+        .map(|(key_pool, threshold)| format!("{key_pool}={threshold}"))
+        .collect::<Vec<_>>()
+        .join(","))
+}
+
+fn validate_backup_dir(value: &str) -> Result<String> {
+    if value.is_empty() {
+        return Err(eyre!("backup_dir can not be empty"));
+    }
+
+    Ok(value.to_owned())
+}
+
+fn validate_backup_interval(value: &str) -> Result<String> {
+    let hours = value
+        .parse::<u64>()
+        .map_err(|_| eyre!("backup_interval_hours must be a whole number of hours"))?;
+
+    Ok(hours.to_string())
+}
+
+fn validate_backup_keep(value: &str) -> Result<String> {
+    let keep = value
+        .parse::<u32>()
+        .map_err(|_| eyre!("backup_keep must be a whole number"))?;
+
+    if keep == 0 {
+        return Err(eyre!("backup_keep must be at least 1"));
+    }
+
+    Ok(keep.to_string())
+}
+
+fn validate_alert_role(value: &str) -> Result<String> {
+    if value == "none" {
+        return Ok(value.to_owned());
+    }
+
+    validate_role_id(value)
+}
+
+// accepts both a raw role id and a role mention like `<@&123>`
+fn validate_role_id(value: &str) -> Result<String> {
+    let id = value
+        .strip_prefix("<@&")
+        .and_then(|v| v.strip_suffix('>'))
+        .unwrap_or(value);
+
+    match id.parse::<u64>() {
+        Ok(id) if id != 0 => Ok(id.to_string()),
+        _ => Err(eyre!("role_id must be a role id or a role mention")),
+    }
+}
+
+// accepts both a raw channel id and a channel mention like `<#123>`
+fn validate_channel_id(value: &str) -> Result<String> {
+    if value == "none" {
+        return Ok(value.to_owned());
+    }
+
+    let id = value
+        .strip_prefix("<#")
+        .and_then(|v| v.strip_suffix('>'))
+        .unwrap_or(value);
+
+    match id.parse::<u64>() {
+        Ok(id) if id != 0 => Ok(id.to_string()),
+        _ => Err(eyre!("must be a channel id, a channel mention or `none`")),
+    }
+}
+
+fn validate_locale(value: &str) -> Result<String> {
+    match Language::from_locale(value) {
+        Some(language) => Ok(language.code().to_owned()),
+        None => Err(eyre!(
+            "must be one of {}",
+            Language::ALL
+                .iter()
+                .map(|l| l.code())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+fn validate_length(value: &str, max: usize) -> Result<String> {
+    if value.chars().count() > max {
+        return Err(eyre!("can be at most {max} characters long"));
+    }
+
+    Ok(value.replace("\\n", "\n"))
+}
+
+// limits are the ones discord puts on embeds
+fn validate_embed_title(value: &str) -> Result<String> {
+    validate_length(value, 256)
+}
+
+fn validate_embed_description(value: &str) -> Result<String> {
+    validate_length(value, 4096)
+}
+
+fn validate_embed_footer(value: &str) -> Result<String> {
+    validate_length(value, 2048)
+}
+
+fn validate_image_url(value: &str) -> Result<String> {
+    if value == "none" || value.starts_with("https://") || value.starts_with("http://") {
+        Ok(value.to_owned())
+    } else {
+        Err(eyre!("must be an http(s) URL or `none`"))
+    }
+}
+
+fn validate_color(value: &str) -> Result<String> {
+    Ok(format!("#{:06x}", parse_color(value)?))
+}
+
+/// Parses a hex color like `#1b2838`, `0x1b2838` or `1b2838`
+pub fn parse_color(value: &str) -> Result<u32> {
+    let hex = value
+        .trim_start_matches('#')
+        .trim_start_matches("0x")
+        .trim_start_matches("0X");
+
+    match u32::from_str_radix(hex, 16) {
+        Ok(color) if hex.len() == 6 => Ok(color),
+        _ => Err(eyre!("must be a hex color code like #1b2838")),
+    }
+}
+
+pub fn create_default_file(path: &str) -> Result<()> {
+    std::fs::write(path, DEFAULT_CONFIG)?;
+
+    Ok(())
+}
+
+/// Reads the config file and validates every known setting in it, unknown keys are logged as they
+/// are most likely typos
+pub fn load_file(path: &str) -> Result<HashMap<String, String>> {
+    let mut values = Config::builder()
+        .set_default("age_bound", 5)?
+        .set_default("giveaway_duration", 3600)?
+        .add_source(config::File::new(path, FileFormat::Json5))
+        .build()?
+        .try_deserialize::<HashMap<String, String>>()?;
+
+    for (key, value) in values.iter_mut() {
+        if key.starts_with("template_") && Template::from_config_key(key).is_none() {
+            return Err(eyre!("Unknown template {key}"));
+        }
+
+        if is_known(key) {
+            *value = validate(key, value).map_err(|e| eyre!("Invalid value for {key}: {e}"))?;
+        } else {
+            warn!("Unknown setting {key} in {path}, it is not used");
+        }
+    }
+
+    Ok(values)
+}
+
+fn modified_at(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Checks the config file for changes every few seconds and applies them to the running bot
+///
+/// `file_values` is what the file contained when the bot started, only keys that change in the file
+/// are applied, so values set with `/config set` are kept until the same key is edited in the file.
+/// An invalid file is logged and ignored, and the previous config stays in use. Stops once a
+/// shutdown is requested.
+pub async fn watch_file(
+    path: String,
+    db: Db,
+    config: Arc<Mutex<HashMap<String, String>>>,
+    http: Arc<serenity::Http>,
+    mut file_values: HashMap<String, String>,
+    shutdown: Arc<Shutdown>,
+) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(5));
+    let mut last_modified = modified_at(&path);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.requested() => break,
+        }
+
+        let modified = modified_at(&path);
+        if modified.is_none() || modified == last_modified {
+            continue;
+        }
+        // a change that is being applied is finished before the bot stops
+        let Some(_running) = shutdown.start() else {
+            break;
+        };
+        last_modified = modified;
+
+        let new_values = match load_file(&path) {
+            Ok(values) => values,
+            Err(e) => {
+                error!("Ignoring invalid change to {path}, keeping the previous config: {e}");
+                continue;
+            }
+        };
+
+        let changed = new_values
+            .iter()
+            .filter(|(key, value)| file_values.get(*key) != Some(value))
+            .collect::<Vec<_>>();
+        let removed = file_values
+            .keys()
+            .filter(|key| !new_values.contains_key(*key))
+            .collect::<Vec<_>>();
+
+        if changed.is_empty() && removed.is_empty() {
+            continue;
+        }
+
+        let mut updates = Vec::new();
+
+        for (key, value) in &changed {
+            // the file is now the newest source for this key, so drop any value set with `/config set`
+            if let Err(e) = db.delete_config_val(key).await {
+                warn!("Could not remove stored value for {key}: {e}");
+            }
+            updates.push((key.to_string(), Some(value.to_string())));
+        }
+
+        for key in &removed {
+            updates.push((key.to_string(), db.get_config_val(key).await.ok()));
+        }
+
+        // apply every change under one lock so commands never see a half applied config
+        let mut conf = config.lock().await;
+
+        let mut diff = Vec::new();
+        for (key, value) in updates {
+            let old = match &value {
+                Some(value) => conf.insert(key.clone(), value.clone()),
+                None => conf.remove(&key),
+            };
+            let line = format!(
+                "{key} changed from {} to {}",
+                old.as_deref().unwrap_or("not set"),
+                value.as_deref().unwrap_or("not set")
+            );
+            info!("Config {line} in {path}");
+            diff.push(line);
+        }
+
+        drop(conf);
+
+        let event = Event::new(EventKind::ConfigChange)
+            .details(format!("{path} was edited\n{}", diff.join("\n")));
+        audit::record(&http, &*db, &config, event).await;
+        file_values = new_values;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pool_keys_are_only_known_for_pool_settings() {
+        let (setting, key_pool) = split_pool_key("embed_title_closed_beta").unwrap();
+        assert_eq!((setting.key, key_pool), ("embed_title", "closed_beta"));
+
+        assert!(is_known("embed_image_vip"));
+        assert!(!is_known("embed_image_"));
+        assert!(!is_known("role_id_vip"));
+        assert!(validate("embed_color_vip", "1b2838").is_ok());
+        assert!(validate("embed_image_vip", "not a url").is_err());
+    }
+}
@@ -0,0 +1,125 @@
+use std::{sync::Arc, time::Duration};
+
+use poise::serenity_prelude as serenity;
+use tokio::sync::watch;
+use tracing::{debug, info, warn};
+
+/// Tells running work that the bot is stopping and waits until that work has wrapped up
+///
+/// Work that shouldn't be cut off halfway, like a command or reading the keys file, holds a
+/// [`Running`] guard while it runs. Giveaway posts watch [`Shutdown::requested`] to pause
+/// themselves.
+pub struct Shutdown {
+    requested: watch::Sender<bool>,
+    running: watch::Sender<usize>,
+}
+
+/// Keeps the shutdown waiting until it is dropped
+pub struct Running(Arc<Shutdown>);
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.0.running.send_modify(|n| *n -= 1);
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            requested: watch::Sender::new(false),
+            running: watch::Sender::new(0),
+        })
+    }
+
+    pub fn is_requested(&self) -> bool {
+        *self.requested.borrow()
+    }
+
+    /// Completes once the bot is stopping
+    pub async fn requested(&self) {
+        // the sender lives in `self`, so waiting can't fail
+        let _ = self.requested.subscribe().wait_for(|r| *r).await;
+    }
+
+    /// Marks work as running, `None` if the bot is already stopping and no new work should start
+    pub fn start(self: &Arc<Self>) -> Option<Running> {
+        if self.is_requested() {
+            return None;
+        }
+        self.running.send_modify(|n| *n += 1);
+
+        Some(Running(self.clone()))
+    }
+
+    /// Asks running work to stop and waits up to `timeout` for it to finish, returns whether it did
+    pub async fn stop(&self, timeout: Duration) -> bool {
+        self.requested.send_replace(true);
+
+        let mut running = self.running.subscribe();
+        let finished = tokio::time::timeout(timeout, running.wait_for(|n| *n == 0)).await;
+
+        finished.is_ok()
+    }
+}
+
+/// Completes on Ctrl-C, or on SIGTERM on unix
+pub async fn signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            warn!("Could not listen for Ctrl-C: {e}");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                warn!("Could not listen for SIGTERM: {e}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => info!("Received Ctrl-C"),
+        _ = terminate => info!("Received SIGTERM"),
+    }
+}
+
+/// Wraps a framework so that every command holds a [`Running`] guard until it is done, commands
+/// sent while the bot is stopping are ignored
+pub struct Tracked<F> {
+    pub framework: F,
+    pub shutdown: Arc<Shutdown>,
+}
+
+#[serenity::async_trait]
+impl<F: serenity::Framework> serenity::Framework for Tracked<F> {
+    async fn init(&mut self, client: &serenity::Client) {
+        self.framework.init(client).await;
+    }
+
+    async fn dispatch(&self, ctx: serenity::Context, event: serenity::FullEvent) {
+        let command = matches!(
+            event,
+            serenity::FullEvent::InteractionCreate {
+                interaction: serenity::Interaction::Command(_)
+            } | serenity::FullEvent::Message { .. }
+        );
+        if !command {
+            self.framework.dispatch(ctx, event).await;
+            return;
+        }
+
+        match self.shutdown.start() {
+            Some(_running) => self.framework.dispatch(ctx, event).await,
+            None => debug!("Ignoring a command, the bot is stopping"),
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+
+use color_eyre::eyre::{eyre, Result};
+use poise::serenity_prelude as serenity;
+use tokio::sync::Mutex;
+use tracing::{error, warn};
+
+use crate::{
+    db::Store,
+    templates::{Language, Template},
+};
+
+/// Parses `pool=threshold` entries separated by commas, e.g. `default=20, vip=5`
+pub fn parse_thresholds(value: &str) -> Result<Vec<(String, u32)>> {
+    let mut thresholds = Vec::new();
+
+    for entry in value.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (key_pool, threshold) = entry
+            .split_once('=')
+            .ok_or_else(|| eyre!("`{entry}` must look like pool=threshold"))?;
+
+        let key_pool = key_pool.trim();
+        if key_pool.is_empty() {
+            return Err(eyre!("`{entry}` has no pool"));
+        }
+        let threshold = threshold
+            .trim()
+            .parse::<u32>()
+            .map_err(|_| eyre!("`{}` is not a whole number", threshold.trim()))?;
+
+        thresholds.push((key_pool.to_owned(), threshold));
+    }
+
+    Ok(thresholds)
+}
+
+/// The low-stock threshold of `key_pool` from the `low_stock_thresholds` setting, if it has one
+fn threshold(config: &HashMap<String, String>, key_pool: &str) -> Option<u32> {
+    config
+        .get("low_stock_thresholds")
+        .and_then(|v| parse_thresholds(v).ok())?
+        .into_iter()
+        .find(|(p, _)| p == key_pool)
+        .map(|(_, threshold)| threshold)
+}
+
+/// Alerts the admins if `key_pool` dropped below its threshold, once per crossing
+///
+/// The alert goes to `low_stock_channel_id`, or to `log_channel_id` if that isn't set, and pings
+/// `low_stock_role_id` if it is set. Without `http` the alert is only logged. Once the pool is back
+/// at its threshold, e.g. after new keys were read, the next crossing alerts again. Errors are
+/// logged and otherwise ignored.
+pub async fn check(
+    http: Option<&serenity::Http>,
+    db: &dyn Store,
+    config: &Mutex<HashMap<String, String>>,
+    key_pool: &str,
+) {
+    let Some(threshold) = threshold(&*config.lock().await, key_pool) else {
+        return;
+    };
+
+    let remaining = match db.remaining_unclaimed(key_pool).await {
+        Ok(remaining) => remaining,
+        Err(e) => {
+            error!("Could not check the stock of pool {key_pool}: {e}");
+            return;
+        }
+    };
+
+    if i64::from(remaining) >= i64::from(threshold) {
+        if let Err(e) = db.clear_low_stock(key_pool).await {
+            error!("Could not reset the low-stock alert of pool {key_pool}: {e}");
+        }
+        return;
+    }
+
+    match db.mark_low_stock(key_pool, remaining).await {
+        Ok(true) => {}
+        Ok(false) => return,
+        Err(e) => {
+            error!("Could not record the low-stock alert of pool {key_pool}: {e}");
+            return;
+        }
+    }
+
+    warn!(
+        "Only {remaining} key(s) left in pool {key_pool}, the low-stock threshold is {threshold}"
+    );
+
+    let Some(http) = http else {
+        return;
+    };
+
+    let (channel, role, content) = {
+        let config = config.lock().await;
+        let channel = config
+            .get("low_stock_channel_id")
+            .or_else(|| config.get("log_channel_id"))
+            .and_then(|c| c.parse::<u64>().ok());
+        let role = config
+            .get("low_stock_role_id")
+            .and_then(|r| r.parse::<u64>().ok());
+        // the alert channel is shared, so it uses the default language rather than anyone's own
+        let content = Template::LowStock.render(
+            &config,
+            Language::from_config(&config).unwrap_or_default(),
+            &[
+                ("remaining", remaining.to_string()),
+                ("pool", key_pool.to_owned()),
+                ("threshold", threshold.to_string()),
+            ],
+        );
+        (channel, role, content)
+    };
+
+    let Some(channel) = channel else {
+        return;
+    };
+
+    let content = match role {
+        Some(role) => format!("<@&{role}> {content}"),
+        None => content,
+    };
+    let msg = serenity::CreateMessage::new()
+        .content(content)
+        .allowed_mentions(serenity::CreateAllowedMentions::new().roles(role));
+
+    if let Err(e) = serenity::ChannelId::new(channel)
+        .send_message(http, msg)
+        .await
+    {
+        error!("Could not post the low-stock alert of pool {key_pool}: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thresholds_are_parsed() {
+        let cases: &[(&str, &[(&str, u32)])] = &[
+            ("", &[]),
+            (" , ", &[]),
+            ("default=20", &[("default", 20)]),
+            ("default=20, vip=5", &[("default", 20), ("vip", 5)]),
+            (" default = 20 ,vip=0,", &[("default", 20), ("vip", 0)]),
+            ("closed beta=3", &[("closed beta", 3)]),
+        ];
+
+        for (value, expected) in cases {
+            let expected: Vec<_> = expected.iter().map(|(p, t)| (p.to_string(), *t)).collect();
+            assert_eq!(parse_thresholds(value).unwrap(), expected, "{value}");
+        }
+    }
+
+    #[test]
+    fn malformed_thresholds_are_rejected() {
+        for value in [
+            "default",
+            "default:20",
+            "=20",
+            " =20",
+            "default=",
+            "default=x",
+            "default=-1",
+            "default=2.5",
+            "default=20, vip",
+        ] {
+            assert!(parse_thresholds(value).is_err(), "{value} was accepted");
+        }
+    }
+}
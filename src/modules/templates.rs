@@ -0,0 +1,1233 @@
+use std::collections::HashMap;
+
+use color_eyre::eyre::{eyre, Result};
+
+/// Languages the built in templates are translated to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Language {
+    #[default]
+    English,
+    German,
+    Spanish,
+}
+
+impl Language {
+    pub const ALL: &'static [Language] = &[Language::English, Language::German, Language::Spanish];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::German => "de",
+            Language::Spanish => "es",
+        }
+    }
+
+    /// The language set with the `default_locale` setting
+    pub fn from_config(config: &HashMap<String, String>) -> Option<Language> {
+        config
+            .get("default_locale")
+            .and_then(|l| Language::from_locale(l))
+    }
+
+    /// Finds the language of a discord locale like `de` or `es-ES`
+    pub fn from_locale(locale: &str) -> Option<Language> {
+        let code = locale.split('-').next().unwrap_or(locale);
+
+        Language::ALL
+            .iter()
+            .copied()
+            .find(|l| l.code().eq_ignore_ascii_case(code))
+    }
+}
+
+/// Every message the bot sends, replies to admins included, each can be overridden in the config with
+/// `template_<name>` for all languages, `template_<name>_<language>` for a single language or
+/// `template_<name>_<pool>` for the giveaways of a single pool, e.g. `template_key_dm`,
+/// `template_key_dm_de` or `template_key_dm_vip`
+///
+/// Placeholders are written as `{name}`, use `{{` and `}}` for literal braces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Template {
+    KeyDm,
+    GiveawayPost,
+    GiveawayOver,
+    GiveawayPaused,
+    AccountTooNew,
+    MissingRole,
+    ClaimFailed,
+    NoKeysLeft,
+    AlreadyClaimed,
+    NotEligible,
+    DeliveryFailed,
+    RoundLimitReached,
+    LifetimeLimitReached,
+    ClaimCooldown,
+    KeySent,
+    KeyResent,
+    NoKeyThisRound,
+    ResendCooldown,
+    BotUser,
+    GiveKeyFailed,
+    NoRoleSet,
+    RoleSet,
+    EmbedEnds,
+    EmbedEnded,
+    EmbedKeysLeft,
+    ButtonGetKey,
+    ButtonResendKey,
+    NotSet,
+    UnknownSetting,
+    InvalidValue,
+    SettingChanged,
+    BulkNoTargets,
+    BulkDryRun,
+    BulkNotEnoughKeys,
+    BulkConfirm,
+    BulkCancel,
+    BulkCancelled,
+    BulkInvalidUser,
+    BulkBotSkipped,
+    BulkDmFailed,
+    BulkProgress,
+    BulkDone,
+    BulkSkipped,
+    BulkFailed,
+    RoundTitle,
+    RoundStarted,
+    RoundEnded,
+    RoundClaims,
+    RoundPlanned,
+    RoundOpenFailed,
+    RoundAlreadyActive,
+    RoundOpened,
+    RoundSwitched,
+    NoActiveRound,
+    RoundCloseFailed,
+    RoundClosed,
+    NoRounds,
+    NoSuchRound,
+    NoRoundReport,
+    RevokeBackupFailed,
+    RevokeFailed,
+    KeyRevokedFrom,
+    KeyRevokedUnclaimed,
+    KeyPool,
+    KeyClaimedBy,
+    KeyClaimedInRound,
+    KeyNotClaimed,
+    KeyClaimedAt,
+    KeyDmSent,
+    KeyDmFailed,
+    KeyNoDm,
+    KeyAddedAt,
+    KeyRevoked,
+    UnknownKey,
+    NoKeysInMessage,
+    UserNoKeys,
+    UserKeys,
+    MoreLines,
+    GiveawayCapWarning,
+    UnknownPreview,
+    UnknownLanguage,
+    ReportTitle,
+    ReportTitleNamed,
+    ReportKeysClaimed,
+    ReportClaimants,
+    ReportFailedDeliveries,
+    ReportTaken,
+    ReportPool,
+    ReportPoolKeys,
+    ReportPoolRanOut,
+    ReasonNoKeysLeft,
+    ReasonAlreadyClaimed,
+    ReasonNoRoundOpen,
+    ReasonLimitReached,
+    ReasonInternal,
+    ReasonDeliveryFailed,
+    LowStock,
+    WaiverReasonMissing,
+}
+
+impl Template {
+    pub const ALL: &'static [Template] = &[
+        Template::KeyDm,
+        Template::GiveawayPost,
+        Template::GiveawayOver,
+        Template::GiveawayPaused,
+        Template::AccountTooNew,
+        Template::MissingRole,
+        Template::ClaimFailed,
+        Template::NoKeysLeft,
+        Template::AlreadyClaimed,
+        Template::NotEligible,
+        Template::DeliveryFailed,
+        Template::RoundLimitReached,
+        Template::LifetimeLimitReached,
+        Template::ClaimCooldown,
+        Template::KeySent,
+        Template::KeyResent,
+        Template::NoKeyThisRound,
+        Template::ResendCooldown,
+        Template::BotUser,
+        Template::GiveKeyFailed,
+        Template::NoRoleSet,
+        Template::RoleSet,
+        Template::EmbedEnds,
+        Template::EmbedEnded,
+        Template::EmbedKeysLeft,
+        Template::ButtonGetKey,
+        Template::ButtonResendKey,
+        Template::NotSet,
+        Template::UnknownSetting,
+        Template::InvalidValue,
+        Template::SettingChanged,
+        Template::BulkNoTargets,
+        Template::BulkDryRun,
+        Template::BulkNotEnoughKeys,
+        Template::BulkConfirm,
+        Template::BulkCancel,
+        Template::BulkCancelled,
+        Template::BulkInvalidUser,
+        Template::BulkBotSkipped,
+        Template::BulkDmFailed,
+        Template::BulkProgress,
+        Template::BulkDone,
+        Template::BulkSkipped,
+        Template::BulkFailed,
+        Template::RoundTitle,
+        Template::RoundStarted,
+        Template::RoundEnded,
+        Template::RoundClaims,
+        Template::RoundPlanned,
+        Template::RoundOpenFailed,
+        Template::RoundAlreadyActive,
+        Template::RoundOpened,
+        Template::RoundSwitched,
+        Template::NoActiveRound,
+        Template::RoundCloseFailed,
+        Template::RoundClosed,
+        Template::NoRounds,
+        Template::NoSuchRound,
+        Template::NoRoundReport,
+        Template::RevokeBackupFailed,
+        Template::RevokeFailed,
+        Template::KeyRevokedFrom,
+        Template::KeyRevokedUnclaimed,
+        Template::KeyPool,
+        Template::KeyClaimedBy,
+        Template::KeyClaimedInRound,
+        Template::KeyNotClaimed,
+        Template::KeyClaimedAt,
+        Template::KeyDmSent,
+        Template::KeyDmFailed,
+        Template::KeyNoDm,
+        Template::KeyAddedAt,
+        Template::KeyRevoked,
+        Template::UnknownKey,
+        Template::NoKeysInMessage,
+        Template::UserNoKeys,
+        Template::UserKeys,
+        Template::MoreLines,
+        Template::GiveawayCapWarning,
+        Template::UnknownPreview,
+        Template::UnknownLanguage,
+        Template::ReportTitle,
+        Template::ReportTitleNamed,
+        Template::ReportKeysClaimed,
+        Template::ReportClaimants,
+        Template::ReportFailedDeliveries,
+        Template::ReportTaken,
+        Template::ReportPool,
+        Template::ReportPoolKeys,
+        Template::ReportPoolRanOut,
+        Template::ReasonNoKeysLeft,
+        Template::ReasonAlreadyClaimed,
+        Template::ReasonNoRoundOpen,
+        Template::ReasonLimitReached,
+        Template::ReasonInternal,
+        Template::ReasonDeliveryFailed,
+        Template::LowStock,
+        Template::WaiverReasonMissing,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Template::KeyDm => "key_dm",
+            Template::GiveawayPost => "giveaway_post",
+            Template::GiveawayOver => "giveaway_over",
+            Template::GiveawayPaused => "giveaway_paused",
+            Template::AccountTooNew => "account_too_new",
+            Template::MissingRole => "missing_role",
+            Template::ClaimFailed => "claim_failed",
+            Template::NoKeysLeft => "no_keys_left",
+            Template::AlreadyClaimed => "already_claimed",
+            Template::NotEligible => "not_eligible",
+            Template::DeliveryFailed => "delivery_failed",
+            Template::RoundLimitReached => "round_limit_reached",
+            Template::LifetimeLimitReached => "lifetime_limit_reached",
+            Template::ClaimCooldown => "claim_cooldown",
+            Template::KeySent => "key_sent",
+            Template::KeyResent => "key_resent",
+            Template::NoKeyThisRound => "no_key_this_round",
+            Template::ResendCooldown => "resend_cooldown",
+            Template::BotUser => "bot_user",
+            Template::GiveKeyFailed => "give_key_failed",
+            Template::NoRoleSet => "no_role_set",
+            Template::RoleSet => "role_set",
+            Template::EmbedEnds => "embed_ends",
+            Template::EmbedEnded => "embed_ended",
+            Template::EmbedKeysLeft => "embed_keys_left",
+            Template::ButtonGetKey => "button_get_key",
+            Template::ButtonResendKey => "button_resend_key",
+            Template::NotSet => "not_set",
+            Template::UnknownSetting => "unknown_setting",
+            Template::InvalidValue => "invalid_value",
+            Template::SettingChanged => "setting_changed",
+            Template::BulkNoTargets => "bulk_no_targets",
+            Template::BulkDryRun => "bulk_dry_run",
+            Template::BulkNotEnoughKeys => "bulk_not_enough_keys",
+            Template::BulkConfirm => "bulk_confirm",
+            Template::BulkCancel => "bulk_cancel",
+            Template::BulkCancelled => "bulk_cancelled",
+            Template::BulkInvalidUser => "bulk_invalid_user",
+            Template::BulkBotSkipped => "bulk_bot_skipped",
+            Template::BulkDmFailed => "bulk_dm_failed",
+            Template::BulkProgress => "bulk_progress",
+            Template::BulkDone => "bulk_done",
+            Template::BulkSkipped => "bulk_skipped",
+            Template::BulkFailed => "bulk_failed",
+            Template::RoundTitle => "round_title",
+            Template::RoundStarted => "round_started",
+            Template::RoundEnded => "round_ended",
+            Template::RoundClaims => "round_claims",
+            Template::RoundPlanned => "round_planned",
+            Template::RoundOpenFailed => "round_open_failed",
+            Template::RoundAlreadyActive => "round_already_active",
+            Template::RoundOpened => "round_opened",
+            Template::RoundSwitched => "round_switched",
+            Template::NoActiveRound => "no_active_round",
+            Template::RoundCloseFailed => "round_close_failed",
+            Template::RoundClosed => "round_closed",
+            Template::NoRounds => "no_rounds",
+            Template::NoSuchRound => "no_such_round",
+            Template::NoRoundReport => "no_round_report",
+            Template::RevokeBackupFailed => "revoke_backup_failed",
+            Template::RevokeFailed => "revoke_failed",
+            Template::KeyRevokedFrom => "key_revoked_from",
+            Template::KeyRevokedUnclaimed => "key_revoked_unclaimed",
+            Template::KeyPool => "key_pool",
+            Template::KeyClaimedBy => "key_claimed_by",
+            Template::KeyClaimedInRound => "key_claimed_in_round",
+            Template::KeyNotClaimed => "key_not_claimed",
+            Template::KeyClaimedAt => "key_claimed_at",
+            Template::KeyDmSent => "key_dm_sent",
+            Template::KeyDmFailed => "key_dm_failed",
+            Template::KeyNoDm => "key_no_dm",
+            Template::KeyAddedAt => "key_added_at",
+            Template::KeyRevoked => "key_revoked",
+            Template::UnknownKey => "unknown_key",
+            Template::NoKeysInMessage => "no_keys_in_message",
+            Template::UserNoKeys => "user_no_keys",
+            Template::UserKeys => "user_keys",
+            Template::MoreLines => "more_lines",
+            Template::GiveawayCapWarning => "giveaway_cap_warning",
+            Template::UnknownPreview => "unknown_preview",
+            Template::UnknownLanguage => "unknown_language",
+            Template::ReportTitle => "report_title",
+            Template::ReportTitleNamed => "report_title_named",
+            Template::ReportKeysClaimed => "report_keys_claimed",
+            Template::ReportClaimants => "report_claimants",
+            Template::ReportFailedDeliveries => "report_failed_deliveries",
+            Template::ReportTaken => "report_taken",
+            Template::ReportPool => "report_pool",
+            Template::ReportPoolKeys => "report_pool_keys",
+            Template::ReportPoolRanOut => "report_pool_ran_out",
+            Template::ReasonNoKeysLeft => "reason_no_keys_left",
+            Template::ReasonAlreadyClaimed => "reason_already_claimed",
+            Template::ReasonNoRoundOpen => "reason_no_round_open",
+            Template::ReasonLimitReached => "reason_limit_reached",
+            Template::ReasonInternal => "reason_internal",
+            Template::ReasonDeliveryFailed => "reason_delivery_failed",
+            Template::LowStock => "low_stock",
+            Template::WaiverReasonMissing => "waiver_reason_missing",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Template> {
+        Template::ALL.iter().copied().find(|t| t.name() == name)
+    }
+
+    /// Finds the template a config key like `template_key_dm`, `template_key_dm_de` or
+    /// `template_key_dm_vip` refers to
+    ///
+    /// A suffix that is a language code is always read as a language, and the longest template
+    /// name wins, so `template_key_dm_sent` is the key_dm_sent template and not key_dm for a pool
+    /// named `sent`.
+    pub fn from_config_key(key: &str) -> Option<(Template, Scope<'_>)> {
+        let name = key.strip_prefix("template_")?;
+
+        if let Some(template) = Template::from_name(name) {
+            return Some((template, Scope::All));
+        }
+
+        Template::ALL
+            .iter()
+            .copied()
+            .filter_map(|t| {
+                let suffix = name.strip_prefix(t.name())?.strip_prefix('_')?;
+                (!suffix.is_empty()).then_some((t, suffix))
+            })
+            .max_by_key(|(t, _)| t.name().len())
+            .map(|(template, suffix)| {
+                let scope = match Language::ALL.iter().copied().find(|l| l.code() == suffix) {
+                    Some(language) => Scope::Language(language),
+                    None => Scope::Pool(suffix),
+                };
+                (template, scope)
+            })
+    }
+
+    /// The config key that overrides this template for `scope`
+    pub fn config_key(self, scope: Scope) -> String {
+        match scope {
+            Scope::All => format!("template_{}", self.name()),
+            Scope::Language(language) => format!("template_{}_{}", self.name(), language.code()),
+            Scope::Pool(key_pool) => format!("template_{}_{key_pool}", self.name()),
+        }
+    }
+
+    /// The built in text of the template in every language, in the order of `Language::ALL`
+    fn translations(self) -> [&'static str; 3] {
+        match self {
+            Template::KeyDm => [
+                "Congratulations, you have been given a key!\nYou can claim your key by entering it into steam.\nYour key is: {key}\n",
+                "Glückwunsch, du hast einen Schlüssel erhalten!\nDu kannst deinen Schlüssel einlösen, indem du ihn in Steam eingibst.\nDein Schlüssel lautet: {key}\n",
+                "¡Enhorabuena, has recibido una clave!\nPuedes canjear tu clave introduciéndola en Steam.\nTu clave es: {key}\n",
+            ],
+            Template::GiveawayPost => [
+                "If you have the role {role}\n\nClick the button below to get a beta key",
+                "Wenn du die Rolle {role} hast\n\nKlicke auf den Button unten, um einen Beta-Schlüssel zu erhalten",
+                "Si tienes el rol {role}\n\nHaz clic en el botón de abajo para obtener una clave beta",
+            ],
+            Template::GiveawayOver => [
+                "This key giveaway is over!",
+                "Dieses Schlüssel-Gewinnspiel ist beendet!",
+                "¡Este sorteo de claves ha terminado!",
+            ],
+            Template::GiveawayPaused => [
+                "This key giveaway was paused because the bot is restarting, {remaining} keys are left. Look out for a new post!",
+                "Dieses Schlüssel-Gewinnspiel wurde pausiert, weil der Bot neu startet, es sind noch {remaining} Schlüssel übrig. Halte Ausschau nach einem neuen Post!",
+                "Este sorteo de claves se ha pausado porque el bot se está reiniciando, quedan {remaining} claves. ¡Atento a una nueva publicación!",
+            ],
+            Template::AccountTooNew => [
+                "Your account is too new to claim a key. Your account must be at least {age_bound} days old",
+                "Dein Konto ist zu neu, um einen Schlüssel zu erhalten. Dein Konto muss mindestens {age_bound} Tage alt sein",
+                "Tu cuenta es demasiado nueva para obtener una clave. Tu cuenta debe tener al menos {age_bound} días de antigüedad",
+            ],
+            Template::MissingRole => [
+                "You do not have permission to claim a key, please contact an admin if you think this is a mistake",
+                "Du bist nicht berechtigt, einen Schlüssel zu erhalten. Bitte wende dich an einen Admin, wenn du denkst, dass das ein Fehler ist",
+                "No tienes permiso para obtener una clave, contacta con un administrador si crees que es un error",
+            ],
+            Template::ClaimFailed => [
+                "Something went wrong while claiming your key, please try again later",
+                "Beim Abrufen deines Schlüssels ist etwas schiefgelaufen, bitte versuche es später erneut",
+                "Algo salió mal al obtener tu clave, inténtalo de nuevo más tarde",
+            ],
+            Template::NoKeysLeft => [
+                "Sorry, there are no keys left",
+                "Leider sind keine Schlüssel mehr übrig",
+                "Lo sentimos, no quedan claves",
+            ],
+            Template::AlreadyClaimed => [
+                "You have already claimed a key in this round",
+                "Du hast in dieser Runde bereits einen Schlüssel erhalten",
+                "Ya has obtenido una clave en esta ronda",
+            ],
+            Template::NotEligible => [
+                "You can't claim a key right now: {reason}",
+                "Du kannst gerade keinen Schlüssel abrufen: {reason}",
+                "No puedes obtener una clave ahora mismo: {reason}",
+            ],
+            Template::DeliveryFailed => [
+                "Your key could not be sent as a DM, please allow DMs from server members and use /my_keys to see it",
+                "Dein Schlüssel konnte nicht per DM gesendet werden, bitte erlaube DMs von Servermitgliedern und nutze /my_keys, um ihn zu sehen",
+                "No se pudo enviar tu clave por MD, permite los MD de miembros del servidor y usa /my_keys para verla",
+            ],
+            Template::RoundLimitReached => [
+                "You have claimed {claimed} of {limit} keys allowed in round {round}, you can claim again in the next round",
+                "Du hast {claimed} von {limit} erlaubten Schlüsseln in Runde {round} erhalten, in der nächsten Runde kannst du wieder einen abrufen",
+                "Has obtenido {claimed} de las {limit} claves permitidas en la ronda {round}, podrás obtener otra en la próxima ronda",
+            ],
+            Template::LifetimeLimitReached => [
+                "You have claimed {claimed} of {limit} keys, which is the most you can claim",
+                "Du hast {claimed} von {limit} Schlüsseln erhalten, mehr kannst du nicht abrufen",
+                "Has obtenido {claimed} de {limit} claves, no puedes obtener más",
+            ],
+            Template::ClaimCooldown => [
+                "You can claim your next key {next_claim}",
+                "Du kannst deinen nächsten Schlüssel {next_claim} abrufen",
+                "Podrás obtener tu próxima clave {next_claim}",
+            ],
+            Template::KeySent => [
+                "Key sent to {user}",
+                "Schlüssel an {user} gesendet",
+                "Clave enviada a {user}",
+            ],
+            Template::KeyResent => [
+                "Your key for round {round} is: {key}",
+                "Dein Schlüssel für Runde {round} lautet: {key}",
+                "Tu clave de la ronda {round} es: {key}",
+            ],
+            Template::NoKeyThisRound => [
+                "You have not claimed a key in round {round}",
+                "Du hast in Runde {round} keinen Schlüssel erhalten",
+                "No has obtenido ninguna clave en la ronda {round}",
+            ],
+            Template::ResendCooldown => [
+                "Your key was sent to you recently, please try again in {minutes} minutes",
+                "Dein Schlüssel wurde dir gerade erst geschickt, bitte versuche es in {minutes} Minuten erneut",
+                "Tu clave se te envió hace poco, inténtalo de nuevo en {minutes} minutos",
+            ],
+            Template::BotUser => [
+                "You can't give a key to a bot!",
+                "Bots können keine Schlüssel erhalten!",
+                "¡No puedes dar una clave a un bot!",
+            ],
+            Template::GiveKeyFailed => [
+                "Could not get key, please try again later\n\nError: {reason}",
+                "Schlüssel konnte nicht abgerufen werden, bitte versuche es später erneut\n\nFehler: {reason}",
+                "No se pudo obtener la clave, inténtalo de nuevo más tarde\n\nError: {reason}",
+            ],
+            Template::NoRoleSet => [
+                "No role set, please set a role using /set_key_role",
+                "Keine Rolle festgelegt, bitte lege mit /set_key_role eine Rolle fest",
+                "No hay ningún rol configurado, configura uno con /set_key_role",
+            ],
+            Template::RoleSet => [
+                "Key role set to {role}",
+                "Schlüsselrolle auf {role} gesetzt",
+                "Rol de clave establecido en {role}",
+            ],
+            Template::EmbedEnds => ["Ends", "Endet", "Termina"],
+            Template::EmbedEnded => ["Ended", "Beendet", "Terminó"],
+            Template::EmbedKeysLeft => ["Keys left", "Verbleibende Schlüssel", "Claves restantes"],
+            Template::ButtonGetKey => ["Get key", "Schlüssel holen", "Obtener clave"],
+            Template::ButtonResendKey => [
+                "Resend my key",
+                "Meinen Schlüssel erneut senden",
+                "Reenviar mi clave",
+            ],
+            Template::NotSet => ["not set", "nicht gesetzt", "sin definir"],
+            Template::UnknownSetting => [
+                "Unknown setting `{key}`, see `/config show` for all settings",
+                "Unbekannte Einstellung `{key}`, alle Einstellungen zeigt `/config show`",
+                "Ajuste desconocido `{key}`, consulta todos los ajustes con `/config show`",
+            ],
+            Template::InvalidValue => [
+                "Invalid value for `{key}`: {reason}",
+                "Ungültiger Wert für `{key}`: {reason}",
+                "Valor no válido para `{key}`: {reason}",
+            ],
+            Template::SettingChanged => [
+                "`{key}` changed from `{old}` to `{new}`",
+                "`{key}` von `{old}` auf `{new}` geändert",
+                "`{key}` cambiado de `{old}` a `{new}`",
+            ],
+            Template::BulkNoTargets => [
+                "Pick a role or paste a list of users",
+                "Wähle eine Rolle oder füge eine Liste von Nutzern ein",
+                "Elige un rol o pega una lista de usuarios",
+            ],
+            Template::BulkDryRun => [
+                "**Dry run:** {users} user(s) will get a key from the `{pool}` pool, {skipped} will be skipped and {unresolved} could not be resolved.\n{remaining} key(s) are left.",
+                "**Probelauf:** {users} Nutzer erhalten einen Schlüssel aus dem Pool `{pool}`, {skipped} werden übersprungen und {unresolved} konnten nicht gefunden werden.\nEs sind noch {remaining} Schlüssel übrig.",
+                "**Simulación:** {users} usuario(s) recibirán una clave del grupo `{pool}`, {skipped} se omitirán y {unresolved} no se pudieron encontrar.\nQuedan {remaining} clave(s).",
+            ],
+            Template::BulkNotEnoughKeys => [
+                "**There are not enough keys left for everyone**, the last users will fail.",
+                "**Es sind nicht genug Schlüssel für alle übrig**, bei den letzten Nutzern schlägt es fehl.",
+                "**No quedan suficientes claves para todos**, los últimos usuarios fallarán.",
+            ],
+            Template::BulkConfirm => ["Confirm", "Bestätigen", "Confirmar"],
+            Template::BulkCancel => ["Cancel", "Abbrechen", "Cancelar"],
+            Template::BulkCancelled => [
+                "Cancelled, no keys were given.",
+                "Abgebrochen, es wurden keine Schlüssel vergeben.",
+                "Cancelado, no se ha dado ninguna clave.",
+            ],
+            Template::BulkInvalidUser => [
+                "`{entry}`: not a user id",
+                "`{entry}`: keine Nutzer-ID",
+                "`{entry}`: no es un ID de usuario",
+            ],
+            Template::BulkBotSkipped => ["{user} (bot)", "{user} (Bot)", "{user} (bot)"],
+            Template::BulkDmFailed => [
+                "{user}: key claimed but DM failed: {reason}",
+                "{user}: Schlüssel vergeben, aber die DM ist fehlgeschlagen: {reason}",
+                "{user}: clave obtenida, pero el MD falló: {reason}",
+            ],
+            Template::BulkProgress => [
+                "Giving keys… {done}/{total}",
+                "Schlüssel werden vergeben… {done}/{total}",
+                "Dando claves… {done}/{total}",
+            ],
+            Template::BulkDone => [
+                "**Bulk give done:** {given} succeeded, {skipped} skipped, {failed} failed",
+                "**Massenvergabe fertig:** {given} erfolgreich, {skipped} übersprungen, {failed} fehlgeschlagen",
+                "**Entrega en masa terminada:** {given} con éxito, {skipped} omitidos, {failed} fallidos",
+            ],
+            Template::BulkSkipped => ["Skipped", "Übersprungen", "Omitidos"],
+            Template::BulkFailed => ["Failed", "Fehlgeschlagen", "Fallidos"],
+            Template::RoundTitle => ["**Round {round}**", "**Runde {round}**", "**Ronda {round}**"],
+            Template::RoundStarted => ["started {time}", "gestartet {time}", "iniciada {time}"],
+            Template::RoundEnded => ["ended {time}", "beendet {time}", "terminada {time}"],
+            Template::RoundClaims => [
+                "{claims} key(s) claimed by {users} user(s)",
+                "{claims} Schlüssel von {users} Nutzer(n) abgerufen",
+                "{claims} clave(s) obtenidas por {users} usuario(s)",
+            ],
+            Template::RoundPlanned => [
+                "Planned round {round} \"{name}\", open it with `/round open {round}`",
+                "Runde {round} \"{name}\" geplant, öffne sie mit `/round open {round}`",
+                "Ronda {round} \"{name}\" planificada, ábrela con `/round open {round}`",
+            ],
+            Template::RoundOpenFailed => [
+                "Could not open round: {reason}",
+                "Runde konnte nicht geöffnet werden: {reason}",
+                "No se pudo abrir la ronda: {reason}",
+            ],
+            Template::RoundAlreadyActive => [
+                "Round {round} is already active",
+                "Runde {round} ist bereits aktiv",
+                "La ronda {round} ya está activa",
+            ],
+            Template::RoundOpened => [
+                "Round {round} opened",
+                "Runde {round} geöffnet",
+                "Ronda {round} abierta",
+            ],
+            Template::RoundSwitched => [
+                "Round {previous} closed, round {round} opened",
+                "Runde {previous} geschlossen, Runde {round} geöffnet",
+                "Ronda {previous} cerrada, ronda {round} abierta",
+            ],
+            Template::NoActiveRound => [
+                "No round is active",
+                "Keine Runde ist aktiv",
+                "No hay ninguna ronda activa",
+            ],
+            Template::RoundCloseFailed => [
+                "Could not close round: {reason}",
+                "Runde konnte nicht geschlossen werden: {reason}",
+                "No se pudo cerrar la ronda: {reason}",
+            ],
+            Template::RoundClosed => [
+                "Round {round} closed, keys can't be claimed until a round is opened",
+                "Runde {round} geschlossen, bis eine Runde geöffnet wird, können keine Schlüssel abgerufen werden",
+                "Ronda {round} cerrada, no se pueden obtener claves hasta que se abra una ronda",
+            ],
+            Template::NoRounds => [
+                "There are no rounds yet",
+                "Es gibt noch keine Runden",
+                "Todavía no hay rondas",
+            ],
+            Template::NoSuchRound => [
+                "No such round",
+                "Diese Runde gibt es nicht",
+                "Esa ronda no existe",
+            ],
+            Template::NoRoundReport => [
+                "There is no report for round {round}, a report is taken when a round closes",
+                "Für Runde {round} gibt es keinen Bericht, ein Bericht wird beim Schließen einer Runde erstellt",
+                "No hay informe de la ronda {round}, el informe se crea al cerrar una ronda",
+            ],
+            Template::RevokeBackupFailed => [
+                "Could not back up the database, nothing was revoked: {reason}",
+                "Die Datenbank konnte nicht gesichert werden, es wurde nichts gesperrt: {reason}",
+                "No se pudo hacer una copia de seguridad de la base de datos, no se ha revocado nada: {reason}",
+            ],
+            Template::RevokeFailed => [
+                "Could not revoke key: {reason}",
+                "Schlüssel konnte nicht gesperrt werden: {reason}",
+                "No se pudo revocar la clave: {reason}",
+            ],
+            Template::KeyRevokedFrom => [
+                "Key revoked, it was claimed by {user}",
+                "Schlüssel gesperrt, er wurde von {user} abgerufen",
+                "Clave revocada, la había obtenido {user}",
+            ],
+            Template::KeyRevokedUnclaimed => [
+                "Key revoked, it had not been claimed",
+                "Schlüssel gesperrt, er wurde noch nicht abgerufen",
+                "Clave revocada, nadie la había obtenido",
+            ],
+            Template::KeyPool => ["{pool} pool", "Pool {pool}", "grupo {pool}"],
+            Template::KeyClaimedBy => [
+                "claimed by **{user}**",
+                "abgerufen von **{user}**",
+                "obtenida por **{user}**",
+            ],
+            Template::KeyClaimedInRound => [
+                "claimed by **{user}** in round {round}",
+                "abgerufen von **{user}** in Runde {round}",
+                "obtenida por **{user}** en la ronda {round}",
+            ],
+            Template::KeyNotClaimed => ["not claimed", "nicht abgerufen", "sin obtener"],
+            Template::KeyClaimedAt => ["at {time}", "am {time}", "el {time}"],
+            Template::KeyDmSent => [
+                "DM sent at {time}",
+                "DM gesendet am {time}",
+                "MD enviado el {time}",
+            ],
+            Template::KeyDmFailed => [
+                "**DM failed** at {time}",
+                "**DM fehlgeschlagen** am {time}",
+                "**MD fallido** el {time}",
+            ],
+            Template::KeyNoDm => ["no DM logged", "keine DM protokolliert", "ningún MD registrado"],
+            Template::KeyAddedAt => [
+                "added at {time}",
+                "hinzugefügt am {time}",
+                "añadida el {time}",
+            ],
+            Template::KeyRevoked => ["**revoked**", "**gesperrt**", "**revocada**"],
+            Template::UnknownKey => [
+                "`{key}` is not a known key",
+                "`{key}` ist kein bekannter Schlüssel",
+                "`{key}` no es una clave conocida",
+            ],
+            Template::NoKeysInMessage => [
+                "No known keys in this message",
+                "Keine bekannten Schlüssel in dieser Nachricht",
+                "No hay claves conocidas en este mensaje",
+            ],
+            Template::UserNoKeys => [
+                "{user} has not received any keys",
+                "{user} hat noch keine Schlüssel erhalten",
+                "{user} no ha recibido ninguna clave",
+            ],
+            Template::UserKeys => [
+                "**{user}** has received {count} key(s):",
+                "**{user}** hat {count} Schlüssel erhalten:",
+                "**{user}** ha recibido {count} clave(s):",
+            ],
+            Template::MoreLines => [
+                "… and {count} more",
+                "… und {count} weitere",
+                "… y {count} más",
+            ],
+            Template::GiveawayCapWarning => [
+                "Only {remaining} key(s) are left in pool `{pool}`, fewer than the cap of {cap} for this giveaway",
+                "Im Pool `{pool}` sind nur noch {remaining} Schlüssel übrig, weniger als die Obergrenze von {cap} für dieses Gewinnspiel",
+                "Solo quedan {remaining} clave(s) en el grupo `{pool}`, menos que el límite de {cap} de este sorteo",
+            ],
+            Template::UnknownPreview => [
+                "Unknown template `{template}`",
+                "Unbekannte Vorlage `{template}`",
+                "Plantilla desconocida `{template}`",
+            ],
+            Template::UnknownLanguage => [
+                "Unknown language `{language}`, use one of en, de or es",
+                "Unbekannte Sprache `{language}`, verwende en, de oder es",
+                "Idioma desconocido `{language}`, usa en, de o es",
+            ],
+            Template::ReportTitle => [
+                "Round {round} report",
+                "Bericht zu Runde {round}",
+                "Informe de la ronda {round}",
+            ],
+            Template::ReportTitleNamed => [
+                "Round {round} report: {name}",
+                "Bericht zu Runde {round}: {name}",
+                "Informe de la ronda {round}: {name}",
+            ],
+            Template::ReportKeysClaimed => [
+                "Keys handed out",
+                "Vergebene Schlüssel",
+                "Claves entregadas",
+            ],
+            Template::ReportClaimants => [
+                "Unique claimants",
+                "Verschiedene Empfänger",
+                "Destinatarios distintos",
+            ],
+            Template::ReportFailedDeliveries => [
+                "Failed deliveries",
+                "Fehlgeschlagene Zustellungen",
+                "Entregas fallidas",
+            ],
+            Template::ReportTaken => ["Taken", "Erstellt", "Creado"],
+            Template::ReportPool => ["Pool {pool}", "Pool {pool}", "Grupo {pool}"],
+            Template::ReportPoolKeys => [
+                "{claimed} handed out, {remaining} left",
+                "{claimed} vergeben, {remaining} übrig",
+                "{claimed} entregadas, quedan {remaining}",
+            ],
+            Template::ReportPoolRanOut => [
+                "{claimed} handed out, {remaining} left, ran out after {duration}",
+                "{claimed} vergeben, {remaining} übrig, nach {duration} leer",
+                "{claimed} entregadas, quedan {remaining}, agotadas tras {duration}",
+            ],
+            Template::ReasonNoKeysLeft => [
+                "no keys left",
+                "keine Schlüssel mehr übrig",
+                "no quedan claves",
+            ],
+            Template::ReasonAlreadyClaimed => [
+                "already claimed",
+                "bereits erhalten",
+                "ya obtenida",
+            ],
+            Template::ReasonNoRoundOpen => [
+                "no round is open",
+                "keine Runde ist geöffnet",
+                "no hay ninguna ronda abierta",
+            ],
+            Template::ReasonLimitReached => [
+                "claim limit reached",
+                "Limit erreicht",
+                "límite alcanzado",
+            ],
+            Template::ReasonInternal => ["internal error", "interner Fehler", "error interno"],
+            Template::ReasonDeliveryFailed => [
+                "could not send a DM",
+                "DM konnte nicht gesendet werden",
+                "no se pudo enviar el MD",
+            ],
+            Template::LowStock => [
+                "Only {remaining} key(s) left in pool `{pool}`, the low-stock threshold is {threshold}",
+                "Im Pool `{pool}` sind nur noch {remaining} Schlüssel übrig, die Warnschwelle liegt bei {threshold}",
+                "Solo quedan {remaining} clave(s) en el grupo `{pool}`, el umbral de existencias bajas es {threshold}",
+            ],
+            Template::WaiverReasonMissing => [
+                "Please give a reason why this user gets a key without the usual checks",
+                "Bitte gib einen Grund an, warum dieser Nutzer ohne die üblichen Prüfungen einen Schlüssel erhält",
+                "Indica un motivo por el que este usuario recibe una clave sin las comprobaciones habituales",
+            ],
+        }
+    }
+
+    pub fn default_text(self, language: Language) -> &'static str {
+        self.translations()[language as usize]
+    }
+
+    /// The placeholders that are filled in when this template is rendered
+    pub fn placeholders(self) -> &'static [&'static str] {
+        match self {
+            Template::KeyDm => &["key", "user", "round"],
+            Template::GiveawayPost => &["role", "round", "remaining", "ends_at"],
+            Template::GiveawayOver | Template::GiveawayPaused => &["round", "remaining"],
+            Template::AccountTooNew => &["user", "age_bound"],
+            Template::MissingRole => &["user", "role"],
+            Template::ClaimFailed => &["user", "reason"],
+            Template::NoKeysLeft | Template::AlreadyClaimed => &["user"],
+            Template::NotEligible => &["user", "reason"],
+            Template::DeliveryFailed => &["user"],
+            Template::RoundLimitReached => &["user", "claimed", "limit", "round"],
+            Template::LifetimeLimitReached => &["user", "claimed", "limit"],
+            Template::ClaimCooldown => &["user", "next_claim"],
+            Template::KeySent => &["user"],
+            Template::KeyResent => &["key", "user", "round"],
+            Template::NoKeyThisRound => &["user", "round"],
+            Template::ResendCooldown => &["user", "minutes"],
+            Template::GiveKeyFailed => &["user", "reason"],
+            Template::RoleSet => &["role"],
+            Template::UnknownSetting => &["key"],
+            Template::InvalidValue => &["key", "reason"],
+            Template::SettingChanged => &["key", "old", "new"],
+            Template::BulkDryRun => &["users", "pool", "skipped", "unresolved", "remaining"],
+            Template::BulkInvalidUser => &["entry"],
+            Template::BulkBotSkipped => &["user"],
+            Template::BulkDmFailed => &["user", "reason"],
+            Template::BulkProgress => &["done", "total"],
+            Template::BulkDone => &["given", "skipped", "failed"],
+            Template::RoundTitle => &["round"],
+            Template::RoundStarted => &["time"],
+            Template::RoundEnded => &["time"],
+            Template::RoundClaims => &["claims", "users"],
+            Template::RoundPlanned => &["round", "name"],
+            Template::RoundOpenFailed => &["reason"],
+            Template::RoundAlreadyActive => &["round"],
+            Template::RoundOpened => &["round"],
+            Template::RoundSwitched => &["previous", "round"],
+            Template::RoundCloseFailed => &["reason"],
+            Template::RoundClosed => &["round"],
+            Template::NoRoundReport => &["round"],
+            Template::RevokeBackupFailed => &["reason"],
+            Template::RevokeFailed => &["reason"],
+            Template::KeyRevokedFrom => &["user"],
+            Template::KeyPool => &["pool"],
+            Template::KeyClaimedBy => &["user"],
+            Template::KeyClaimedInRound => &["user", "round"],
+            Template::KeyClaimedAt => &["time"],
+            Template::KeyDmSent => &["time"],
+            Template::KeyDmFailed => &["time"],
+            Template::KeyAddedAt => &["time"],
+            Template::UnknownKey => &["key"],
+            Template::UserNoKeys => &["user"],
+            Template::UserKeys => &["user", "count"],
+            Template::MoreLines => &["count"],
+            Template::GiveawayCapWarning => &["remaining", "pool", "cap"],
+            Template::UnknownPreview => &["template"],
+            Template::UnknownLanguage => &["language"],
+            Template::ReportTitle => &["round"],
+            Template::ReportTitleNamed => &["round", "name"],
+            Template::ReportPool => &["pool"],
+            Template::ReportPoolKeys => &["claimed", "remaining"],
+            Template::ReportPoolRanOut => &["claimed", "remaining", "duration"],
+            Template::LowStock => &["remaining", "pool", "threshold"],
+            Template::BotUser
+            | Template::NoRoleSet
+            | Template::EmbedEnds
+            | Template::EmbedEnded
+            | Template::EmbedKeysLeft
+            | Template::ButtonGetKey
+            | Template::ButtonResendKey
+            | Template::NotSet
+            | Template::BulkNoTargets
+            | Template::BulkNotEnoughKeys
+            | Template::BulkConfirm
+            | Template::BulkCancel
+            | Template::BulkCancelled
+            | Template::BulkSkipped
+            | Template::BulkFailed
+            | Template::NoActiveRound
+            | Template::NoRounds
+            | Template::NoSuchRound
+            | Template::KeyRevokedUnclaimed
+            | Template::KeyNotClaimed
+            | Template::KeyNoDm
+            | Template::KeyRevoked
+            | Template::NoKeysInMessage
+            | Template::ReportKeysClaimed
+            | Template::ReportClaimants
+            | Template::ReportFailedDeliveries
+            | Template::ReportTaken
+            | Template::ReasonNoKeysLeft
+            | Template::ReasonAlreadyClaimed
+            | Template::ReasonNoRoundOpen
+            | Template::ReasonLimitReached
+            | Template::ReasonInternal
+            | Template::ReasonDeliveryFailed
+            | Template::WaiverReasonMissing => &[],
+        }
+    }
+
+    /// Example values used when previewing the template
+    pub fn sample_values(self) -> Vec<(&'static str, String)> {
+        self.placeholders()
+            .iter()
+            .map(|&p| {
+                let value = match p {
+                    "key" => "AAAAA-BBBBB-CCCCC",
+                    "user" => "@user",
+                    "round" => "1",
+                    "role" => "@role",
+                    "remaining" => "42",
+                    "ends_at" => "in an hour",
+                    "age_bound" => "5",
+                    "minutes" => "10",
+                    "claimed" => "1",
+                    "limit" => "1",
+                    "next_claim" => "in 10 minutes",
+                    "reason" => "no keys left",
+                    "old" | "new" => "5",
+                    "users" | "given" | "claims" => "3",
+                    "skipped" | "unresolved" | "failed" => "0",
+                    "pool" => "default",
+                    "entry" => "@someone",
+                    "done" => "10",
+                    "total" => "20",
+                    "time" => "today at 12:00",
+                    "name" => "Closed beta",
+                    "previous" => "0",
+                    "count" => "3",
+                    "cap" => "10",
+                    "template" => "key_dm",
+                    "language" => "fr",
+                    "duration" => "2h 5m",
+                    "threshold" => "20",
+                    _ => "?",
+                };
+                (p, value.to_owned())
+            })
+            .collect()
+    }
+
+    /// The text of the template, an override for the language is used first, then an override for
+    /// all languages and finally the built in text
+    pub fn text(self, config: &HashMap<String, String>, language: Language) -> &str {
+        config
+            .get(&self.config_key(Scope::Language(language)))
+            .or_else(|| config.get(&self.config_key(Scope::All)))
+            .map(String::as_str)
+            .unwrap_or_else(|| self.default_text(language))
+    }
+
+    /// Like [`Template::text`], but an override for `key_pool` comes before all others
+    pub fn pool_text<'a>(
+        self,
+        config: &'a HashMap<String, String>,
+        language: Language,
+        key_pool: &str,
+    ) -> &'a str {
+        config
+            .get(&self.config_key(Scope::Pool(key_pool)))
+            .map(String::as_str)
+            .unwrap_or_else(|| self.text(config, language))
+    }
+
+    pub fn render(
+        self,
+        config: &HashMap<String, String>,
+        language: Language,
+        values: &[(&str, String)],
+    ) -> String {
+        fill(self.text(config, language), values)
+    }
+
+    /// Renders the template of a giveaway or claim in `key_pool`, see [`Template::pool_text`]
+    pub fn render_in_pool(
+        self,
+        config: &HashMap<String, String>,
+        language: Language,
+        key_pool: &str,
+        values: &[(&str, String)],
+    ) -> String {
+        fill(self.pool_text(config, language, key_pool), values)
+    }
+
+    /// Checks that `text` only uses placeholders this template knows about and returns it in the
+    /// form it should be stored, with `\n` turned into line breaks
+    pub fn validate(self, text: &str) -> Result<String> {
+        let text = text.replace("\\n", "\n");
+
+        for part in parse(&text)? {
+            if let Part::Placeholder(name) = part {
+                if !self.placeholders().contains(&name.as_str()) {
+                    return Err(eyre!(
+                        "unknown placeholder {{{name}}} in template {}, available placeholders are: {}",
+                        self.name(),
+                        self.placeholders()
+                            .iter()
+                            .map(|p| format!("{{{p}}}"))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ));
+                }
+            }
+        }
+
+        Ok(text)
+    }
+}
+
+/// What an override of a template in the config applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope<'a> {
+    /// `template_<name>`, every language and pool
+    All,
+    /// `template_<name>_<language>`
+    Language(Language),
+    /// `template_<name>_<pool>`, giveaways and claims in that pool in every language
+    Pool(&'a str),
+}
+
+enum Part {
+    Text(String),
+    Placeholder(String),
+}
+
+fn fill(text: &str, values: &[(&str, String)]) -> String {
+    // the text is validated before it is stored, so parsing can't fail here
+    let parts = parse(text).unwrap_or_default();
+
+    parts
+        .into_iter()
+        .map(|part| match part {
+            Part::Text(text) => text,
+            Part::Placeholder(name) => values
+                .iter()
+                .find(|(p, _)| *p == name)
+                .map(|(_, value)| value.clone())
+                .unwrap_or_default(),
+        })
+        .collect()
+}
+
+fn parse(text: &str) -> Result<Vec<Part>> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                current.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                current.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) if c.is_ascii_alphanumeric() || c == '_' => name.push(c),
+                        _ => return Err(eyre!("unclosed or invalid placeholder after {{{name}")),
+                    }
+                }
+                parts.push(Part::Text(std::mem::take(&mut current)));
+                parts.push(Part::Placeholder(name));
+            }
+            '}' => return Err(eyre!("unmatched }}, use }}}} for a literal brace")),
+            c => current.push(c),
+        }
+    }
+    parts.push(Part::Text(current));
+
+    Ok(parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn unknown_placeholders_are_rejected() {
+        assert!(Template::KeyDm.validate("your key is {key}").is_ok());
+        assert!(Template::KeyDm.validate("your key is {keys}").is_err());
+        assert!(Template::NoRoleSet.validate("{user}").is_err());
+    }
+
+    #[test]
+    fn unbalanced_braces_are_rejected() {
+        for text in ["{key", "key}", "{key}}", "{{key}", "{ke y}", "{"] {
+            assert!(
+                Template::KeyDm.validate(text).is_err(),
+                "{text} was accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn doubled_braces_are_literal() {
+        let text = Template::KeyDm.validate("{{{key}}} {{key}}\\n").unwrap();
+        let conf = config(&[("template_key_dm", &text)]);
+
+        let text = Template::KeyDm.render(&conf, Language::English, &[("key", "ABC".into())]);
+        assert_eq!(text, "{ABC} {key}\n");
+    }
+
+    #[test]
+    fn overrides_fall_back_to_the_base_template_and_then_the_built_in_text() {
+        let values = [("key", "ABC".to_owned())];
+        let conf = config(&[
+            ("template_key_dm", "base {key}"),
+            ("template_key_dm_de", "de {key}"),
+        ]);
+
+        let render = |conf: &HashMap<String, String>, language| {
+            Template::KeyDm.render(conf, language, &values)
+        };
+
+        assert_eq!(render(&conf, Language::German), "de ABC");
+        assert_eq!(render(&conf, Language::Spanish), "base ABC");
+
+        let conf = config(&[("template_key_dm_de", "de {key}")]);
+        assert_eq!(
+            render(&conf, Language::English),
+            Template::KeyDm
+                .default_text(Language::English)
+                .replace("{key}", "ABC")
+        );
+    }
+
+    #[test]
+    fn pool_overrides_come_before_the_language_ones() {
+        let values = [("key", "ABC".to_owned())];
+        let conf = config(&[
+            ("template_key_dm", "base {key}"),
+            ("template_key_dm_de", "de {key}"),
+            ("template_key_dm_vip", "vip {key}"),
+        ]);
+
+        let render =
+            |language, key_pool| Template::KeyDm.render_in_pool(&conf, language, key_pool, &values);
+
+        assert_eq!(render(Language::German, "vip"), "vip ABC");
+        assert_eq!(render(Language::German, "default"), "de ABC");
+        assert_eq!(render(Language::English, "default"), "base ABC");
+        assert_eq!(
+            Template::KeyDm.render(&conf, Language::English, &values),
+            "base ABC"
+        );
+    }
+
+    #[test]
+    fn longer_template_names_win_over_pools() {
+        assert_eq!(
+            Template::from_config_key("template_key_dm_sent"),
+            Some((Template::KeyDmSent, Scope::All))
+        );
+        assert_eq!(
+            Template::from_config_key("template_key_dm_sent_vip"),
+            Some((Template::KeyDmSent, Scope::Pool("vip")))
+        );
+        assert_eq!(
+            Template::from_config_key("template_key_dm_es"),
+            Some((Template::KeyDm, Scope::Language(Language::Spanish)))
+        );
+        assert_eq!(Template::from_config_key("template_key_dm_"), None);
+        assert_eq!(Template::from_config_key("template_nothing_vip"), None);
+    }
+
+    #[test]
+    fn config_keys_round_trip() {
+        for &template in Template::ALL {
+            assert_eq!(
+                Template::from_config_key(&template.config_key(Scope::All)),
+                Some((template, Scope::All))
+            );
+            for &language in Language::ALL {
+                let scope = Scope::Language(language);
+                assert_eq!(
+                    Template::from_config_key(&template.config_key(scope)),
+                    Some((template, scope))
+                );
+            }
+            for key_pool in ["vip", "closed_beta"] {
+                let scope = Scope::Pool(key_pool);
+                assert_eq!(
+                    Template::from_config_key(&template.config_key(scope)),
+                    Some((template, scope))
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn built_in_texts_only_use_their_placeholders() {
+        for &template in Template::ALL {
+            for &language in Language::ALL {
+                let text = template.default_text(language);
+                assert!(
+                    template.validate(text).is_ok(),
+                    "{} ({}) doesn't validate",
+                    template.name(),
+                    language.code()
+                );
+            }
+        }
+    }
+}